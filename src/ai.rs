@@ -1,10 +1,29 @@
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::path::PathBuf;
 use thiserror::Error;
-use tokio::time::{timeout, Duration};
-
-use crate::config::{Config, ConfigError};
+use chrono::{Local, Utc};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::time::Duration;
+use uuid::Uuid;
+
+use crate::ask::Context;
+use crate::config::{Config, ConfigError, ProviderConfig, ProviderKind};
+use crate::provider::{self, ChatMessage, ChatRequest, Provider};
 use crate::models::Note;
+use crate::privacy::{self, AuditEntry, Masked, Policy, PrivacyError};
+use crate::query::{self, QueryError};
+use crate::enrich::{self, Suggestion};
+use crate::prompts::{Prompt, PromptError};
+use crate::store::StoreError;
+use crate::tags::Vocabulary;
+
+// how many existing tags and projects are offered to the model
+const VOCABULARY_LIMIT: usize = 300;
+// how much of a note is read when suggesting its metadata, in characters
+const ENRICH_CONTENT_LIMIT: usize = 6000;
+// how much of the note on either side of a rewritten section goes along with
+// it, in characters
+const SECTION_CONTEXT_LIMIT: usize = 1500;
 
 #[derive(Error, Debug)]
 pub enum AiError {
@@ -14,136 +33,275 @@ pub enum AiError {
     Http(#[from] reqwest::Error),
     #[error("api error: {status} - {message}")]
     Api { status: u16, message: String },
-    #[error("timeout error: request took longer than 30 seconds")]
-    Timeout,
+    #[error("timeout error: request took longer than {0} seconds")]
+    Timeout(u64),
     #[error("invalid response format")]
     InvalidResponse,
+    #[error("ai returned an invalid query: {0}")]
+    InvalidQuery(QueryError),
+    #[error("store error: {0}")]
+    Store(#[from] StoreError),
+    #[error("{0}")]
+    Prompt(#[from] PromptError),
+    #[error("{0}")]
+    Privacy(#[from] PrivacyError),
+    #[error("the answer changed the placeholder of a masked secret, so the secret can't be put back")]
+    MaskedSecret,
 }
 
-#[derive(Serialize)]
-struct OpenAiRequest {
-    model: String,
-    messages: Vec<OpenAiMessage>,
-    max_tokens: u32,
-    temperature: f32,
-}
-
-#[derive(Serialize)]
-struct OpenAiMessage {
-    role: String,
-    content: String,
-}
-
-#[derive(Deserialize)]
-struct OpenAiResponse {
-    choices: Vec<OpenAiChoice>,
-}
-
-#[derive(Deserialize)]
-struct OpenAiChoice {
-    message: OpenAiResponseMessage,
-}
-
-#[derive(Deserialize)]
-struct OpenAiResponseMessage {
-    content: String,
+// a request that has been checked against the privacy policy and had its
+// secrets masked, along with what the audit log records about it
+pub struct Outgoing {
+    feature: String,
+    notes: Vec<Uuid>,
+    masked: Masked,
+    stream: bool,
+    request: ChatRequest,
 }
 
 pub struct AiClient {
-    client: Client,
+    provider: Box<dyn Provider>,
+    ready: bool,
     config: Config,
+    provider_name: String,
+    url: String,
+    policy: Policy,
+    audit_log: PathBuf,
 }
 
 impl AiClient {
     pub fn new() -> Result<Self, AiError> {
         let config = Config::load()?;
-        let client = Client::new();
+        let name = config.active_provider_name().to_string();
+        let settings = config.provider(&name)?;
+
+        Self::with_provider(config, &name, settings)
+    }
 
-        Ok(Self { client, config })
+    pub fn with_provider(config: Config, name: &str, settings: ProviderConfig) -> Result<Self, AiError> {
+        let ready = settings.is_ready();
+        let url = match settings.kind {
+            ProviderKind::Mock => String::new(),
+            ProviderKind::OpenAi => format!("{}/chat/completions", settings.base_url.trim_end_matches('/')),
+        };
+        let policy = Policy::new(&config.privacy)?;
+
+        Ok(Self {
+            provider: provider::from_config(settings),
+            ready,
+            config,
+            provider_name: name.to_string(),
+            url,
+            policy,
+            audit_log: privacy::audit_log_path()?,
+        })
+    }
+
+    // the provider a prompt asks for, or the active one, with the prompt's
+    // model and temperature in place of the provider's
+    pub fn for_prompt(prompt: &Prompt) -> Result<Self, AiError> {
+        let config = Config::load()?;
+        let name = prompt.provider.clone().unwrap_or_else(|| config.active_provider_name().to_string());
+        let mut settings = config.provider(&name)?;
+        if let Some(model) = &prompt.model {
+            settings.model = model.clone();
+        }
+        if prompt.temperature.is_some() {
+            settings.temperature = prompt.temperature;
+        }
+
+        Self::with_provider(config, &name, settings)
     }
 
     pub fn is_configured(&self) -> bool {
-        self.config.has_api_key()
+        self.ready
+    }
+
+    pub fn model(&self) -> &str {
+        self.provider.model()
     }
 
-    pub async fn rewrite_note(&self, note: &Note) -> Result<String, AiError> {
+    pub fn privacy(&self) -> &Policy {
+        &self.policy
+    }
+
+    fn request(&self, system_prompt: &str, user_prompt: String, max_tokens: u32, temperature: f32, limit: Duration) -> Result<ChatRequest, AiError> {
         if !self.is_configured() {
             return Err(AiError::Config(ConfigError::ApiKeyNotSet));
         }
 
-        let api_key = self.config.get_api_key()?;
-        let system_prompt = self.config.get_ai_system_prompt();
+        Ok(ChatRequest {
+            messages: vec![ChatMessage::system(system_prompt), ChatMessage::user(user_prompt)],
+            max_tokens,
+            temperature,
+            timeout: limit,
+        })
+    }
 
-        let prompt = self.create_rewrite_prompt(note);
+    // refuses notes the policy keeps private and masks secrets in every
+    // message. nothing reaches a provider without going through here
+    fn outgoing(&self, feature: impl Into<String>, notes: &[&Note], mut request: ChatRequest, stream: bool) -> Result<Outgoing, AiError> {
+        for note in notes {
+            self.policy.check(note)?;
+        }
+
+        let mut masked = Masked::default();
+        for message in &mut request.messages {
+            message.content = self.policy.redact(&message.content, &mut masked);
+        }
+
+        Ok(Outgoing {
+            feature: feature.into(),
+            notes: notes.iter().map(|note| note.id).filter(|id| !id.is_nil()).collect(),
+            masked,
+            stream,
+            request,
+        })
+    }
+
+    fn audit(&self, outgoing: &Outgoing) -> Result<(), AiError> {
+        privacy::record(&self.audit_log, &AuditEntry {
+            time: Utc::now(),
+            feature: outgoing.feature.clone(),
+            provider: self.provider_name.clone(),
+            model: self.model().to_string(),
+            url: self.url.clone(),
+            notes: outgoing.notes.clone(),
+            characters: outgoing.request.messages.iter().map(|message| message.content.chars().count()).sum(),
+            redactions: outgoing.masked.count(),
+        })?;
+        Ok(())
+    }
+
+    // the answer comes back with the masked secrets put back in place
+    async fn send(&self, outgoing: &Outgoing) -> Result<String, AiError> {
+        self.audit(outgoing)?;
+        let answer = self.provider.complete(&outgoing.request).await?;
+        Ok(outgoing.masked.restore(&answer))
+    }
 
-        let request = OpenAiRequest {
-            model: "gpt-4o-mini".to_string(),
-            messages: vec![
-                OpenAiMessage {
-                    role: "system".to_string(),
-                    content: system_prompt,
-                },
-                OpenAiMessage {
-                    role: "user".to_string(),
-                    content: prompt,
-                },
-            ],
-            max_tokens: 2000,
-            temperature: 0.3,
+    // the masked secrets are put back in the pieces sent to `tokens` as they
+    // arrive, as well as in the whole answer
+    async fn send_streaming(&self, outgoing: &Outgoing, tokens: &UnboundedSender<String>) -> Result<String, AiError> {
+        self.audit(outgoing)?;
+
+        let (masked_tokens, mut pieces) = unbounded_channel::<String>();
+        let restore = async {
+            let mut restorer = outgoing.masked.streaming();
+            while let Some(piece) = pieces.recv().await {
+                let piece = restorer.push(&piece);
+                if !piece.is_empty() {
+                    let _ = tokens.send(piece);
+                }
+            }
+            let rest = restorer.finish();
+            if !rest.is_empty() {
+                let _ = tokens.send(rest);
+            }
+        };
+        let request = async {
+            let masked_tokens = masked_tokens;
+            self.provider.stream(&outgoing.request, &masked_tokens).await
         };
+        let (answer, _) = tokio::join!(request, restore);
 
-        let response_future = self.client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send();
-
-        let response = timeout(Duration::from_secs(30), response_future)
-            .await
-            .map_err(|_| AiError::Timeout)?
-            .map_err(AiError::Http)?;
-
-        if !response.status().is_success() {
-            let status = response.status().as_u16();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(AiError::Api {
-                status,
-                message: error_text,
-            });
-        }
+        Ok(outgoing.masked.restore(&answer?))
+    }
 
-        let ai_response: OpenAiResponse = response.json().await.map_err(AiError::Http)?;
+    // exactly what would be sent for `outgoing`, without sending it
+    pub fn preview(&self, outgoing: &Outgoing) -> String {
+        let destination = if self.url.is_empty() {
+            format!("{} ({}), answered offline", self.provider_name, self.model())
+        } else {
+            format!("POST {} ({}, {})", self.url, self.provider_name, self.model())
+        };
+        let masked = match outgoing.masked.count() {
+            0 => "nothing masked".to_string(),
+            1 => "1 secret masked".to_string(),
+            count => format!("{} secrets masked", count),
+        };
+        let body = self.provider.payload(&outgoing.request, outgoing.stream);
 
-        ai_response
-            .choices
-            .into_iter()
-            .next()
-            .map(|choice| choice.message.content.trim().to_string())
-            .ok_or(AiError::InvalidResponse)
+        format!("{} • {} • {}\n\n{}", outgoing.feature, destination, masked, body)
     }
 
+    // the built-in rewrite of a whole note
+    fn rewrite_request(&self, note: &Note) -> Result<Outgoing, AiError> {
+        let system_prompt = self.config.get_ai_system_prompt();
+        let prompt = self.create_rewrite_prompt(note);
 
+        let request = self.request(&system_prompt, prompt, 2000, 0.3, Duration::from_secs(30))?;
+        self.outgoing("rewrite", &[note], request, true)
+    }
 
-    pub async fn parse_natural_command(&self, input: &str) -> Result<String, AiError> {
-        if !self.is_configured() {
-            return Err(AiError::Config(ConfigError::ApiKeyNotSet));
+    // rewrites only `region` of the note, with a little of what comes before
+    // and after it for context. the answer is the new text for the region alone
+    fn section_request(&self, note: &Note, region: Range<usize>) -> Result<Outgoing, AiError> {
+        let system_prompt = self.config.get_ai_system_prompt();
+        let prompt = self.section_prompt("Please clean up and improve one section of a note. Keep the same meaning and tone, but make it clearer, fix any grammar issues, and ensure proper markdown formatting.", note, region);
+
+        let request = self.request(&system_prompt, prompt, 2000, 0.3, Duration::from_secs(30))?;
+        self.outgoing("rewrite section", &[note], request, true)
+    }
+
+    // runs a named prompt over the note, or just `region` of it, streaming
+    // the answer into `tokens` as it is written. the timeout is how long to
+    // wait for the next piece, not for the whole note. an answer that still
+    // holds a placeholder is refused, it would write the placeholder into the note
+    pub async fn run_prompt(&self, prompt: &Prompt, note: &Note, region: Option<Range<usize>>, tokens: &UnboundedSender<String>) -> Result<String, AiError> {
+        let original = region.clone().and_then(|region| note.content.get(region)).unwrap_or(&note.content).to_string();
+        let outgoing = self.prompt_request(prompt, note, region)?;
+        let answer = self.send_streaming(&outgoing, tokens).await?;
+
+        if privacy::has_placeholders(&answer, &original) {
+            return Err(AiError::MaskedSecret);
+        }
+        Ok(answer)
+    }
+
+    pub fn prompt_request(&self, prompt: &Prompt, note: &Note, region: Option<Range<usize>>) -> Result<Outgoing, AiError> {
+        if prompt.builtin {
+            return match region {
+                Some(region) => self.section_request(note, region),
+                None => self.rewrite_request(note),
+            };
         }
 
-        let api_key = self.config.get_api_key()?;
+        let system_prompt = format!(
+            "{}\n\nThe note is titled \"{}\". Reply with only the resulting text, without any introduction or commentary.",
+            prompt.instructions,
+            note.title.as_deref().unwrap_or("untitled")
+        );
+        let user_prompt = match region {
+            Some(region) => self.section_prompt("Follow your instructions for one section of a note only.", note, region),
+            None => note.content.clone(),
+        };
+
+        let request = self.request(&system_prompt, user_prompt, 2000, 0.3, Duration::from_secs(30))?;
+        let feature = format!("prompt {}", prompt.name);
+        self.outgoing(feature, &[note], request, true)
+    }
 
+    pub async fn parse_natural_command(&self, input: &str) -> Result<String, AiError> {
         let system_prompt = "You are a command parser for the 'stash' note-taking application. Your job is to convert natural language queries into valid stash search commands.
 
 IMPORTANT: Return ONLY the search arguments, NOT the full command. Do not include 'stash search' in your response. Do not wrap your response in quotes.
 
 Available search patterns:
 - text search: just the search term (e.g., rust, async await)
-- tag search: #tagname (e.g., #rust, #webdev)
-- project search: +projectname (e.g., +myapp, +backend)
-- combined: #tag +project text (e.g., #rust +webapp error handling)
-- exclude: -#tagname or -+projectname (e.g., -#old)
-- list options: --list-tags or --list-projects
-- case sensitive: --case-sensitive followed by search term
+- exact phrase: \"error handling\"
+- tag search: #tagname or tag:tagname (e.g., #rust, #webdev)
+- project search: +projectname or project:projectname (e.g., +myapp, +backend)
+- nested tags and projects: #lang/rust also matches #lang/rust/async, +client/acme also matches +client/acme/billing
+- field search: title:word, content:word, title:\"some phrase\"
+- combined (all must match): #tag +project text (e.g., #rust +webapp error handling)
+- either: OR between terms (e.g., #rust OR #go)
+- exclude: NOT term or -term (e.g., -#old, NOT draft)
+- grouping: parentheses (e.g., (#rust OR #go) +backend)
+- dates: created: or updated: with a date, month or year (created:2025-03-14, created:2025-03, updated:2024)
+- date comparisons: created:>2025-01-01, updated:<=2025-03-31
+- recent or old: an age in h, d or w with < for newer than and > for older than (updated:<7d, created:>2w)
+- named periods: today, yesterday, this-week, last-week, this-month, last-month, this-year, last-year (created:last-week)
 
 Examples:
 - find rust notes → #rust
@@ -151,78 +309,307 @@ Examples:
 - notes about rust in my webapp → #rust +webapp
 - math notes → math
 - find my old javascript code → #javascript
-- list all my tags → --list-tags
+- rust or go notes about the backend → (#rust OR #go) +backend
+- notes titled meeting → title:meeting
 - find notes with javascript but not old stuff → #javascript -#old
+- notes mentioning error handling exactly → \"error handling\"
+- what did i write about databases last week → databases created:last-week
+- rust notes i changed in the last 3 days → #rust updated:<3d
+- meeting notes from march 2025 → title:meeting created:2025-03
 
-Return ONLY the search arguments that would come after 'stash search'. Do not use quotes around your response.";
-
-        let user_prompt = format!("Convert this natural language query to stash search arguments: {}", input);
-
-        let request = OpenAiRequest {
-            model: "gpt-4o-mini".to_string(),
-            messages: vec![
-                OpenAiMessage {
-                    role: "system".to_string(),
-                    content: system_prompt.to_string(),
-                },
-                OpenAiMessage {
-                    role: "user".to_string(),
-                    content: user_prompt,
-                },
-            ],
-            max_tokens: 100,
-            temperature: 0.1,
-        };
-
-        let response_future = self.client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send();
-
-        let response = timeout(Duration::from_secs(10), response_future)
-            .await
-            .map_err(|_| AiError::Timeout)?
-            .map_err(AiError::Http)?;
-
-        if !response.status().is_success() {
-            let status = response.status().as_u16();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(AiError::Api {
-                status,
-                message: error_text,
-            });
-        }
+Return ONLY the search arguments that would come after 'stash search'. Do not wrap your whole response in quotes.";
 
-        let ai_response: OpenAiResponse = response.json().await.map_err(AiError::Http)?;
+        let user_prompt = format!(
+            "Today is {}. Convert this natural language query to stash search arguments: {}",
+            Local::now().format("%A %Y-%m-%d"),
+            input
+        );
 
-        let args = ai_response
-            .choices
-            .into_iter()
-            .next()
-            .map(|choice| choice.message.content.trim().to_string())
-            .ok_or(AiError::InvalidResponse)?;
+        let request = self.request(system_prompt, user_prompt, 100, 0.1, Duration::from_secs(10))?;
+        let outgoing = self.outgoing("search", &[], request, false)?;
+        let args = self.send(&outgoing).await?;
 
         let cleaned_args = args
             .trim_start_matches('`')
             .trim_end_matches('`')
             .trim_start_matches("stash search ")
             .trim_start_matches("search ")
-            .trim_start_matches('"')
-            .trim_end_matches('"')
             .trim_start_matches('\'')
             .trim_end_matches('\'')
             .trim()
             .to_string();
 
+        // a response wrapped in quotes as a whole, rather than a phrase inside a query
+        let cleaned_args = match cleaned_args.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+            Some(inner) if !inner.contains('"') && inner.contains(' ') && query::parse(inner).is_ok() => inner.to_string(),
+            _ => cleaned_args,
+        };
+
+        // only hand back queries the search can actually run
+        query::parse(&cleaned_args).map_err(AiError::InvalidQuery)?;
+
         Ok(cleaned_args)
     }
 
+    // answers from the numbered notes in `context`, citing them as [1], [2]
+    pub async fn answer_question(&self, question: &str, context: &Context) -> Result<String, AiError> {
+        let outgoing = self.question_request(question, context)?;
+        self.send(&outgoing).await
+    }
+
+    pub fn question_request(&self, question: &str, context: &Context) -> Result<Outgoing, AiError> {
+        let system_prompt = "You answer questions using only the user's own notes, which are given to you numbered like [1] and [2]. Base every statement on those notes and cite the notes you used with their numbers in square brackets right after the statement, e.g. \"The deploy runs on Fridays [2].\" If the notes don't contain the answer, say so plainly instead of guessing. Keep the answer short and use markdown only where it helps.";
+
+        let prompt = format!("My notes:\n\n{}\nQuestion: {}", context.text, question);
+        let notes: Vec<&Note> = context.sources.iter().map(|source| &source.note).collect();
+
+        let request = self.request(system_prompt, prompt, 800, 0.2, Duration::from_secs(60))?;
+        self.outgoing("ask", &notes, request, false)
+    }
+
+    // a title, tags and projects for the note, picked from what the vault
+    // already uses
+    pub async fn suggest_metadata(&self, note: &Note, vocabulary: &Vocabulary) -> Result<Vec<Suggestion>, AiError> {
+        let outgoing = self.metadata_request(note, vocabulary)?;
+        let response = self.send(&outgoing).await?;
+        enrich::parse(&response, note, vocabulary)
+    }
+
+    pub fn metadata_request(&self, note: &Note, vocabulary: &Vocabulary) -> Result<Outgoing, AiError> {
+        let list = |names: &[String], sigil: char| {
+            if names.is_empty() {
+                "(none yet)".to_string()
+            } else {
+                names.iter().take(VOCABULARY_LIMIT).map(|name| format!("{}{}", sigil, name)).collect::<Vec<_>>().join(" ")
+            }
+        };
+        // a blocked name can say as much as the notes it keeps back
+        let vocabulary = self.policy.visible(vocabulary);
+
+        let system_prompt = format!(
+            "You organise notes in a note-taking app. Suggest a short title, tags and projects for the note you are given. Only use tags from this list: {}\nOnly use projects from this list: {}\nNever invent new tags or projects; if none fit, leave the list empty. Suggest at most {} tags and {} projects. The title is at most eight words, plain text, in the language of the note. Reply with only a JSON object like {{\"title\": \"...\", \"tags\": [\"...\"], \"projects\": [\"...\"]}} and nothing else.",
+            list(&vocabulary.tags, '#'),
+            list(&vocabulary.projects, '+'),
+            enrich::MAX_TAGS,
+            enrich::MAX_PROJECTS
+        );
+
+        let content: String = note.content.chars().take(ENRICH_CONTENT_LIMIT).collect();
+        let prompt = format!(
+            "Title: {}\nTags: {}\nProjects: {}\n\n{}",
+            note.title.as_deref().unwrap_or("(none)"),
+            list(&note.tags, '#'),
+            list(&note.projects, '+'),
+            content
+        );
+
+        let request = self.request(&system_prompt, prompt, 200, 0.2, Duration::from_secs(30))?;
+        self.outgoing("enrich", &[note], request, false)
+    }
+
+    fn section_prompt(&self, instruction: &str, note: &Note, region: Range<usize>) -> String {
+        let before = &note.content[..region.start];
+        let after = &note.content[region.end..];
+        let skipped = before.chars().count().saturating_sub(SECTION_CONTEXT_LIMIT);
+        let before: String = before.chars().skip(skipped).collect();
+        let after: String = after.chars().take(SECTION_CONTEXT_LIMIT).collect();
+
+        format!(
+            "{} The text around it is only there for context: do not repeat or change it, and reply with the rewritten section alone.\n\n<before>\n{}\n</before>\n\n<section>\n{}\n</section>\n\n<after>\n{}\n</after>",
+            instruction,
+            before,
+            &note.content[region],
+            after
+        )
+    }
+
     fn create_rewrite_prompt(&self, note: &Note) -> String {
         format!(
             "Please clean up and improve the following note content. Keep the same meaning and tone, but make it clearer, fix any grammar issues, and ensure proper markdown formatting:\n\n{}",
             note.content
         )
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use crate::ask;
+    use crate::store::test_note;
+
+    // a mock client that always answers `response`, logging to a temp dir
+    fn client(response: &str) -> (AiClient, TempDir) {
+        mock(Some(response))
+    }
+
+    // without a response the mock echoes what it was sent
+    fn mock(response: Option<&str>) -> (AiClient, TempDir) {
+        let settings = ProviderConfig { response: response.map(str::to_string), ..ProviderConfig::mock() };
+        let mut client = AiClient::with_provider(Config::default(), "mock", settings).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        client.audit_log = dir.path().join("ai-audit.jsonl");
+        (client, dir)
+    }
+
+    fn audited(dir: &TempDir) -> Vec<AuditEntry> {
+        std::fs::read_to_string(dir.path().join("ai-audit.jsonl"))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn natural_commands_are_cleaned_up() {
+        for (response, expected) in [
+            ("#rust +web", "#rust +web"),
+            ("`stash search #rust updated:<3d`", "#rust updated:<3d"),
+            ("search title:meeting", "title:meeting"),
+            ("\"rust async\"", "rust async"),
+            ("\"error handling\" #rust", "\"error handling\" #rust"),
+        ] {
+            let (client, _dir) = client(response);
+            assert_eq!(client.parse_natural_command("anything").await.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn natural_commands_must_parse() {
+        let (client, dir) = client("(#rust OR");
+
+        let result = client.parse_natural_command("rust or").await;
+        assert!(matches!(result, Err(AiError::InvalidQuery(_))));
+
+        // it was still sent, so it's still logged
+        let entries = audited(&dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].feature, "search");
+        assert!(entries[0].notes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn suggestions_stay_within_the_vocabulary() {
+        let vocabulary = Vocabulary {
+            tags: vec!["Rust".to_string(), "lang/go".to_string(), "done".to_string()],
+            projects: vec!["webapp".to_string()],
+        };
+        let (client, dir) = client(r##"sure: {"title": "Borrow checker tips", "tags": ["#rust", "invented", "done", "lang/go", "rust"], "projects": ["+webapp", "other"]}"##);
+        let mut untitled = test_note("", "fighting the borrow checker #done");
+        untitled.title = None;
+        untitled.tags = vec!["done".to_string()];
+
+        let suggestions = client.suggest_metadata(&untitled, &vocabulary).await.unwrap();
+        assert_eq!(suggestions, vec![
+            Suggestion::Title("Borrow checker tips".to_string()),
+            Suggestion::Tag("Rust".to_string()),
+            Suggestion::Tag("lang/go".to_string()),
+            Suggestion::Project("webapp".to_string()),
+        ]);
+        assert_eq!(audited(&dir)[0].notes, vec![untitled.id]);
+
+        // a note that already has a title keeps it
+        let titled = test_note("tips", "fighting the borrow checker");
+        let suggestions = client.suggest_metadata(&titled, &vocabulary).await.unwrap();
+        assert!(!suggestions.iter().any(|suggestion| matches!(suggestion, Suggestion::Title(_))));
+    }
+
+    #[tokio::test]
+    async fn suggestions_need_json() {
+        let (client, _dir) = client("I think #rust fits");
+        let result = client.suggest_metadata(&test_note("empty", "x"), &Vocabulary::default()).await;
+        assert!(matches!(result, Err(AiError::InvalidResponse)));
+    }
+
+    #[tokio::test]
+    async fn answers_cite_the_packed_notes() {
+        let deploys = test_note("deploys", "we deploy on fridays");
+        let oncall = test_note("on call", "whoever deployed is on call");
+        let context = ask::pack(vec![deploys.clone(), oncall.clone()], 1000);
+        assert!(context.text.starts_with("[1] deploys (id "));
+        assert!(context.text.contains("[2] on call (id "));
+
+        let (client, dir) = client("Deploys run on Fridays [1] and the deployer is on call [2][1], see also [1, 7] and [x].");
+        let answer = client.answer_question("when do we deploy?", &context).await.unwrap();
+
+        assert_eq!(ask::citations(&answer, context.sources.len()), vec![1, 2]);
+        assert_eq!(audited(&dir)[0].notes, vec![deploys.id, oncall.id]);
+    }
+
+    #[test]
+    fn packing_stops_at_the_budget() {
+        let long = test_note("long", &"word ".repeat(400));
+        let short = test_note("short", "tiny");
+
+        let context = ask::pack(vec![short.clone(), long.clone(), short], 400);
+        assert_eq!(context.sources.len(), 2);
+        assert!(!context.sources[0].truncated);
+        assert!(context.sources[1].truncated);
+        assert!(context.text.ends_with("…\n\n"));
+
+        let context = ask::pack(vec![long], 100);
+        assert!(context.sources.is_empty());
+    }
+
+    fn echo_prompt() -> Prompt {
+        Prompt {
+            name: "echo".to_string(),
+            description: None,
+            instructions: "repeat the note".to_string(),
+            provider: None,
+            model: None,
+            temperature: None,
+            builtin: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn masked_secrets_are_put_back_into_the_answer() {
+        let (client, dir) = mock(None);
+        let note = test_note("keys", "use sk-abcdefghijklmnop1234 for staging\nmail ops@example.com, again sk-abcdefghijklmnop1234\npassword = hunter2hunter2");
+        let (tokens, mut streamed) = tokio::sync::mpsc::unbounded_channel();
+
+        let outgoing = client.prompt_request(&echo_prompt(), &note, None).unwrap();
+        let sent = &outgoing.request.messages[1].content;
+        assert_eq!(sent, "use [redacted api key 1] for staging\nmail [redacted email 2], again [redacted api key 1]\npassword = [redacted secret 3]");
+
+        let answer = client.run_prompt(&echo_prompt(), &note, None, &tokens).await.unwrap();
+        assert_eq!(answer, note.content);
+        assert_eq!(audited(&dir)[0].redactions, 4);
+
+        // what streams in is restored as well, so nothing prints a placeholder
+        let mut pieces = String::new();
+        while let Ok(piece) = streamed.try_recv() {
+            pieces.push_str(&piece);
+        }
+        assert_eq!(pieces, note.content);
+    }
+
+    #[tokio::test]
+    async fn answers_with_changed_placeholders_are_refused() {
+        let (changed, _dir) = client("Use [redacted api key] for staging.");
+        let keys = test_note("keys", "use sk-abcdefghijklmnop1234 for staging");
+        let (tokens, _streamed) = tokio::sync::mpsc::unbounded_channel();
+
+        let result = changed.run_prompt(&echo_prompt(), &keys, None, &tokens).await;
+        assert!(matches!(result, Err(AiError::MaskedSecret)));
+
+        // a note that talks about placeholders itself can still be rewritten
+        let (docs_client, _docs_dir) = client("Secrets show up as [redacted email 1].");
+        let docs = test_note("docs", "secrets show up as [redacted email 1]");
+        assert!(docs_client.run_prompt(&echo_prompt(), &docs, None, &tokens).await.is_ok());
+    }
+
+    #[test]
+    fn explicitly_private_notes_are_never_sent() {
+        let mut config = Config::default();
+        config.privacy.blocked_tags = vec!["private".to_string()];
+        let client = AiClient::with_provider(config, "mock", ProviderConfig::mock()).unwrap();
+
+        let mut draft = test_note("draft", "nothing inline gives it away");
+        draft.explicit_tags = vec!["private".to_string()];
+
+        let result = client.prompt_request(&echo_prompt(), &draft, None);
+        assert!(matches!(result, Err(AiError::Privacy(PrivacyError::Blocked { .. }))));
+    }
+}
@@ -0,0 +1,206 @@
+use console::Style;
+
+use crate::ai::{AiClient, AiError};
+use crate::links;
+use crate::models::Note;
+use crate::natural;
+use crate::ranking::SortOrder;
+use crate::store::{self, SearchOptions, StoreError};
+
+// how much note content goes along with a question, in rough tokens
+pub const DEFAULT_BUDGET: usize = 3000;
+// how many of the best matching notes are considered
+pub const DEFAULT_SOURCES: usize = 8;
+
+// a note below this many tokens of room isn't worth cutting down to fit
+const MIN_EXCERPT_TOKENS: usize = 150;
+
+// a note handed to the model, numbered from 1 in the order it was sent
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub number: usize,
+    pub note: Note,
+    pub truncated: bool,
+}
+
+// the notes a question was answered from, and the prompt text built from them
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub sources: Vec<Source>,
+    pub text: String,
+    pub tokens: usize,
+}
+
+// the best matching live notes for a question. any of its words may match,
+// tags, projects and dates in it still narrow things down
+pub fn retrieve(question: &str, limit: usize) -> Result<Vec<Note>, StoreError> {
+    let vocabulary = store::vocabulary()?;
+    let query = natural::translate_loose(question, &vocabulary).unwrap_or_default();
+
+    let options = SearchOptions {
+        query,
+        filter_tags: None,
+        filter_projects: None,
+        list_tags: false,
+        list_projects: false,
+        case_sensitive: false,
+        limit: Some(limit),
+        since: None,
+        until: None,
+        sort: SortOrder::Score,
+        fuzzy: false,
+        recency: true,
+    };
+
+    Ok(store::search_notes_return_results(options)?
+        .into_iter()
+        .map(|result| result.note)
+        .collect())
+}
+
+// about four characters to a token, close enough for english prose
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+// numbers the notes and packs them best first until the budget runs out. the
+// note that crosses the budget is cut short if enough room is left for it
+pub fn pack(notes: Vec<Note>, budget: usize) -> Context {
+    let mut context = Context::default();
+
+    for note in notes {
+        let number = context.sources.len() + 1;
+        let header = format!(
+            "[{}] {} (id {})\n",
+            number,
+            note.title.as_deref().unwrap_or("untitled"),
+            links::short_id(note.id)
+        );
+        let body = note.content.trim();
+        let room = budget.saturating_sub(context.tokens + estimate_tokens(&header));
+
+        let (body, truncated) = if estimate_tokens(body) <= room {
+            (body.to_string(), false)
+        } else if room >= MIN_EXCERPT_TOKENS {
+            let cut: String = body.chars().take(room * 4).collect();
+            (format!("{}…", cut), true)
+        } else {
+            break;
+        };
+
+        let entry = format!("{}{}\n\n", header, body);
+        context.tokens += estimate_tokens(&entry);
+        context.text.push_str(&entry);
+        context.sources.push(Source { number, note, truncated });
+
+        if truncated {
+            break;
+        }
+    }
+
+    context
+}
+
+// the source numbers an answer cites, in order of first mention. handles
+// [1], [1][3] and [1, 3], and ignores numbers that weren't sent
+pub fn citations(answer: &str, count: usize) -> Vec<usize> {
+    let mut cited = Vec::new();
+    let mut rest = answer;
+
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+
+        let inside = &rest[..end];
+        let numbers: Option<Vec<usize>> = inside.split(',').map(|part| part.trim().parse().ok()).collect();
+        for number in numbers.unwrap_or_default() {
+            if (1..=count).contains(&number) && !cited.contains(&number) {
+                cited.push(number);
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+
+    cited
+}
+
+// `preview` prints the request instead of sending it
+pub async fn run(question: &str, budget: usize, limit: usize, preview: bool) -> Result<(), AiError> {
+    let heading_style = Style::new().bold().cyan();
+    let source_style = Style::new().bold();
+    let dim_style = Style::new().dim();
+    let cited_style = Style::new().bold().green();
+
+    let notes = retrieve(question, limit)?;
+    if notes.is_empty() {
+        println!("no notes look related to that question");
+        return Ok(());
+    }
+
+    let ai_client = AiClient::new()?;
+
+    if !ai_client.is_configured() {
+        eprintln!("answering needs an ai provider, add an openai key in the tui settings or run 'stash provider add'");
+        println!("\n{} these notes look most related:", heading_style.apply_to("📚"));
+        for source in &pack(notes, budget).sources {
+            println!("  {} {}", source_style.apply_to(source.note.title.as_deref().unwrap_or("untitled")), dim_style.apply_to(links::short_id(source.note.id)));
+        }
+        return Ok(());
+    }
+
+    // private notes are left out rather than refusing the whole question
+    let (notes, held_back) = ai_client.privacy().allowed(notes);
+    if held_back > 0 {
+        eprintln!("{}", dim_style.apply_to(format!("left out {} private note{}", held_back, if held_back == 1 { "" } else { "s" })));
+    }
+    if notes.is_empty() {
+        println!("only private notes look related to that question, nothing was sent");
+        return Ok(());
+    }
+
+    let context = pack(notes, budget);
+
+    if preview {
+        println!("{}", ai_client.preview(&ai_client.question_request(question, &context)?));
+        return Ok(());
+    }
+
+    println!(
+        "{} reading {} note{} (~{} tokens) with {}...",
+        heading_style.apply_to("🤖"),
+        context.sources.len(),
+        if context.sources.len() == 1 { "" } else { "s" },
+        context.tokens,
+        ai_client.model()
+    );
+
+    let answer = ai_client.answer_question(question, &context).await?;
+    let cited = citations(&answer, context.sources.len());
+
+    println!("\n{}\n", answer);
+    println!("{}", heading_style.apply_to("sources"));
+
+    for source in &context.sources {
+        let marker = if cited.contains(&source.number) {
+            cited_style.apply_to("✓").to_string()
+        } else {
+            " ".to_string()
+        };
+        println!(
+            "{} [{}] {} {}{}",
+            marker,
+            source.number,
+            source_style.apply_to(source.note.title.as_deref().unwrap_or("untitled")),
+            dim_style.apply_to(links::short_id(source.note.id)),
+            if source.truncated { dim_style.apply_to(" (shortened)").to_string() } else { String::new() }
+        );
+    }
+
+    if cited.is_empty() {
+        println!("\n{}", dim_style.apply_to("the answer didn't cite any note, check it against the sources"));
+    }
+
+    Ok(())
+}
@@ -1,10 +1,15 @@
+use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 
+use crate::ranking::SortOrder;
+
 #[derive(Parser)]
 #[command(name = "stash")]
 #[command(about = "a command-line tool for managing your stash")]
 #[command(version)]
 pub struct Cli {
+    #[arg(long, global = true, help = "named vault to use instead of the active one")]
+    pub vault: Option<String>,
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -16,6 +21,8 @@ pub enum Commands {
         title: Option<String>,
         #[arg(help = "the content of the note")]
         content: String,
+        #[arg(long, help = "let ai add a title, tags and projects you already use")]
+        enrich: bool,
     },
     New,
     Search {
@@ -31,9 +38,279 @@ pub enum Commands {
         list_projects: bool,
         #[arg(long, help = "case-sensitive search")]
         case_sensitive: bool,
+        #[arg(long, help = "only show the first n results")]
+        limit: Option<usize>,
+        #[arg(long, help = "only notes created since a date or age (e.g. 2025-03-01, 7d, yesterday)")]
+        since: Option<String>,
+        #[arg(long, help = "only notes created up to a date or age (e.g. 2025-03-31, 30d, last-week)")]
+        until: Option<String>,
+        #[arg(long, value_enum, default_value_t = SortOrder::Score, help = "order results by relevance score, newest created, newest updated or title")]
+        sort: SortOrder,
+        #[arg(long, help = "match words fuzzily, forgiving typos, and rank by the best line")]
+        fuzzy: bool,
+        #[arg(long, help = "don't favour recently updated notes when ranking")]
+        no_recency: bool,
+        #[arg(long, group = "output", help = "print results as a json array")]
+        json: bool,
+        #[arg(long, group = "output", help = "print one json object per line")]
+        ndjson: bool,
+        #[arg(long, group = "output", help = "print one line per result from a template, e.g. \"{path}:{title}\"")]
+        format: Option<String>,
+        #[arg(long, group = "output", help = "print only the file path of each result")]
+        paths_only: bool,
     },
     Ai {
         #[arg(help = "natural language query to search for notes")]
         query: String,
+        #[arg(long, help = "translate the query offline instead of asking the ai provider")]
+        local: bool,
+    },
+    #[command(about = "let ai add titles, tags and projects to the notes matching a query")]
+    Enrich {
+        #[arg(help = "search query picking the notes, e.g. \"created:this-week\"")]
+        query: String,
+        #[arg(long, help = "only enrich the first n matches")]
+        limit: Option<usize>,
+        #[arg(long, help = "show the suggestions without saving them")]
+        dry_run: bool,
+        #[arg(long, conflicts_with = "dry_run", help = "print exactly what would be sent for each note, without sending it")]
+        preview: bool,
+    },
+    #[command(about = "answer a question from your notes, citing the ones it used")]
+    Ask {
+        #[arg(help = "the question to answer")]
+        question: String,
+        #[arg(long, default_value_t = crate::ask::DEFAULT_BUDGET, help = "roughly how many tokens of note content to send along")]
+        budget: usize,
+        #[arg(long, default_value_t = crate::ask::DEFAULT_SOURCES, help = "how many of the best matching notes to consider")]
+        sources: usize,
+        #[arg(long, help = "print exactly what would be sent, without sending it")]
+        preview: bool,
+    },
+    #[command(about = "run a named prompt from ~/.stash/prompts/ over a note")]
+    AiRun {
+        #[arg(help = "prompt name, e.g. \"meeting-minutes\" or \"rewrite\"")]
+        prompt: String,
+        #[arg(help = "note title, id or id prefix")]
+        note: String,
+        #[arg(long, help = "save the result over the note instead of only printing it")]
+        apply: bool,
+        #[arg(long, conflicts_with = "apply", help = "print exactly what would be sent, without sending it")]
+        preview: bool,
+    },
+    #[command(about = "list the prompts ai-run and the tui can use")]
+    Prompts,
+    #[command(about = "keep notes away from ai providers and mask secrets in what is sent")]
+    Privacy {
+        #[command(subcommand)]
+        action: PrivacyCommands,
+    },
+    Vault {
+        #[command(subcommand)]
+        action: VaultCommands,
+    },
+    #[command(about = "list, add or switch the ai provider")]
+    Provider {
+        #[command(subcommand)]
+        action: ProviderCommands,
+    },
+    #[command(about = "rebuild the search index from scratch")]
+    Reindex,
+    #[command(about = "show the notes a note links to")]
+    Links {
+        #[arg(help = "note title, id or id prefix")]
+        note: String,
+    },
+    #[command(about = "show the notes that link to a note")]
+    Backlinks {
+        #[arg(help = "note title, id or id prefix")]
+        note: String,
+    },
+    #[command(about = "list the saved revisions of a note")]
+    History {
+        #[arg(help = "note title, id or id prefix")]
+        note: String,
+    },
+    #[command(about = "show what changed since a revision")]
+    Diff {
+        #[arg(help = "note title, id or id prefix")]
+        note: String,
+        #[arg(help = "revision to compare against (defaults to the latest)")]
+        rev: Option<u32>,
+    },
+    #[command(about = "restore a note to an earlier revision")]
+    Revert {
+        #[arg(help = "note title, id or id prefix")]
+        note: String,
+        #[arg(help = "revision to restore")]
+        rev: u32,
+    },
+    #[command(about = "turn automatic git commits on or off")]
+    Git {
+        #[command(subcommand)]
+        action: GitCommands,
+    },
+    #[command(about = "pull, rebase and push the stash against its git remote")]
+    Sync,
+    #[command(about = "list, restore or empty the trash")]
+    Trash {
+        #[command(subcommand)]
+        action: TrashCommands,
+    },
+    #[command(about = "rename, merge or delete tags across every note")]
+    Tags {
+        #[command(subcommand)]
+        action: RetagCommands,
+    },
+    #[command(about = "rename, merge or delete projects across every note")]
+    Projects {
+        #[command(subcommand)]
+        action: RetagCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RetagCommands {
+    #[command(about = "rename one, along with everything nested under it")]
+    Rename {
+        #[arg(help = "current name")]
+        old: String,
+        #[arg(help = "new name")]
+        new: String,
+        #[arg(long, help = "show the changes without saving them")]
+        dry_run: bool,
+    },
+    #[command(about = "fold several into one")]
+    Merge {
+        #[arg(required = true, help = "names to merge")]
+        sources: Vec<String>,
+        #[arg(long, help = "name to merge them into")]
+        into: String,
+        #[arg(long, help = "show the changes without saving them")]
+        dry_run: bool,
+    },
+    #[command(about = "remove one from every note, inline mentions stay as plain words")]
+    Delete {
+        #[arg(help = "name to delete")]
+        name: String,
+        #[arg(long, help = "show the changes without saving them")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum GitCommands {
+    #[command(about = "commit the stash to git after every change")]
+    Enable {
+        #[arg(long, help = "remote url for stash sync")]
+        remote: Option<String>,
+        #[arg(long, help = "branch to commit to (defaults to main)")]
+        branch: Option<String>,
+    },
+    #[command(about = "stop committing changes")]
+    Disable,
+}
+
+#[derive(Subcommand)]
+pub enum TrashCommands {
+    #[command(about = "list soft-deleted notes")]
+    List,
+    #[command(about = "move a note out of the trash")]
+    Restore {
+        #[arg(help = "note title, id or id prefix")]
+        id: String,
+    },
+    #[command(about = "permanently delete notes in the trash")]
+    Empty {
+        #[arg(long, help = "only delete notes trashed longer ago than this (e.g. 30d, 2w, 12h)")]
+        older_than: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProviderCommands {
+    #[command(about = "list the built-in and configured providers")]
+    List,
+    #[command(about = "add or replace a provider speaking the openai chat completions api")]
+    Add {
+        #[arg(help = "name of the provider")]
+        name: String,
+        #[arg(long, help = "api base url, e.g. http://localhost:11434/v1 for ollama")]
+        base_url: Option<String>,
+        #[arg(long, help = "model to ask for (defaults to gpt-4o-mini)")]
+        model: Option<String>,
+        #[arg(long, help = "api key, sent as a bearer token")]
+        api_key: Option<String>,
+        #[arg(long = "header", value_name = "NAME=VALUE", help = "extra header to send with every request, can be repeated")]
+        headers: Vec<String>,
+        #[arg(long, help = "sampling temperature for every request, instead of each feature's own")]
+        temperature: Option<f32>,
+        #[arg(long, value_name = "SECONDS", help = "request timeout, instead of each feature's own")]
+        timeout: Option<u64>,
+        #[arg(long, help = "answer offline with a canned response instead of calling an api")]
+        mock: bool,
+        #[arg(long, requires = "mock", help = "what the mock provider answers (it echoes the request otherwise)")]
+        response: Option<String>,
+    },
+    #[command(about = "make a provider the active one")]
+    Use {
+        #[arg(help = "name of the provider")]
+        name: String,
+    },
+    #[command(about = "forget a configured provider")]
+    Remove {
+        #[arg(help = "name of the provider")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PrivacyCommands {
+    #[command(about = "show what is blocked and masked")]
+    Show,
+    #[command(about = "never send notes with these tags or projects, e.g. #private +client-x")]
+    Block {
+        #[arg(required = true, help = "#tags and +projects, anything nested under them is blocked too")]
+        markers: Vec<String>,
+    },
+    #[command(about = "allow notes with these tags or projects to be sent again")]
+    Unblock {
+        #[arg(required = true, help = "#tags and +projects")]
+        markers: Vec<String>,
+    },
+    #[command(about = "mask text matching a regular expression before it is sent")]
+    Redact {
+        #[arg(help = "regular expression, e.g. \"ACME-\\d{6}\"")]
+        pattern: String,
+        #[arg(long, help = "stop masking this pattern")]
+        remove: bool,
+    },
+    #[command(about = "show the tui's rewrite payload before it is sent")]
+    Preview {
+        #[arg(long, help = "send rewrites without showing them first")]
+        off: bool,
+    },
+    #[command(about = "list the requests sent to ai providers")]
+    Log {
+        #[arg(long, default_value_t = 20, help = "how many of the latest requests to show")]
+        limit: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum VaultCommands {
+    #[command(about = "list all registered vaults")]
+    List,
+    #[command(about = "register a new vault")]
+    Add {
+        #[arg(help = "name of the vault")]
+        name: String,
+        #[arg(help = "directory the vault lives in")]
+        path: PathBuf,
+    },
+    #[command(about = "make a vault the active one")]
+    Use {
+        #[arg(help = "name of the vault")]
+        name: String,
     },
 }
\ No newline at end of file
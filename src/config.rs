@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
@@ -13,14 +14,187 @@ pub enum ConfigError {
     HomeNotFound,
     #[error("api key not configured")]
     ApiKeyNotSet,
+    #[error("unknown vault: {0}")]
+    UnknownVault(String),
+    #[error("vault already exists: {0}")]
+    VaultExists(String),
+    #[error("unknown ai provider: {0}")]
+    UnknownProvider(String),
+    #[error("invalid header '{0}', expected NAME=VALUE")]
+    InvalidHeader(String),
 }
 
+// the provider used when none has been picked, talking to openai with the
+// key from the settings screen
+pub const DEFAULT_PROVIDER: &str = "openai";
+// answers without any network, for trying the ai features out offline
+pub const MOCK_PROVIDER: &str = "mock";
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const OPENAI_MODEL: &str = "gpt-4o-mini";
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub openai_api_key: Option<String>,
     pub ai_enabled: bool,
     pub ai_prompt_style: String,
     pub custom_ai_prompt: Option<String>,
+    #[serde(default)]
+    pub vaults: BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    pub active_vault: Option<String>,
+    // keyed by the stash directory git was enabled in, so every vault keeps its own remote
+    #[serde(default)]
+    pub git: BTreeMap<PathBuf, GitConfig>,
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderConfig>,
+    #[serde(default)]
+    pub active_provider: Option<String>,
+    #[serde(default)]
+    pub privacy: PrivacyConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    // anything speaking the openai chat completions api: openai itself,
+    // ollama, llama.cpp, vllm or a gateway in front of them
+    #[default]
+    OpenAi,
+    Mock,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderConfig {
+    #[serde(default)]
+    pub kind: ProviderKind,
+    #[serde(default = "ProviderConfig::default_base_url")]
+    pub base_url: String,
+    #[serde(default = "ProviderConfig::default_model")]
+    pub model: String,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    // sent with every request, e.g. a gateway's auth or routing headers
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    // overrides the temperature each ai feature would otherwise pick
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    // overrides each ai feature's own timeout, local models can be slow
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    // what the mock provider answers with, it echoes the request otherwise
+    #[serde(default)]
+    pub response: Option<String>,
+}
+
+impl ProviderConfig {
+    fn default_base_url() -> String {
+        OPENAI_BASE_URL.to_string()
+    }
+
+    fn default_model() -> String {
+        OPENAI_MODEL.to_string()
+    }
+
+    pub fn mock() -> Self {
+        Self {
+            kind: ProviderKind::Mock,
+            base_url: String::new(),
+            model: MOCK_PROVIDER.to_string(),
+            ..Self::default()
+        }
+    }
+
+    // openai itself needs a key, local servers and gateways usually don't
+    pub fn is_ready(&self) -> bool {
+        match self.kind {
+            ProviderKind::Mock => true,
+            ProviderKind::OpenAi => {
+                self.api_key.as_deref().is_some_and(|key| !key.is_empty())
+                    || self.base_url.trim_end_matches('/') != OPENAI_BASE_URL
+            }
+        }
+    }
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self {
+            kind: ProviderKind::OpenAi,
+            base_url: Self::default_base_url(),
+            model: Self::default_model(),
+            api_key: None,
+            headers: BTreeMap::new(),
+            temperature: None,
+            timeout_secs: None,
+            response: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GitConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub remote: Option<String>,
+    #[serde(default = "GitConfig::default_branch")]
+    pub branch: String,
+}
+
+impl GitConfig {
+    fn default_branch() -> String {
+        "main".to_string()
+    }
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            remote: None,
+            branch: Self::default_branch(),
+        }
+    }
+}
+
+// what may be sent to an ai provider. notes with a blocked tag or project
+// never leave the machine, and secrets are masked in everything that does
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrivacyConfig {
+    // names without the sigil, anything nested under them is blocked too
+    #[serde(default)]
+    pub blocked_tags: Vec<String>,
+    #[serde(default)]
+    pub blocked_projects: Vec<String>,
+    // regular expressions masked on top of the built-in secret patterns
+    #[serde(default)]
+    pub redact: Vec<String>,
+    // emails, api keys, tokens and private keys
+    #[serde(default = "PrivacyConfig::default_redact_secrets")]
+    pub redact_secrets: bool,
+    // show the tui the exact payload before every rewrite is sent
+    #[serde(default)]
+    pub preview: bool,
+}
+
+impl PrivacyConfig {
+    fn default_redact_secrets() -> bool {
+        true
+    }
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            blocked_tags: Vec::new(),
+            blocked_projects: Vec::new(),
+            redact: Vec::new(),
+            redact_secrets: Self::default_redact_secrets(),
+            preview: false,
+        }
+    }
 }
 
 impl Default for Config {
@@ -30,6 +204,12 @@ impl Default for Config {
             ai_enabled: false,
             ai_prompt_style: "professional".to_string(),
             custom_ai_prompt: None,
+            vaults: BTreeMap::new(),
+            active_vault: None,
+            git: BTreeMap::new(),
+            providers: BTreeMap::new(),
+            active_provider: None,
+            privacy: PrivacyConfig::default(),
         }
     }
 }
@@ -65,10 +245,6 @@ impl Config {
         self.save()
     }
 
-    pub fn get_api_key(&self) -> Result<&str, ConfigError> {
-        self.openai_api_key.as_deref().ok_or(ConfigError::ApiKeyNotSet)
-    }
-
     pub fn has_api_key(&self) -> bool {
         self.openai_api_key.is_some() && !self.openai_api_key.as_ref().unwrap().is_empty()
     }
@@ -108,8 +284,140 @@ impl Config {
         format!("{}{}", base_instruction, style_instruction)
     }
 
-    fn config_file_path() -> Result<PathBuf, ConfigError> {
+    pub fn active_provider_name(&self) -> &str {
+        self.active_provider.as_deref().unwrap_or(DEFAULT_PROVIDER)
+    }
+
+    // a configured provider, or one of the built-in ones. the built-in openai
+    // provider picks up the api key from the settings screen
+    pub fn provider(&self, name: &str) -> Result<ProviderConfig, ConfigError> {
+        if let Some(provider) = self.providers.get(name) {
+            let mut provider = provider.clone();
+            if name == DEFAULT_PROVIDER && provider.api_key.is_none() {
+                provider.api_key = self.openai_api_key.clone();
+            }
+            return Ok(provider);
+        }
+
+        match name {
+            DEFAULT_PROVIDER => Ok(ProviderConfig {
+                api_key: self.openai_api_key.clone(),
+                ..ProviderConfig::default()
+            }),
+            MOCK_PROVIDER => Ok(ProviderConfig::mock()),
+            _ => Err(ConfigError::UnknownProvider(name.to_string())),
+        }
+    }
+
+    pub fn add_provider(&mut self, name: String, provider: ProviderConfig) -> Result<(), ConfigError> {
+        self.providers.insert(name, provider);
+        self.save()
+    }
+
+    pub fn use_provider(&mut self, name: &str) -> Result<(), ConfigError> {
+        self.provider(name)?;
+        self.active_provider = if name == DEFAULT_PROVIDER { None } else { Some(name.to_string()) };
+        self.save()
+    }
+
+    pub fn remove_provider(&mut self, name: &str) -> Result<(), ConfigError> {
+        if self.providers.remove(name).is_none() {
+            return Err(ConfigError::UnknownProvider(name.to_string()));
+        }
+        if self.active_provider.as_deref() == Some(name) {
+            self.active_provider = None;
+        }
+        self.save()
+    }
+
+    pub fn add_vault(&mut self, name: String, path: PathBuf) -> Result<(), ConfigError> {
+        if name == crate::vault::DEFAULT_VAULT || self.vaults.contains_key(&name) {
+            return Err(ConfigError::VaultExists(name));
+        }
+
+        self.vaults.insert(name, path);
+        self.save()
+    }
+
+    pub fn use_vault(&mut self, name: &str) -> Result<(), ConfigError> {
+        if name == crate::vault::DEFAULT_VAULT {
+            self.active_vault = None;
+        } else if self.vaults.contains_key(name) {
+            self.active_vault = Some(name.to_string());
+        } else {
+            return Err(ConfigError::UnknownVault(name.to_string()));
+        }
+
+        self.save()
+    }
+
+    pub fn vault_path(&self, name: &str) -> Result<PathBuf, ConfigError> {
+        if name == crate::vault::DEFAULT_VAULT {
+            return Self::default_stash_dir();
+        }
+
+        self.vaults
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ConfigError::UnknownVault(name.to_string()))
+    }
+
+    pub fn resolve_stash_dir(&self, vault: Option<&str>, env_dir: Option<PathBuf>) -> Result<PathBuf, ConfigError> {
+        if let Some(name) = vault {
+            return self.vault_path(name);
+        }
+
+        if let Some(dir) = env_dir {
+            return Ok(dir);
+        }
+
+        match self.active_vault {
+            Some(ref name) => self.vault_path(name),
+            None => Self::default_stash_dir(),
+        }
+    }
+
+    pub fn default_stash_dir() -> Result<PathBuf, ConfigError> {
         let home = dirs::home_dir().ok_or(ConfigError::HomeNotFound)?;
-        Ok(home.join(".stash").join("config.json"))
+        Ok(home.join(".stash"))
+    }
+
+    fn config_file_path() -> Result<PathBuf, ConfigError> {
+        Ok(Self::default_stash_dir()?.join("config.json"))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(active: Option<&str>) -> Config {
+        let mut config = Config::default();
+        config.vaults.insert("work".to_string(), PathBuf::from("/vaults/work"));
+        config.vaults.insert("personal".to_string(), PathBuf::from("/vaults/personal"));
+        config.active_vault = active.map(str::to_string);
+        config
+    }
+
+    #[test]
+    fn the_vault_flag_beats_the_env_which_beats_the_active_vault() {
+        let home = Config::default_stash_dir().unwrap();
+        let env = || Some(PathBuf::from("/from/env"));
+
+        assert_eq!(config(None).resolve_stash_dir(None, None).unwrap(), home);
+        assert_eq!(config(Some("personal")).resolve_stash_dir(None, None).unwrap(), PathBuf::from("/vaults/personal"));
+        assert_eq!(config(Some("personal")).resolve_stash_dir(None, env()).unwrap(), PathBuf::from("/from/env"));
+        assert_eq!(config(Some("personal")).resolve_stash_dir(Some("work"), env()).unwrap(), PathBuf::from("/vaults/work"));
+        assert_eq!(config(Some("personal")).resolve_stash_dir(Some("default"), env()).unwrap(), home);
+    }
+
+    #[test]
+    fn unknown_vaults_are_errors_not_fallbacks() {
+        let result = config(None).resolve_stash_dir(Some("nope"), Some(PathBuf::from("/from/env")));
+        assert!(matches!(result, Err(ConfigError::UnknownVault(name)) if name == "nope"));
+
+        // an active vault that was removed from the config
+        assert!(matches!(config(Some("gone")).resolve_stash_dir(None, None), Err(ConfigError::UnknownVault(_))));
+        assert_eq!(config(Some("gone")).resolve_stash_dir(None, Some(PathBuf::from("/from/env"))).unwrap(), PathBuf::from("/from/env"));
+    }
+}
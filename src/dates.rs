@@ -0,0 +1,193 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Utc};
+
+// a half-open [start, end) span of time, either side may be open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl DateRange {
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| at >= start) && self.end.is_none_or(|end| at < end)
+    }
+}
+
+// a named stretch of time like "2025-03" or "yesterday", or an age like "7d"
+#[derive(Debug, Clone, Copy)]
+enum Period {
+    Span(DateTime<Utc>, DateTime<Utc>),
+    Age(Duration),
+}
+
+// parses the value of a created:/updated: filter. plain values match the
+// whole period, comparisons work on time for dates and on age for durations:
+//
+//   2025-03          any time in march 2025
+//   >2025-01-01      after new year's day
+//   <=2025-01-01     up to and including new year's day
+//   <7d              less than 7 days old
+//   >2w              more than 2 weeks old
+//   today, yesterday, this-week, last-week, this-month, last-month, this-year, last-year
+pub fn parse_range(expr: &str, now: DateTime<Local>) -> Option<DateRange> {
+    let (comparison, value) = ["<=", ">=", "<", ">"]
+        .iter()
+        .find_map(|op| expr.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("", expr));
+
+    let range = match (parse_period(value, now)?, comparison) {
+        (Period::Span(start, end), "") => DateRange { start: Some(start), end: Some(end) },
+        (Period::Span(_, end), ">") => DateRange { start: Some(end), end: None },
+        (Period::Span(start, _), ">=") => DateRange { start: Some(start), end: None },
+        (Period::Span(start, _), "<") => DateRange { start: None, end: Some(start) },
+        (Period::Span(_, end), "<=") => DateRange { start: None, end: Some(end) },
+        (Period::Age(age), ">" | ">=") => DateRange { start: None, end: Some(ago(age, now)?) },
+        (Period::Age(age), _) => DateRange { start: Some(ago(age, now)?), end: None },
+        _ => return None,
+    };
+
+    Some(range)
+}
+
+// --since as a created:/updated: value
+pub fn since_expr(value: &str) -> String {
+    if parse_duration(value).is_some() {
+        format!("<{}", value)
+    } else {
+        format!(">={}", value)
+    }
+}
+
+// --until as a created:/updated: value, inclusive of the named period
+pub fn until_expr(value: &str) -> String {
+    if parse_duration(value).is_some() {
+        format!(">{}", value)
+    } else {
+        format!("<={}", value)
+    }
+}
+
+// ages like "30d", "2w" or "12h". None for negative ages and ones too
+// long to represent
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let unit = value.chars().last()?;
+    let amount = value[..value.len() - unit.len_utf8()].parse::<u32>().ok()? as i64;
+
+    match unit {
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+// the moment `age` before `now`, None if that's before the earliest date chrono knows
+pub fn ago(age: Duration, now: DateTime<impl TimeZone>) -> Option<DateTime<Utc>> {
+    now.with_timezone(&Utc).checked_sub_signed(age)
+}
+
+fn parse_period(value: &str, now: DateTime<Local>) -> Option<Period> {
+    if let Some(age) = parse_duration(value) {
+        return Some(Period::Age(age));
+    }
+
+    let today = now.date_naive();
+    let this_week = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let this_month = NaiveDate::from_ymd_opt(today.year(), today.month(), 1)?;
+    let this_year = NaiveDate::from_ymd_opt(today.year(), 1, 1)?;
+
+    let (start, end) = match value {
+        "today" => (today, today + Duration::days(1)),
+        "yesterday" => (today - Duration::days(1), today),
+        "this-week" => (this_week, this_week + Duration::weeks(1)),
+        "last-week" => (this_week - Duration::weeks(1), this_week),
+        "this-month" => (this_month, next_month(this_month)?),
+        "last-month" => (previous_month(this_month)?, this_month),
+        "this-year" => (this_year, NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)?),
+        "last-year" => (NaiveDate::from_ymd_opt(today.year() - 1, 1, 1)?, this_year),
+        _ => parse_calendar_period(value)?,
+    };
+
+    Some(Period::Span(local_midnight(start)?, local_midnight(end)?))
+}
+
+// 2025, 2025-03 or 2025-03-14
+fn parse_calendar_period(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    let number = |i: usize| parts.get(i).and_then(|part| part.parse::<u32>().ok());
+
+    match parts.len() {
+        1 if parts[0].len() == 4 => {
+            let year = number(0)? as i32;
+            Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?))
+        }
+        2 => {
+            let start = NaiveDate::from_ymd_opt(number(0)? as i32, number(1)?, 1)?;
+            Some((start, next_month(start)?))
+        }
+        3 => {
+            let day = NaiveDate::from_ymd_opt(number(0)? as i32, number(1)?, number(2)?)?;
+            Some((day, day + Duration::days(1)))
+        }
+        _ => None,
+    }
+}
+
+fn next_month(first: NaiveDate) -> Option<NaiveDate> {
+    if first.month() == 12 {
+        NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+    }
+}
+
+fn previous_month(first: NaiveDate) -> Option<NaiveDate> {
+    if first.month() == 1 {
+        NaiveDate::from_ymd_opt(first.year() - 1, 12, 1)
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() - 1, 1)
+    }
+}
+
+// day boundaries follow the local clock, not utc
+fn local_midnight(date: NaiveDate) -> Option<DateTime<Utc>> {
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+        .map(|at| at.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_duration("12h"), Some(Duration::hours(12)));
+        assert_eq!(parse_duration("7d"), Some(Duration::days(7)));
+        assert_eq!(parse_duration("2w"), Some(Duration::weeks(2)));
+        assert_eq!(parse_duration("7"), None);
+        assert_eq!(parse_duration("7y"), None);
+    }
+
+    #[test]
+    fn rejects_negative_and_huge_ages() {
+        assert_eq!(parse_duration("-3d"), None);
+        assert_eq!(parse_duration("99999999999999d"), None);
+
+        let now = Local::now();
+        assert!(parse_range("<100000000d", now).is_none());
+        assert!(parse_range(">4000000000w", now).is_none());
+        assert!(parse_range("<-3d", now).is_none());
+    }
+
+    #[test]
+    fn ages_count_back_from_now() {
+        let now = Local::now();
+        let range = parse_range("<7d", now).unwrap();
+
+        assert_eq!(range.start, Some(now.with_timezone(&Utc) - Duration::days(7)));
+        assert!(range.contains(now.with_timezone(&Utc) - Duration::days(6)));
+        assert!(!range.contains(now.with_timezone(&Utc) - Duration::days(8)));
+    }
+}
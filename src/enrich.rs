@@ -0,0 +1,237 @@
+use chrono::Utc;
+use console::Style;
+use serde::Deserialize;
+
+use crate::ai::{AiClient, AiError};
+use crate::models::{Note, NoteSource};
+use crate::ranking::SortOrder;
+use crate::repository::{FileRepository, NoteRepository};
+use crate::store::{self, SearchOptions};
+use crate::tags::Vocabulary;
+
+// at most this many of each are proposed for one note
+pub const MAX_TAGS: usize = 5;
+pub const MAX_PROJECTS: usize = 3;
+const MAX_TITLE_CHARS: usize = 80;
+
+// one proposed change to a note, accepted or dismissed on its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Suggestion {
+    Title(String),
+    Tag(String),
+    Project(String),
+}
+
+impl Suggestion {
+    pub fn label(&self) -> String {
+        match self {
+            Suggestion::Title(title) => format!("title: {}", title),
+            Suggestion::Tag(tag) => format!("#{}", tag),
+            Suggestion::Project(project) => format!("+{}", project),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawSuggestions {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    projects: Vec<String>,
+}
+
+// the model's json reply as suggestions for `note`. tags and projects outside
+// the vocabulary, or already on the note, are dropped, and a title is only
+// proposed for an untitled note
+pub fn parse(response: &str, note: &Note, vocabulary: &Vocabulary) -> Result<Vec<Suggestion>, AiError> {
+    let json = match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => return Err(AiError::InvalidResponse),
+    };
+    let raw: RawSuggestions = serde_json::from_str(json).map_err(|_| AiError::InvalidResponse)?;
+
+    let mut suggestions = Vec::new();
+
+    let untitled = note.title.as_deref().is_none_or(|title| title.trim().is_empty());
+    if let Some(title) = raw.title.as_deref().map(|title| title.trim().trim_matches('"').trim()) {
+        if untitled && !title.is_empty() && title.chars().count() <= MAX_TITLE_CHARS {
+            suggestions.push(Suggestion::Title(title.to_string()));
+        }
+    }
+
+    let tags = pick(&raw.tags, &note.tags, '#', MAX_TAGS, |name| vocabulary.tag(name));
+    let projects = pick(&raw.projects, &note.projects, '+', MAX_PROJECTS, |name| vocabulary.project(name));
+
+    suggestions.extend(tags.into_iter().map(Suggestion::Tag));
+    suggestions.extend(projects.into_iter().map(Suggestion::Project));
+    Ok(suggestions)
+}
+
+// the proposed names that exist in the vocabulary, spelled the way the vault
+// spells them, minus the ones the note already has
+fn pick<'a>(proposed: &[String], existing: &[String], sigil: char, max: usize, lookup: impl Fn(&str) -> Option<&'a str>) -> Vec<String> {
+    let mut picked: Vec<String> = Vec::new();
+
+    for name in proposed {
+        let Some(name) = lookup(name.trim().trim_start_matches(sigil)) else {
+            continue;
+        };
+        let taken = existing.iter().chain(picked.iter()).any(|other| other.eq_ignore_ascii_case(name));
+        if !taken && picked.len() < max {
+            picked.push(name.to_string());
+        }
+    }
+
+    picked
+}
+
+// the note with the accepted suggestions applied. tags and projects are
+// attached explicitly, the content is left alone
+pub fn apply(note: &Note, accepted: &[Suggestion]) -> Note {
+    let mut updated = note.clone();
+
+    for suggestion in accepted {
+        match suggestion {
+            Suggestion::Title(title) => updated.title = Some(title.clone()),
+            Suggestion::Tag(tag) => {
+                if !updated.explicit_tags.contains(tag) {
+                    updated.explicit_tags.push(tag.clone());
+                }
+            }
+            Suggestion::Project(project) => {
+                if !updated.explicit_projects.contains(project) {
+                    updated.explicit_projects.push(project.clone());
+                }
+            }
+        }
+    }
+
+    updated.merge_metadata();
+    updated
+}
+
+fn describe(suggestions: &[Suggestion]) -> String {
+    suggestions.iter().map(Suggestion::label).collect::<Vec<_>>().join("  ")
+}
+
+fn ready_client() -> Result<Option<AiClient>, AiError> {
+    let ai_client = AiClient::new()?;
+    if !ai_client.is_configured() {
+        eprintln!("enriching needs an ai provider, add an openai key in the tui settings or run 'stash provider add'");
+        return Ok(None);
+    }
+    Ok(Some(ai_client))
+}
+
+// `stash add --enrich`: the note is saved either way, with whatever the
+// model suggested when it could be reached
+pub async fn add(content: String, title: Option<String>) -> Result<(), AiError> {
+    let success_style = Style::new().bold().green();
+    let dim_style = Style::new().dim();
+
+    let note = store::new_note(content, title, NoteSource::QuickCapture);
+    let vocabulary = store::vocabulary()?;
+
+    let suggestions = match ready_client()? {
+        Some(ai_client) => match ai_client.suggest_metadata(&note, &vocabulary).await {
+            Ok(suggestions) => suggestions,
+            Err(e) => {
+                eprintln!("couldn't get suggestions ({}), saving the note as it is", e);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let note = apply(&note, &suggestions);
+    let mut repository = FileRepository::open()?;
+    repository.create(note)?;
+
+    println!("note saved successfully");
+    if suggestions.is_empty() {
+        println!("{}", dim_style.apply_to("no suggestions to add"));
+    } else {
+        println!("{} added {}", success_style.apply_to("✓"), describe(&suggestions));
+    }
+    Ok(())
+}
+
+// `stash enrich <query>`: suggestions for every matching note, saved as one
+// change unless it's a dry run. a preview prints each request instead
+pub async fn run(query: String, limit: Option<usize>, dry_run: bool, preview: bool) -> Result<(), AiError> {
+    let heading_style = Style::new().bold().cyan();
+    let success_style = Style::new().bold().green();
+    let dim_style = Style::new().dim();
+
+    let options = SearchOptions {
+        query,
+        filter_tags: None,
+        filter_projects: None,
+        list_tags: false,
+        list_projects: false,
+        case_sensitive: false,
+        limit,
+        since: None,
+        until: None,
+        sort: SortOrder::Score,
+        fuzzy: false,
+        recency: true,
+    };
+    let notes: Vec<Note> = store::search_notes_return_results(options)?
+        .into_iter()
+        .map(|result| result.note)
+        .collect();
+
+    if notes.is_empty() {
+        println!("no notes match that query");
+        return Ok(());
+    }
+
+    let Some(ai_client) = ready_client()? else {
+        return Ok(());
+    };
+    let vocabulary = store::vocabulary()?;
+
+    if preview {
+        for note in &notes {
+            match ai_client.metadata_request(note, &vocabulary) {
+                Ok(outgoing) => println!("{}\n", ai_client.preview(&outgoing)),
+                Err(e) => eprintln!("  {}", e),
+            }
+        }
+        return Ok(());
+    }
+
+    println!("{} asking {} about {} note{}...", heading_style.apply_to("🤖"), ai_client.model(), notes.len(), if notes.len() == 1 { "" } else { "s" });
+
+    let mut updated = Vec::new();
+    for note in &notes {
+        let name = note.title.as_deref().unwrap_or("untitled");
+        match ai_client.suggest_metadata(note, &vocabulary).await {
+            Ok(suggestions) if suggestions.is_empty() => {
+                println!("  {} {}", name, dim_style.apply_to("nothing to add"));
+            }
+            Ok(suggestions) => {
+                println!("  {} {}", name, success_style.apply_to(describe(&suggestions)));
+                let mut enriched = apply(note, &suggestions);
+                enriched.updated = Some(Utc::now());
+                updated.push(enriched);
+            }
+            Err(e) => eprintln!("  {} failed: {}", name, e),
+        }
+    }
+
+    if updated.is_empty() {
+        println!("\nnothing to change");
+    } else if dry_run {
+        println!("\nwould enrich {} note{}, run again without --dry-run to save", updated.len(), if updated.len() == 1 { "" } else { "s" });
+    } else {
+        let mut repository = FileRepository::open()?;
+        repository.update_all(&updated, &format!("enrich {} notes", updated.len()))?;
+        println!("\n{} enriched {} note{}", success_style.apply_to("✓"), updated.len(), if updated.len() == 1 { "" } else { "s" });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,279 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use console::Style;
+use thiserror::Error;
+
+use crate::config::{Config, ConfigError, GitConfig};
+use crate::vault;
+
+const REMOTE_NAME: &str = "origin";
+
+// the index is a cache, config.json holds the api key and the ai audit log
+// records what left the machine, none of them belong in history
+const GITIGNORE: &str = "index/\nconfig.json\nai-audit.jsonl\n";
+
+#[derive(Error, Debug)]
+pub enum GitError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("git {command} failed: {stderr}")]
+    Command { command: String, stderr: String },
+    #[error("no git remote configured, run `stash git enable --remote <url>`")]
+    NoRemote,
+    #[error("git backend is not enabled, run `stash git enable`")]
+    NotEnabled,
+}
+
+// commits the stash directory after every change. shells out to the git
+// binary so the user's own credentials and ssh setup just work
+pub struct GitBackend {
+    dir: PathBuf,
+    remote: Option<String>,
+    branch: String,
+}
+
+impl GitBackend {
+    pub fn new(dir: PathBuf, config: &GitConfig) -> Self {
+        Self {
+            dir,
+            remote: config.remote.clone(),
+            branch: config.branch.clone(),
+        }
+    }
+
+    // the backend for the current vault, if the user turned it on there
+    pub fn from_config(config: &Config) -> Result<Option<Self>, GitError> {
+        Ok(Self::for_dir(config, vault::stash_dir()?))
+    }
+
+    fn for_dir(config: &Config, dir: PathBuf) -> Option<Self> {
+        config.git.get(&dir).filter(|git| git.enabled).map(|git| Self::new(dir, git))
+    }
+
+    pub fn ensure_repo(&self) -> Result<(), GitError> {
+        fs::create_dir_all(&self.dir)?;
+
+        if !self.dir.join(".git").exists() {
+            self.git(&["init", "--quiet", "--initial-branch", &self.branch])?;
+        }
+
+        let gitignore = self.dir.join(".gitignore");
+        if !gitignore.exists() {
+            fs::write(gitignore, GITIGNORE)?;
+        }
+
+        Ok(())
+    }
+
+    // stages everything and commits it. returns false when there was nothing to commit
+    pub fn commit(&self, message: &str) -> Result<bool, GitError> {
+        self.ensure_repo()?;
+        self.git(&["add", "--all"])?;
+
+        let status = self.git(&["status", "--porcelain"])?;
+        if status.stdout.is_empty() {
+            return Ok(false);
+        }
+
+        self.git_as_committer(&["commit", "--quiet", "-m", message])?;
+
+        Ok(true)
+    }
+
+    // commits anything pending, rebases onto the remote branch and pushes
+    pub fn sync(&self) -> Result<(), GitError> {
+        let remote = self.remote.as_deref().ok_or(GitError::NoRemote)?;
+
+        self.commit("sync")?;
+        self.set_remote(remote)?;
+        self.git(&["fetch", "--quiet", REMOTE_NAME])?;
+
+        let remote_branch = format!("{}/{}", REMOTE_NAME, self.branch);
+        if self.git(&["rev-parse", "--verify", "--quiet", &remote_branch]).is_ok() {
+            if let Err(e) = self.git_as_committer(&["rebase", "--quiet", &remote_branch]) {
+                let _ = self.git(&["rebase", "--abort"]);
+                return Err(e);
+            }
+        }
+
+        let refspec = format!("HEAD:refs/heads/{}", self.branch);
+        self.git(&["push", "--quiet", REMOTE_NAME, &refspec])?;
+        Ok(())
+    }
+
+    fn set_remote(&self, url: &str) -> Result<(), GitError> {
+        match self.git(&["remote", "get-url", REMOTE_NAME]) {
+            Ok(output) if String::from_utf8_lossy(&output.stdout).trim() == url => Ok(()),
+            Ok(_) => self.git(&["remote", "set-url", REMOTE_NAME, url]).map(|_| ()),
+            Err(_) => self.git(&["remote", "add", REMOTE_NAME, url]).map(|_| ()),
+        }
+    }
+
+    // runs a command that writes commits, falling back to a local identity so
+    // it never fails on a fresh machine
+    fn git_as_committer(&self, args: &[&str]) -> Result<Output, GitError> {
+        if self.git(&["config", "user.email"]).is_ok() {
+            return self.git(args);
+        }
+
+        let mut with_identity = vec!["-c", "user.name=stash", "-c", "user.email=stash@localhost"];
+        with_identity.extend_from_slice(args);
+        self.git(&with_identity)
+    }
+
+    fn git(&self, args: &[&str]) -> Result<Output, GitError> {
+        run_git(&self.dir, args)
+    }
+}
+
+fn run_git(dir: &Path, args: &[&str]) -> Result<Output, GitError> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+
+    if !output.status.success() {
+        return Err(GitError::Command {
+            // skip any leading `-c key=value` overrides
+            command: args.iter().find(|arg| !arg.starts_with('-') && !arg.contains('=')).copied().unwrap_or_default().to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(output)
+}
+
+pub fn enable(remote: Option<String>, branch: Option<String>) -> Result<(), GitError> {
+    let mut config = Config::load()?;
+    let dir = vault::stash_dir()?;
+    let git = config.git.entry(dir.clone()).or_default();
+    git.enabled = true;
+    if remote.is_some() {
+        git.remote = remote;
+    }
+    if let Some(branch) = branch {
+        git.branch = branch;
+    }
+    let git = git.clone();
+    config.save()?;
+
+    let backend = GitBackend::new(dir, &git);
+    backend.commit("enable git backend")?;
+
+    println!("git backend enabled for {}", backend.dir.display());
+    if let Some(ref remote) = git.remote {
+        println!("remote: {} ({})", remote, git.branch);
+    }
+    Ok(())
+}
+
+pub fn disable() -> Result<(), GitError> {
+    let mut config = Config::load()?;
+    let git = config.git.get_mut(&vault::stash_dir()?).ok_or(GitError::NotEnabled)?;
+    git.enabled = false;
+    config.save()?;
+    println!("git backend disabled, existing history is left in place");
+    Ok(())
+}
+
+pub fn sync() -> Result<(), GitError> {
+    let config = Config::load()?;
+    let backend = GitBackend::from_config(&config)?.ok_or(GitError::NotEnabled)?;
+    let success_style = Style::new().bold().green();
+
+    backend.sync()?;
+    println!("{} synced with {}", success_style.apply_to("✓"), backend.remote.as_deref().unwrap_or(REMOTE_NAME));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_note;
+
+    fn git_config(remote: &Path) -> GitConfig {
+        GitConfig { enabled: true, remote: Some(remote.display().to_string()), branch: "main".to_string() }
+    }
+
+    fn backend(dir: PathBuf, remote: &Path) -> GitBackend {
+        GitBackend::new(dir, &git_config(remote))
+    }
+
+    fn save(dir: &Path, title: &str) {
+        let note = test_note(title, &format!("about {}", title));
+        fs::create_dir_all(dir.join("notes")).unwrap();
+        note.save_to_file(dir.join("notes").join(format!("{}.md", note.id))).unwrap();
+    }
+
+    fn count(dir: &Path, revision: &str) -> usize {
+        let output = run_git(dir, &["rev-list", "--count", revision]).unwrap();
+        String::from_utf8_lossy(&output.stdout).trim().parse().unwrap()
+    }
+
+    #[test]
+    fn saves_commit_and_sync_pushes_to_a_bare_remote() {
+        let temp = tempfile::tempdir().unwrap();
+        let remote = temp.path().join("remote.git");
+        run_git(temp.path(), &["init", "--bare", "--quiet", "--initial-branch", "main", "remote.git"]).unwrap();
+
+        let laptop = backend(temp.path().join("laptop"), &remote);
+        save(&laptop.dir, "first");
+        assert!(laptop.commit("create 'first'").unwrap());
+        assert!(!laptop.commit("nothing changed").unwrap());
+
+        // the index is a cache and never committed
+        fs::create_dir_all(laptop.dir.join("index")).unwrap();
+        fs::write(laptop.dir.join("index").join("index.json"), "{}").unwrap();
+        assert!(!laptop.commit("index only").unwrap());
+
+        save(&laptop.dir, "second");
+        assert!(laptop.commit("create 'second'").unwrap());
+        assert_eq!(count(&laptop.dir, "HEAD"), 2);
+
+        laptop.sync().unwrap();
+        assert_eq!(count(&remote, "main"), 2);
+
+        // a second machine picks the notes up, adds one and pushes it back
+        let desktop = backend(temp.path().join("desktop"), &remote);
+        save(&desktop.dir, "third");
+        desktop.commit("create 'third'").unwrap();
+        desktop.sync().unwrap();
+        assert_eq!(count(&remote, "main"), 3);
+        assert_eq!(fs::read_dir(desktop.dir.join("notes")).unwrap().count(), 3);
+
+        laptop.sync().unwrap();
+        assert_eq!(count(&laptop.dir, "HEAD"), 3);
+        assert_eq!(fs::read_dir(laptop.dir.join("notes")).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn the_ai_audit_log_is_never_staged() {
+        let temp = tempfile::tempdir().unwrap();
+        let backend = backend(temp.path().join("stash"), &temp.path().join("remote.git"));
+        save(&backend.dir, "first");
+        fs::write(backend.dir.join("ai-audit.jsonl"), "{}\n").unwrap();
+        assert!(backend.commit("create 'first'").unwrap());
+
+        fs::write(backend.dir.join("ai-audit.jsonl"), "{}\n{}\n").unwrap();
+        assert!(!backend.commit("audit only").unwrap());
+
+        let tracked = run_git(&backend.dir, &["ls-files"]).unwrap();
+        assert!(!String::from_utf8_lossy(&tracked.stdout).contains("ai-audit.jsonl"));
+    }
+
+    #[test]
+    fn each_vault_uses_only_its_own_git_settings() {
+        let temp = tempfile::tempdir().unwrap();
+        let (work, personal) = (temp.path().join("work"), temp.path().join("personal"));
+        let mut config = Config::default();
+        config.git.insert(work.clone(), git_config(Path::new("git@example.com:work.git")));
+
+        let backend = GitBackend::for_dir(&config, work.clone()).unwrap();
+        assert_eq!(backend.dir, work);
+        assert_eq!(backend.remote.as_deref(), Some("git@example.com:work.git"));
+        assert!(GitBackend::for_dir(&config, personal).is_none());
+
+        config.git.get_mut(&work).unwrap().enabled = false;
+        assert!(GitBackend::for_dir(&config, work).is_none());
+    }
+}
@@ -0,0 +1,310 @@
+use std::fs;
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use console::Style;
+use similar::{ChangeTag, TextDiff};
+use uuid::Uuid;
+
+use crate::links::{self, short_id};
+use crate::models::Note;
+use crate::repository::{Change, FileRepository, NoteRepository};
+use crate::store::StoreError;
+use crate::vault;
+
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub number: u32,
+    pub note: Note,
+}
+
+impl Revision {
+    // when this version of the note was last saved
+    pub fn saved_at(&self) -> DateTime<Utc> {
+        self.note.updated.unwrap_or(self.note.created)
+    }
+}
+
+// previous versions of each note, kept as plain markdown files under
+// <stash>/history/<note id>/<revision>.md
+pub struct HistoryStore {
+    dir: PathBuf,
+}
+
+impl HistoryStore {
+    pub fn open() -> Result<Self, StoreError> {
+        Ok(Self::at(vault::history_dir()?))
+    }
+
+    pub fn at(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn note_dir(&self, id: Uuid) -> PathBuf {
+        self.dir.join(id.to_string())
+    }
+
+    // snapshots `note` as a new revision unless it matches the latest one
+    pub fn record(&self, note: &Note) -> Result<Option<u32>, StoreError> {
+        let revisions = self.revisions(note.id)?;
+
+        if revisions.last().is_some_and(|latest| same_version(&latest.note, note)) {
+            return Ok(None);
+        }
+
+        let number = revisions.last().map_or(1, |latest| latest.number + 1);
+        let dir = self.note_dir(note.id);
+        fs::create_dir_all(&dir)?;
+        note.save_to_file(dir.join(format!("{}.md", number)))?;
+
+        Ok(Some(number))
+    }
+
+    // oldest first
+    pub fn revisions(&self, id: Uuid) -> Result<Vec<Revision>, StoreError> {
+        let dir = self.note_dir(id);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut revisions = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let number = path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u32>().ok());
+
+            if let Some(number) = number {
+                if let Ok(note) = Note::load_from_file(&path) {
+                    revisions.push(Revision { number, note });
+                }
+            }
+        }
+
+        revisions.sort_by_key(|revision| revision.number);
+        Ok(revisions)
+    }
+
+    pub fn get(&self, id: Uuid, number: u32) -> Result<Revision, StoreError> {
+        let path = self.note_dir(id).join(format!("{}.md", number));
+        if !path.exists() {
+            return Err(StoreError::RevisionNotFound(number));
+        }
+
+        Ok(Revision {
+            number,
+            note: Note::load_from_file(path)?,
+        })
+    }
+
+    pub fn remove(&self, id: Uuid) -> Result<(), StoreError> {
+        let dir = self.note_dir(id);
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
+
+// only changes a person would notice count as a new version; link bookkeeping
+// and trash state don't
+pub fn same_version(a: &Note, b: &Note) -> bool {
+    a.title == b.title && a.content == b.content && a.tags == b.tags && a.projects == b.projects
+}
+
+// brings back the title, tags, projects and content of a revision. the current
+// version is snapshotted first, so a revert can itself be reverted
+pub fn revert_note(repository: &mut dyn NoteRepository, note: &Note, revision: &Revision) -> Result<Note, StoreError> {
+    let mut reverted = note.clone();
+    reverted.title = revision.note.title.clone();
+    reverted.tags = revision.note.tags.clone();
+    reverted.projects = revision.note.projects.clone();
+    reverted.explicit_tags = revision.note.explicit_tags.clone();
+    reverted.explicit_projects = revision.note.explicit_projects.clone();
+    reverted.content = revision.note.content.clone();
+    reverted.updated = Some(Utc::now());
+
+    repository.update_as(&reverted, Change::Revert)?;
+    Ok(reverted)
+}
+
+pub fn show_history(query: &str) -> Result<(), StoreError> {
+    let mut repository = FileRepository::open()?;
+    let notes = repository.load()?.notes;
+    let note = links::find_note(&notes, query)?;
+    let revisions = HistoryStore::open()?.revisions(note.id)?;
+    let number_style = Style::new().bold().cyan();
+    let dim_style = Style::new().dim();
+
+    println!("\nhistory of {}", Style::new().bold().cyan().apply_to(note.title.as_deref().unwrap_or("untitled")));
+    println!("{}", "─".repeat(50));
+
+    if revisions.is_empty() {
+        println!("{}", dim_style.apply_to("no earlier revisions"));
+        return Ok(());
+    }
+
+    for revision in revisions.iter().rev() {
+        let (added, removed) = line_changes(&revision.note.content, &note.content);
+        println!("  {} {} {} {}",
+            number_style.apply_to(format!("{:>3}", revision.number)),
+            dim_style.apply_to(revision.saved_at().format("%Y-%m-%d %H:%M")),
+            revision.note.title.as_deref().unwrap_or("untitled"),
+            dim_style.apply_to(format!("(+{} -{} vs current)", added, removed))
+        );
+    }
+
+    println!("\n💡 compare with: stash diff {} <rev>", short_id(note.id));
+    Ok(())
+}
+
+pub fn show_diff(query: &str, number: Option<u32>) -> Result<(), StoreError> {
+    let mut repository = FileRepository::open()?;
+    let notes = repository.load()?.notes;
+    let note = links::find_note(&notes, query)?;
+    let history = HistoryStore::open()?;
+
+    let revision = match number {
+        Some(number) => history.get(note.id, number)?,
+        None => history.revisions(note.id)?.pop().ok_or(StoreError::NoRevisions(note.id))?,
+    };
+
+    print!("{}", render_diff(&revision.note, note, &format!("revision {}", revision.number), "current"));
+    Ok(())
+}
+
+pub fn revert(query: &str, number: u32) -> Result<(), StoreError> {
+    let mut repository = FileRepository::open()?;
+    let notes = repository.load()?.notes;
+    let note = links::find_note(&notes, query)?;
+    let revision = HistoryStore::open()?.get(note.id, number)?;
+
+    if same_version(&revision.note, note) {
+        println!("'{}' already matches revision {}", note.title.as_deref().unwrap_or("untitled"), number);
+        return Ok(());
+    }
+
+    let reverted = revert_note(&mut repository, note, &revision)?;
+    println!("reverted '{}' to revision {}", reverted.title.as_deref().unwrap_or("untitled"), number);
+    Ok(())
+}
+
+// a coloured unified diff of `old` against `new`, title first, then content
+pub fn render_diff(old: &Note, new: &Note, old_label: &str, new_label: &str) -> String {
+    let removed_style = Style::new().red();
+    let added_style = Style::new().green();
+    let hunk_style = Style::new().cyan();
+    let mut out = String::new();
+
+    out.push_str(&format!("{}\n", removed_style.apply_to(format!("--- {}", old_label))));
+    out.push_str(&format!("{}\n", added_style.apply_to(format!("+++ {}", new_label))));
+
+    if old.title != new.title {
+        out.push_str(&format!("{}\n", removed_style.apply_to(format!("-title: {}", old.title.as_deref().unwrap_or("")))));
+        out.push_str(&format!("{}\n", added_style.apply_to(format!("+title: {}", new.title.as_deref().unwrap_or("")))));
+    }
+
+    for (label, old_entries, new_entries) in [("tags", &old.tags, &new.tags), ("projects", &old.projects, &new.projects)] {
+        if old_entries != new_entries {
+            out.push_str(&format!("{}\n", removed_style.apply_to(format!("-{}: {}", label, old_entries.join(", ")))));
+            out.push_str(&format!("{}\n", added_style.apply_to(format!("+{}: {}", label, new_entries.join(", ")))));
+        }
+    }
+
+    for line in diff_lines(&old.content, &new.content) {
+        let formatted = match line {
+            DiffLine::Hunk(header) => hunk_style.apply_to(header).to_string(),
+            DiffLine::Removed(text) => removed_style.apply_to(format!("-{}", text)).to_string(),
+            DiffLine::Added(text) => added_style.apply_to(format!("+{}", text)).to_string(),
+            DiffLine::Context(text) => format!(" {}", text),
+        };
+        out.push_str(&formatted);
+        out.push('\n');
+    }
+
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    Hunk(String),
+    Removed(String),
+    Added(String),
+    Context(String),
+}
+
+// unified diff lines with three lines of context around each hunk
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = Vec::new();
+
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        lines.push(DiffLine::Hunk(hunk.header().to_string()));
+
+        for change in hunk.iter_changes() {
+            let text = change.value().trim_end_matches('\n').to_string();
+            lines.push(match change.tag() {
+                ChangeTag::Delete => DiffLine::Removed(text),
+                ChangeTag::Insert => DiffLine::Added(text),
+                ChangeTag::Equal => DiffLine::Context(text),
+            });
+        }
+    }
+
+    lines
+}
+
+// (added, removed) line counts going from `old` to `new`
+pub fn line_changes(old: &str, new: &str) -> (usize, usize) {
+    let diff = TextDiff::from_lines(old, new);
+    diff.iter_all_changes().fold((0, 0), |(added, removed), change| match change.tag() {
+        ChangeTag::Insert => (added + 1, removed),
+        ChangeTag::Delete => (added, removed + 1),
+        ChangeTag::Equal => (added, removed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_note;
+
+    #[test]
+    fn saving_an_unchanged_note_keeps_one_version() {
+        let temp = tempfile::tempdir().unwrap();
+        let history = HistoryStore::at(temp.path().to_path_buf());
+        let mut note = test_note("plan", "first");
+
+        assert_eq!(history.record(&note).unwrap(), Some(1));
+        assert_eq!(history.record(&note).unwrap(), None);
+
+        // link bookkeeping and the trash don't make a new version
+        note.unresolved_links = vec!["elsewhere".to_string()];
+        note.deleted_at = Some(Utc::now());
+        assert_eq!(history.record(&note).unwrap(), None);
+
+        note.content = "second".to_string();
+        assert_eq!(history.record(&note).unwrap(), Some(2));
+        assert_eq!(history.revisions(note.id).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn revert_snapshots_the_current_version_first() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut repository = FileRepository::at(temp.path()).unwrap();
+        let mut note = repository.create(test_note("plan", "first draft")).unwrap();
+        note.content = "second draft".to_string();
+        repository.update(&note).unwrap();
+
+        let first = repository.history().unwrap().get(note.id, 1).unwrap();
+        assert_eq!(first.note.content, "first draft");
+
+        let reverted = revert_note(&mut repository, &note, &first).unwrap();
+        assert_eq!(reverted.content, "first draft");
+        assert_eq!(repository.get(note.id).unwrap().content, "first draft");
+
+        // the draft that was current before the revert can be brought back
+        let revisions = repository.history().unwrap().revisions(note.id).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[1].note.content, "second draft");
+    }
+}
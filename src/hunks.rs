@@ -0,0 +1,132 @@
+use std::ops::Range;
+use similar::{ChangeTag, DiffTag, TextDiff};
+
+// reviewing an ai rewrite hunk by hunk: the changed regions between the
+// original and the rewrite, and the text with only the accepted ones applied
+
+// a changed region between two texts, as line ranges into each
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+// the lines `hunks` ranges index into, line endings kept
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+// every changed region from `old` to `new`, without context, in order
+pub fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let diff = TextDiff::from_lines(old, new);
+    diff.grouped_ops(0)
+        .iter()
+        .filter_map(|group| {
+            // groups can carry empty equal runs at their edges
+            let mut changes = group.iter().filter(|op| op.tag() != DiffTag::Equal);
+            let first = changes.next()?;
+            let last = changes.next_back().unwrap_or(first);
+            Some(Hunk {
+                old: first.old_range().start..last.old_range().end,
+                new: first.new_range().start..last.new_range().end,
+            })
+        })
+        .collect()
+}
+
+// `old` with the accepted hunks taken from `new` and the rest left alone
+pub fn merge(old: &str, new: &str, hunks: &[Hunk], accepted: &[bool]) -> String {
+    let (old_lines, new_lines) = (split_lines(old), split_lines(new));
+    let mut merged = String::new();
+    let mut position = 0;
+
+    for (hunk, accepted) in hunks.iter().zip(accepted) {
+        merged.extend(old_lines[position..hunk.old.start].iter().copied());
+        if *accepted {
+            merged.extend(new_lines[hunk.new.clone()].iter().copied());
+        } else {
+            merged.extend(old_lines[hunk.old.clone()].iter().copied());
+        }
+        position = hunk.old.end;
+    }
+
+    merged.extend(old_lines[position..].iter().copied());
+    merged
+}
+
+// a line split into words, each marked true where it differs from the other side
+pub type Words = Vec<(String, bool)>;
+
+// both sides of a line changed in place, word by word
+pub fn word_changes(old_line: &str, new_line: &str) -> (Words, Words) {
+    let diff = TextDiff::from_words(old_line, new_line);
+    let (mut old_words, mut new_words) = (Vec::new(), Vec::new());
+
+    for change in diff.iter_all_changes() {
+        let word = change.value().to_string();
+        match change.tag() {
+            ChangeTag::Equal => {
+                old_words.push((word.clone(), false));
+                new_words.push((word, false));
+            }
+            ChangeTag::Delete => old_words.push((word, true)),
+            ChangeTag::Insert => new_words.push((word, true)),
+        }
+    }
+
+    (old_words, new_words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(old: &str, new: &str, accepted: &[bool]) -> String {
+        merge(old, new, &hunks(old, new), accepted)
+    }
+
+    #[test]
+    fn accepting_or_rejecting_every_hunk_gives_either_side() {
+        let (old, new) = ("a\nb\nc\nd\ne\n", "a\nB\nc\nD\nE\nf");
+        assert_eq!(hunks(old, new), vec![Hunk { old: 1..2, new: 1..2 }, Hunk { old: 3..5, new: 3..6 }]);
+
+        assert_eq!(review(old, new, &[true, true]), new);
+        assert_eq!(review(old, new, &[false, false]), old);
+        assert_eq!(review(old, new, &[true, false]), "a\nB\nc\nd\ne\n");
+        assert_eq!(review(old, new, &[false, true]), "a\nb\nc\nD\nE\nf");
+    }
+
+    #[test]
+    fn hunks_one_line_apart_are_reviewed_separately() {
+        let (old, new) = ("a\nb\nc\n", "A\nb\nC\n");
+        assert_eq!(hunks(old, new).len(), 2);
+        assert_eq!(review(old, new, &[false, true]), "a\nb\nC\n");
+
+        // lines changed back to back are one hunk
+        assert_eq!(hunks("a\nb\n", "A\nB\n"), vec![Hunk { old: 0..2, new: 0..2 }]);
+    }
+
+    #[test]
+    fn a_trailing_newline_is_a_change_of_its_own() {
+        let (old, new) = ("x\ny", "x\ny\n");
+        assert_eq!(hunks(old, new), vec![Hunk { old: 1..2, new: 1..2 }]);
+        assert_eq!(review(old, new, &[false]), old);
+        assert_eq!(review(old, new, &[true]), new);
+
+        // a line added at the very end
+        assert_eq!(review("a\n", "a\nb\n", &[true]), "a\nb\n");
+        assert_eq!(review("a\n", "a\nb\n", &[false]), "a\n");
+    }
+
+    #[test]
+    fn word_changes_mark_only_the_changed_words() {
+        let (old, new) = word_changes("the quick fox jumps", "the slow fox jumps");
+        let changed = |words: &Words| words.iter().filter(|(_, changed)| *changed).map(|(word, _)| word.clone()).collect::<Vec<_>>();
+        let joined = |words: &Words| words.iter().map(|(word, _)| word.as_str()).collect::<String>();
+
+        assert_eq!(changed(&old), vec!["quick"]);
+        assert_eq!(changed(&new), vec!["slow"]);
+        assert_eq!(joined(&old), "the quick fox jumps");
+        assert_eq!(joined(&new), "the slow fox jumps");
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::config::ConfigError;
+use crate::models::Note;
+use crate::tags;
+use crate::vault;
+
+// bump when what gets extracted from a note changes, so old indexes are rebuilt
+const INDEX_VERSION: u32 = 4;
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Error, Debug)]
+pub enum IndexError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified_secs: u64,
+    modified_nanos: u32,
+    size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexEntry {
+    pub note: Note,
+    pub path: PathBuf,
+    pub projects: Vec<String>,
+    pub counts: TermCounts,
+    stamp: FileStamp,
+}
+
+// how often each word appears in a note's title and content, and how many
+// words each has, so ranking never has to tokenize a note again
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TermCounts {
+    pub title: BTreeMap<String, usize>,
+    pub content: BTreeMap<String, usize>,
+    pub title_len: usize,
+    pub content_len: usize,
+}
+
+impl TermCounts {
+    fn of(note: &Note) -> Self {
+        let title = tokenize(note.title.as_deref().unwrap_or(""));
+        let content = tokenize(&note.content);
+
+        Self {
+            title_len: title.len(),
+            content_len: content.len(),
+            title: frequencies(title),
+            content: frequencies(content),
+        }
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.title.contains_key(word) || self.content.contains_key(word)
+    }
+
+    fn words(&self) -> BTreeSet<String> {
+        self.title.keys().chain(self.content.keys()).cloned().collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SearchIndex {
+    version: u32,
+    entries: BTreeMap<String, IndexEntry>,
+    terms: BTreeMap<String, BTreeSet<Uuid>>,
+    tags: BTreeMap<String, BTreeSet<Uuid>>,
+    projects: BTreeMap<String, BTreeSet<Uuid>>,
+    // where the index is saved, next to the notes it covers
+    #[serde(skip)]
+    dir: PathBuf,
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(skip)]
+    pub skipped: Vec<PathBuf>,
+}
+
+impl SearchIndex {
+    // loads the index kept in `dir` and brings it up to date with the notes
+    // directory, only re-parsing files whose mtime or size changed. one that
+    // can't be read or was written by another version is rebuilt from scratch
+    pub fn open_at(dir: PathBuf, notes_dir: &Path) -> Result<Self, IndexError> {
+        let mut index = match fs::read_to_string(dir.join(INDEX_FILE)) {
+            Ok(content) => serde_json::from_str::<SearchIndex>(&content)
+                .ok()
+                .filter(|index| index.version == INDEX_VERSION)
+                .unwrap_or_default(),
+            Err(_) => SearchIndex::default(),
+        };
+
+        index.version = INDEX_VERSION;
+        index.dir = dir;
+        index.sync(notes_dir)?;
+
+        Ok(index)
+    }
+
+    // refreshes from disk and persists the result if anything changed
+    pub fn sync(&mut self, notes_dir: &Path) -> Result<(), IndexError> {
+        self.refresh(notes_dir)?;
+
+        if self.dirty {
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn rebuild() -> Result<Self, IndexError> {
+        let mut index = SearchIndex {
+            version: INDEX_VERSION,
+            dir: vault::index_dir()?,
+            ..SearchIndex::default()
+        };
+
+        index.refresh(&vault::notes_dir()?)?;
+        index.save()?;
+        Ok(index)
+    }
+
+    pub fn refresh(&mut self, notes_dir: &Path) -> Result<(), IndexError> {
+        self.skipped.clear();
+
+        let mut seen = HashSet::new();
+
+        if notes_dir.exists() {
+            for entry in fs::read_dir(notes_dir)? {
+                let entry = entry?;
+                let path = entry.path();
+
+                if path.extension().and_then(|s| s.to_str()) != Some("md") {
+                    continue;
+                }
+
+                let Some(key) = path.file_name().and_then(|s| s.to_str()).map(str::to_string) else {
+                    continue;
+                };
+
+                let stamp = file_stamp(&entry.metadata()?);
+                seen.insert(key.clone());
+
+                if self.entries.get(&key).is_some_and(|existing| existing.stamp == stamp) {
+                    continue;
+                }
+
+                self.remove_entry(&key);
+
+                match Note::load_from_file(&path) {
+                    Ok(note) => self.insert_entry(key, note, path, stamp),
+                    Err(_) => self.skipped.push(path),
+                }
+            }
+        }
+
+        let stale: Vec<String> = self.entries.keys()
+            .filter(|key| !seen.contains(*key))
+            .cloned()
+            .collect();
+
+        for key in stale {
+            self.remove_entry(&key);
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> Result<(), IndexError> {
+        let index_path = self.dir.join(INDEX_FILE);
+        fs::create_dir_all(&self.dir)?;
+
+        let tmp_path = index_path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string(self)?)?;
+        fs::rename(&tmp_path, &index_path)?;
+
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.values()
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&IndexEntry> {
+        self.entries.values().find(|entry| entry.note.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // ids of notes whose title or content could contain `query` as a
+    // substring, or None when the query has no indexable terms. every term is
+    // checked on purpose: a prefix range over the sorted terms would miss
+    // matches inside a word, and there are far fewer terms than note text
+    pub fn text_candidates(&self, query: &str) -> Option<HashSet<Uuid>> {
+        let query_terms = tokenize(query);
+        if query_terms.is_empty() {
+            return None;
+        }
+
+        let mut candidates: Option<HashSet<Uuid>> = None;
+
+        for query_term in query_terms {
+            let matching: HashSet<Uuid> = self.terms.iter()
+                .filter(|(term, _)| term.contains(&query_term))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+
+            candidates = Some(match candidates {
+                Some(existing) => existing.intersection(&matching).copied().collect(),
+                None => matching,
+            });
+        }
+
+        candidates
+    }
+
+    // notes tagged `tag` or anything nested under it
+    pub fn with_tag(&self, tag: &str) -> HashSet<Uuid> {
+        lookup_within(&self.tags, &tag.to_lowercase())
+    }
+
+    pub fn with_project(&self, project: &str) -> HashSet<Uuid> {
+        lookup_within(&self.projects, &project.to_lowercase())
+    }
+
+    fn insert_entry(&mut self, key: String, note: Note, path: PathBuf, stamp: FileStamp) {
+        let projects = note.projects.clone();
+        let counts = TermCounts::of(&note);
+
+        for term in counts.words() {
+            self.terms.entry(term).or_default().insert(note.id);
+        }
+
+        for tag in &note.tags {
+            self.tags.entry(tag.to_lowercase()).or_default().insert(note.id);
+        }
+
+        for project in &projects {
+            self.projects.entry(project.to_lowercase()).or_default().insert(note.id);
+        }
+
+        self.entries.insert(key, IndexEntry { note, path, projects, counts, stamp });
+        self.dirty = true;
+    }
+
+    fn remove_entry(&mut self, key: &str) {
+        let Some(entry) = self.entries.remove(key) else {
+            return;
+        };
+
+        let id = entry.note.id;
+        let tags = entry.note.tags.iter().map(|t| t.to_lowercase());
+        let projects = entry.projects.iter().map(|p| p.to_lowercase());
+
+        remove_postings(&mut self.terms, entry.counts.words(), id);
+        remove_postings(&mut self.tags, tags, id);
+        remove_postings(&mut self.projects, projects, id);
+
+        self.dirty = true;
+    }
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect()
+}
+
+fn frequencies(words: Vec<String>) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for word in words {
+        *counts.entry(word).or_default() += 1;
+    }
+    counts
+}
+
+fn lookup_within(postings: &BTreeMap<String, BTreeSet<Uuid>>, ancestor: &str) -> HashSet<Uuid> {
+    postings.range(ancestor.to_string()..)
+        .take_while(|(key, _)| key.starts_with(ancestor))
+        .filter(|(key, _)| tags::is_within(key, ancestor))
+        .flat_map(|(_, ids)| ids.iter().copied())
+        .collect()
+}
+
+fn remove_postings<I>(postings: &mut BTreeMap<String, BTreeSet<Uuid>>, keys: I, id: Uuid)
+where
+    I: IntoIterator<Item = String>,
+{
+    for key in keys {
+        if let Some(ids) = postings.get_mut(&key) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                postings.remove(&key);
+            }
+        }
+    }
+}
+
+fn file_stamp(metadata: &fs::Metadata) -> FileStamp {
+    let modified = metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+
+    FileStamp {
+        modified_secs: modified.as_secs(),
+        modified_nanos: modified.subsec_nanos(),
+        size: metadata.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::store::test_note;
+
+    fn write_note(notes_dir: &Path, title: &str, content: &str) -> (Uuid, PathBuf) {
+        let note = test_note(title, content);
+        let path = notes_dir.join(format!("{}.md", note.id));
+        fs::create_dir_all(notes_dir).unwrap();
+        note.save_to_file(&path).unwrap();
+        (note.id, path)
+    }
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        fs::File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    fn title_of(index: &SearchIndex, id: Uuid) -> Option<String> {
+        index.get(id).and_then(|entry| entry.note.title.clone())
+    }
+
+    #[test]
+    fn entries_refresh_when_the_file_mtime_or_size_changes() {
+        let temp = tempfile::tempdir().unwrap();
+        let notes_dir = temp.path().join("notes");
+        let (id, path) = write_note(&notes_dir, "alpha", "first draft");
+        let mut index = SearchIndex::open_at(temp.path().join("index"), &notes_dir).unwrap();
+        assert!(index.text_candidates("draft").unwrap().contains(&id));
+
+        // a longer file is picked up from its size alone
+        let original = fs::read_to_string(&path).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, original.replace("first draft", "second longer draft")).unwrap();
+        set_modified(&path, modified);
+        index.sync(&notes_dir).unwrap();
+        assert!(index.text_candidates("longer").unwrap().contains(&id));
+
+        // same size and same mtime looks unchanged, a new mtime doesn't
+        let current = fs::read_to_string(&path).unwrap();
+        fs::write(&path, current.replace("title: alpha", "title: omega")).unwrap();
+        set_modified(&path, modified);
+        index.sync(&notes_dir).unwrap();
+        assert_eq!(title_of(&index, id).as_deref(), Some("alpha"));
+
+        set_modified(&path, modified + Duration::from_secs(5));
+        index.sync(&notes_dir).unwrap();
+        assert_eq!(title_of(&index, id).as_deref(), Some("omega"));
+    }
+
+    #[test]
+    fn deleted_files_are_dropped() {
+        let temp = tempfile::tempdir().unwrap();
+        let notes_dir = temp.path().join("notes");
+        let (kept, _) = write_note(&notes_dir, "kept", "shared words");
+        let (gone, gone_path) = write_note(&notes_dir, "gone", "shared words");
+        let mut index = SearchIndex::open_at(temp.path().join("index"), &notes_dir).unwrap();
+        assert_eq!(index.len(), 2);
+
+        fs::remove_file(gone_path).unwrap();
+        index.sync(&notes_dir).unwrap();
+        assert_eq!(index.len(), 1);
+        assert!(index.get(gone).is_none());
+        assert_eq!(index.text_candidates("shared").unwrap(), HashSet::from([kept]));
+        assert!(!index.text_candidates("gone").unwrap().contains(&gone));
+    }
+
+    #[test]
+    fn an_index_from_another_version_is_rebuilt() {
+        let temp = tempfile::tempdir().unwrap();
+        let (notes_dir, index_dir) = (temp.path().join("notes"), temp.path().join("index"));
+        let (id, path) = write_note(&notes_dir, "fresh", "text");
+        SearchIndex::open_at(index_dir.clone(), &notes_dir).unwrap();
+
+        // tamper with the saved entry, which is only noticed if it is reused
+        let key = path.file_name().unwrap().to_str().unwrap().to_string();
+        let saved = |version: u32| {
+            let mut json: serde_json::Value = serde_json::from_str(&fs::read_to_string(index_dir.join(INDEX_FILE)).unwrap()).unwrap();
+            json["version"] = version.into();
+            json["entries"][&key]["note"]["title"] = "stale".into();
+            fs::write(index_dir.join(INDEX_FILE), json.to_string()).unwrap();
+        };
+
+        saved(INDEX_VERSION);
+        let index = SearchIndex::open_at(index_dir.clone(), &notes_dir).unwrap();
+        assert_eq!(title_of(&index, id).as_deref(), Some("stale"));
+
+        saved(INDEX_VERSION - 1);
+        let index = SearchIndex::open_at(index_dir.clone(), &notes_dir).unwrap();
+        assert_eq!(title_of(&index, id).as_deref(), Some("fresh"));
+    }
+
+    #[test]
+    fn a_corrupt_index_falls_back_to_a_full_scan() {
+        let temp = tempfile::tempdir().unwrap();
+        let (notes_dir, index_dir) = (temp.path().join("notes"), temp.path().join("index"));
+        let (id, _) = write_note(&notes_dir, "survivor", "still here");
+        fs::create_dir_all(&index_dir).unwrap();
+        fs::write(index_dir.join(INDEX_FILE), "{ not json").unwrap();
+
+        let index = SearchIndex::open_at(index_dir.clone(), &notes_dir).unwrap();
+        assert_eq!(title_of(&index, id).as_deref(), Some("survivor"));
+
+        // and the broken file is replaced
+        let saved: SearchIndex = serde_json::from_str(&fs::read_to_string(index_dir.join(INDEX_FILE)).unwrap()).unwrap();
+        assert_eq!(saved.len(), 1);
+    }
+}
@@ -0,0 +1,273 @@
+use console::Style;
+use regex::Regex;
+use uuid::Uuid;
+
+use crate::models::Note;
+use crate::repository::{FileRepository, NoteRepository};
+use crate::store::StoreError;
+
+// shortest id prefix we accept in [[...]] links, anything shorter is too likely to collide
+const MIN_ID_PREFIX_LEN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    Found(Uuid),
+    Ambiguous,
+    Missing,
+}
+
+pub fn extract_links(content: &str) -> Vec<String> {
+    let link_regex = Regex::new(r"\[\[([^\]]+)\]\]").unwrap();
+    let mut links: Vec<String> = Vec::new();
+
+    for cap in link_regex.captures_iter(content) {
+        // [[target|label]] links to target
+        let target = cap[1].split('|').next().unwrap_or("").trim().to_string();
+        if !target.is_empty() && !links.contains(&target) {
+            links.push(target);
+        }
+    }
+
+    links
+}
+
+// resolves a [[target]] against a set of notes. a full uuid wins, then an
+// exact (case-insensitive) title, then a unique id prefix
+pub fn resolve_target<'a, I>(target: &str, notes: I) -> LinkTarget
+where
+    I: IntoIterator<Item = &'a Note>,
+{
+    let target = target.trim();
+    let notes: Vec<&Note> = notes.into_iter().collect();
+
+    if let Ok(id) = Uuid::parse_str(target) {
+        return if notes.iter().any(|note| note.id == id) {
+            LinkTarget::Found(id)
+        } else {
+            LinkTarget::Missing
+        };
+    }
+
+    let target_lower = target.to_lowercase();
+    let title_matches: Vec<Uuid> = notes.iter()
+        .filter(|note| note.title.as_ref().is_some_and(|title| title.to_lowercase() == target_lower))
+        .map(|note| note.id)
+        .collect();
+
+    match title_matches.len() {
+        1 => return LinkTarget::Found(title_matches[0]),
+        0 => {}
+        _ => return LinkTarget::Ambiguous,
+    }
+
+    let looks_like_prefix = target_lower.len() >= MIN_ID_PREFIX_LEN
+        && target_lower.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+
+    if looks_like_prefix {
+        let prefix_matches: Vec<Uuid> = notes.iter()
+            .filter(|note| note.id.to_string().starts_with(&target_lower))
+            .map(|note| note.id)
+            .collect();
+
+        match prefix_matches.len() {
+            1 => return LinkTarget::Found(prefix_matches[0]),
+            0 => {}
+            _ => return LinkTarget::Ambiguous,
+        }
+    }
+
+    LinkTarget::Missing
+}
+
+// recomputes links_to and unresolved_links from the note's content
+pub fn resolve_note_links(note: &mut Note, notes: &[Note]) {
+    let mut links_to = Vec::new();
+    let mut unresolved_links = Vec::new();
+
+    for link in extract_links(&note.content) {
+        let candidates = notes.iter().filter(|other| other.id != note.id).chain(std::iter::once(&*note));
+        match resolve_target(&link, candidates) {
+            LinkTarget::Found(id) if !links_to.contains(&id) => links_to.push(id),
+            LinkTarget::Found(_) => {}
+            LinkTarget::Ambiguous | LinkTarget::Missing => unresolved_links.push(link),
+        }
+    }
+
+    note.links_to = links_to;
+    note.unresolved_links = unresolved_links;
+}
+
+// moves any of `note`'s unresolved links that now point at `target` into links_to.
+// returns true if anything changed
+pub fn resolve_pending_links(note: &mut Note, target: &Note) -> bool {
+    let before = note.unresolved_links.len();
+    let mut resolved = Vec::new();
+
+    note.unresolved_links.retain(|link| {
+        match resolve_target(link, std::iter::once(target)) {
+            LinkTarget::Found(id) => {
+                resolved.push(id);
+                false
+            }
+            _ => true,
+        }
+    });
+
+    for id in resolved {
+        if !note.links_to.contains(&id) {
+            note.links_to.push(id);
+        }
+    }
+
+    note.unresolved_links.len() != before
+}
+
+// unresolved links are re-checked too, so notes written before their target existed still count.
+// notes in the trash don't link anywhere
+pub fn backlinks(notes: &[Note], id: Uuid) -> Vec<&Note> {
+    notes.iter()
+        .filter(|note| note.id != id && !note.is_deleted())
+        .filter(|note| {
+            note.links_to.contains(&id)
+                || note.unresolved_links.iter().any(|link| resolve_target(link, notes) == LinkTarget::Found(id))
+        })
+        .collect()
+}
+
+pub fn find_note<'a>(notes: &'a [Note], query: &str) -> Result<&'a Note, StoreError> {
+    match resolve_target(query, notes) {
+        LinkTarget::Found(id) => Ok(notes.iter().find(|note| note.id == id).unwrap()),
+        LinkTarget::Ambiguous => Err(StoreError::AmbiguousNote(query.to_string())),
+        LinkTarget::Missing => Err(StoreError::NoMatchingNote(query.to_string())),
+    }
+}
+
+pub fn show_links(query: &str) -> Result<(), StoreError> {
+    let mut repository = FileRepository::open()?;
+    let notes = repository.load()?.notes;
+    let note = find_note(&notes, query)?;
+    let dim_style = Style::new().dim();
+
+    print_note_heading("links from", note);
+
+    if note.links_to.is_empty() && note.unresolved_links.is_empty() {
+        println!("{}", dim_style.apply_to("no outgoing links"));
+        return Ok(());
+    }
+
+    for id in &note.links_to {
+        match notes.iter().find(|other| other.id == *id) {
+            Some(linked) => print_note_line(linked),
+            None => println!("  {}", dim_style.apply_to(format!("{} (missing note)", short_id(*id)))),
+        }
+    }
+
+    for link in &note.unresolved_links {
+        match resolve_target(link, &notes) {
+            LinkTarget::Found(id) => print_note_line(notes.iter().find(|other| other.id == id).unwrap()),
+            _ => println!("  {}", dim_style.apply_to(format!("[[{}]] (unresolved)", link))),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn show_backlinks(query: &str) -> Result<(), StoreError> {
+    let mut repository = FileRepository::open()?;
+    let notes = repository.load()?.notes;
+    let note = find_note(&notes, query)?;
+    let linking = backlinks(&notes, note.id);
+
+    print_note_heading("backlinks to", note);
+
+    if linking.is_empty() {
+        println!("{}", Style::new().dim().apply_to("no notes link here"));
+    }
+
+    for other in linking {
+        print_note_line(other);
+    }
+
+    Ok(())
+}
+
+pub fn short_id(id: Uuid) -> String {
+    id.to_string()[..8].to_string()
+}
+
+fn print_note_heading(label: &str, note: &Note) {
+    let title_style = Style::new().bold().cyan();
+
+    println!("\n{} {}", label, title_style.apply_to(note.title.as_deref().unwrap_or("untitled")));
+    println!("{}", "─".repeat(50));
+}
+
+fn print_note_line(note: &Note) {
+    let title_style = Style::new().bold();
+    let id_style = Style::new().dim();
+
+    println!("  {} {}",
+        title_style.apply_to(note.title.as_deref().unwrap_or("untitled")),
+        id_style.apply_to(short_id(note.id))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_note;
+
+    fn with_id(id: &str, title: &str, content: &str) -> Note {
+        let mut note = test_note(title, content);
+        note.id = Uuid::parse_str(id).unwrap();
+        note
+    }
+
+    #[test]
+    fn a_full_id_beats_a_title_which_beats_a_prefix() {
+        let target = with_id("abcd1234-0000-4000-8000-000000000001", "Roadmap", "");
+        let titled_like_a_prefix = with_id("99990000-0000-4000-8000-000000000002", "abcd12", "");
+        let titled_like_an_id = with_id("99990000-0000-4000-8000-000000000003", "abcd1234-0000-4000-8000-000000000001", "");
+        let notes = vec![target.clone(), titled_like_a_prefix.clone(), titled_like_an_id];
+
+        assert_eq!(resolve_target("abcd1234-0000-4000-8000-000000000001", &notes), LinkTarget::Found(target.id));
+        assert_eq!(resolve_target(" roadmap ", &notes), LinkTarget::Found(target.id));
+        assert_eq!(resolve_target("abcd12", &notes), LinkTarget::Found(titled_like_a_prefix.id));
+        assert_eq!(resolve_target("abcd123", &notes), LinkTarget::Found(target.id));
+        assert_eq!(resolve_target("abcd1234-0000-4000-8000-00000000ffff", &notes), LinkTarget::Missing);
+    }
+
+    #[test]
+    fn ambiguous_and_short_prefixes_are_rejected() {
+        let notes = vec![
+            with_id("abcd1234-0000-4000-8000-000000000001", "one", ""),
+            with_id("abcd5678-0000-4000-8000-000000000002", "two", ""),
+            with_id("fedc0000-0000-4000-8000-000000000003", "same", ""),
+            with_id("0123ffff-0000-4000-8000-000000000004", "same", ""),
+        ];
+
+        assert_eq!(resolve_target("abcd", &notes), LinkTarget::Ambiguous);
+        assert_eq!(resolve_target("abcd5", &notes), LinkTarget::Found(notes[1].id));
+        assert_eq!(resolve_target("same", &notes), LinkTarget::Ambiguous);
+
+        // fewer than four characters never count as a prefix, even a unique one
+        assert_eq!(resolve_target("fed", &notes), LinkTarget::Missing);
+        assert_eq!(resolve_target("fedc", &notes), LinkTarget::Found(notes[2].id));
+    }
+
+    #[test]
+    fn backlinks_skip_trashed_notes() {
+        let target = test_note("target", "");
+        let mut linked = test_note("linked", "see [[target]]");
+        linked.links_to = vec![target.id];
+        let mut pending = test_note("pending", "written before [[Target]] existed");
+        pending.unresolved_links = vec!["Target".to_string()];
+        let mut trashed = linked.clone();
+        trashed.id = Uuid::new_v4();
+        trashed.deleted_at = Some(chrono::Utc::now());
+        let notes = vec![target.clone(), linked.clone(), pending.clone(), trashed];
+
+        let ids: Vec<Uuid> = backlinks(&notes, target.id).into_iter().map(|note| note.id).collect();
+        assert_eq!(ids, vec![linked.id, pending.id]);
+    }
+}
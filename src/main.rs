@@ -4,22 +4,55 @@ mod models;
 mod tui;
 mod config;
 mod ai;
+mod vault;
+mod index;
+mod repository;
+mod links;
+mod trash;
+mod history;
+mod hunks;
+mod git;
+mod query;
+mod dates;
+mod ranking;
+mod tags;
+mod retag;
+mod provider;
+mod natural;
+mod ask;
+mod enrich;
+mod region;
+mod prompts;
+mod privacy;
 
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, GitCommands, PrivacyCommands, ProviderCommands, RetagCommands, TrashCommands, VaultCommands};
+use ranking::SortOrder;
+use retag::Retag;
+use tags::MarkerKind;
 use console::Style;
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
 
+    if let Err(e) = vault::init(cli.vault.as_deref()) {
+        eprintln!("vault error: {}", e);
+        return;
+    }
+
     match cli.command {
         None => {
             if let Err(e) = tui::run_tui() {
                 eprintln!("tui error: {}", e);
             }
         },
-        Some(Commands::Add { content, title }) => {
+        Some(Commands::Add { content, title, enrich: true }) => {
+            if let Err(e) = enrich::add(content, title).await {
+                eprintln!("error saving note: {}", e);
+            }
+        },
+        Some(Commands::Add { content, title, enrich: false }) => {
             match store::save_quick_note(content, title) {
                 Ok(()) => println!("note saved successfully"),
                 Err(e) => eprintln!("error saving note: {}", e),
@@ -30,7 +63,7 @@ async fn main() {
                 eprintln!("tui error: {}", e);
             }
         },
-        Some(Commands::Search { query, tags, projects, list_tags, list_projects, case_sensitive }) => {
+        Some(Commands::Search { query, tags, projects, list_tags, list_projects, case_sensitive, limit, since, until, sort, fuzzy, no_recency, json, ndjson, format, paths_only }) => {
             let search_options = store::SearchOptions {
                 query,
                 filter_tags: tags,
@@ -38,50 +71,220 @@ async fn main() {
                 list_tags,
                 list_projects,
                 case_sensitive,
+                limit,
+                since,
+                until,
+                sort,
+                fuzzy,
+                recency: !no_recency,
             };
 
-            if let Err(e) = store::search_notes_advanced(search_options) {
+            let output = if json {
+                store::SearchOutput::Json
+            } else if ndjson {
+                store::SearchOutput::Ndjson
+            } else if paths_only {
+                store::SearchOutput::Paths
+            } else if let Some(template) = format {
+                store::SearchOutput::Template(template)
+            } else {
+                store::SearchOutput::for_stdout()
+            };
+
+            if let Err(e) = store::search_notes_advanced(search_options, output) {
                 eprintln!("search error: {}", e);
             }
         },
-        Some(Commands::Ai { query }) => {
-            if let Err(e) = ai_search_cli(&query).await {
+        Some(Commands::Ai { query, local }) => {
+            if let Err(e) = ai_search_cli(&query, local).await {
                 eprintln!("ai search error: {}", e);
             }
         },
+        Some(Commands::Enrich { query, limit, dry_run, preview }) => {
+            if let Err(e) = enrich::run(query, limit, dry_run, preview).await {
+                eprintln!("enrich error: {}", e);
+            }
+        },
+        Some(Commands::Ask { question, budget, sources, preview }) => {
+            if let Err(e) = ask::run(&question, budget, sources, preview).await {
+                eprintln!("ask error: {}", e);
+            }
+        },
+        Some(Commands::AiRun { prompt, note, apply, preview }) => {
+            if let Err(e) = prompts::run(&prompt, &note, apply, preview).await {
+                eprintln!("ai-run error: {}", e);
+            }
+        },
+        Some(Commands::Prompts) => {
+            if let Err(e) = prompts::list() {
+                eprintln!("prompts error: {}", e);
+            }
+        },
+        Some(Commands::Privacy { action }) => {
+            let result = match action {
+                PrivacyCommands::Show => privacy::show(),
+                PrivacyCommands::Block { markers } => privacy::block(&markers),
+                PrivacyCommands::Unblock { markers } => privacy::unblock(&markers),
+                PrivacyCommands::Redact { pattern, remove } => privacy::redact(&pattern, remove),
+                PrivacyCommands::Preview { off } => privacy::set_preview(!off),
+                PrivacyCommands::Log { limit } => privacy::show_log(limit),
+            };
+
+            if let Err(e) = result {
+                eprintln!("privacy error: {}", e);
+            }
+        },
+        Some(Commands::Vault { action }) => {
+            let result = match action {
+                VaultCommands::List => vault::list_vaults(),
+                VaultCommands::Add { name, path } => vault::add_vault(&name, &path),
+                VaultCommands::Use { name } => vault::use_vault(&name),
+            };
+
+            if let Err(e) = result {
+                eprintln!("vault error: {}", e);
+            }
+        },
+        Some(Commands::Provider { action }) => {
+            if let Err(e) = run_provider(action) {
+                eprintln!("provider error: {}", e);
+            }
+        },
+        Some(Commands::Reindex) => {
+            if let Err(e) = store::rebuild_index() {
+                eprintln!("reindex error: {}", e);
+            }
+        },
+        Some(Commands::Links { note }) => {
+            if let Err(e) = links::show_links(&note) {
+                eprintln!("links error: {}", e);
+            }
+        },
+        Some(Commands::Backlinks { note }) => {
+            if let Err(e) = links::show_backlinks(&note) {
+                eprintln!("backlinks error: {}", e);
+            }
+        },
+        Some(Commands::History { note }) => {
+            if let Err(e) = history::show_history(&note) {
+                eprintln!("history error: {}", e);
+            }
+        },
+        Some(Commands::Diff { note, rev }) => {
+            if let Err(e) = history::show_diff(&note, rev) {
+                eprintln!("diff error: {}", e);
+            }
+        },
+        Some(Commands::Revert { note, rev }) => {
+            if let Err(e) = history::revert(&note, rev) {
+                eprintln!("revert error: {}", e);
+            }
+        },
+        Some(Commands::Git { action }) => {
+            let result = match action {
+                GitCommands::Enable { remote, branch } => git::enable(remote, branch),
+                GitCommands::Disable => git::disable(),
+            };
+
+            if let Err(e) = result {
+                eprintln!("git error: {}", e);
+            }
+        },
+        Some(Commands::Sync) => {
+            if let Err(e) = git::sync() {
+                eprintln!("sync error: {}", e);
+            }
+        },
+        Some(Commands::Trash { action }) => {
+            let result = match action {
+                TrashCommands::List => trash::list_trash(),
+                TrashCommands::Restore { id } => trash::restore_from_trash(&id),
+                TrashCommands::Empty { older_than } => trash::empty_trash(older_than.as_deref()),
+            };
+
+            if let Err(e) = result {
+                eprintln!("trash error: {}", e);
+            }
+        },
+        Some(Commands::Tags { action }) => {
+            if let Err(e) = run_retag(MarkerKind::Tag, action) {
+                eprintln!("tags error: {}", e);
+            }
+        },
+        Some(Commands::Projects { action }) => {
+            if let Err(e) = run_retag(MarkerKind::Project, action) {
+                eprintln!("projects error: {}", e);
+            }
+        },
     }
 }
 
-async fn ai_search_cli(natural_query: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let ai_client = match ai::AiClient::new() {
-        Ok(client) => client,
-        Err(e) => {
-            eprintln!("failed to initialize ai client: {}", e);
-            eprintln!("please run 'stash ui' and press 's' to configure your openai api key");
-            return Ok(());
-        }
-    };
-
-    if !ai_client.is_configured() {
-        eprintln!("openai api key not configured");
-        eprintln!("please run 'stash ui' and press 's' to configure your api key");
-        return Ok(());
+fn run_retag(kind: MarkerKind, action: RetagCommands) -> Result<(), store::StoreError> {
+    match action {
+        RetagCommands::Rename { old, new, dry_run } => retag::run(Retag::rename(kind, &old, &new)?, dry_run),
+        RetagCommands::Merge { sources, into, dry_run } => retag::run(Retag::merge(kind, &sources, &into)?, dry_run),
+        RetagCommands::Delete { name, dry_run } => retag::run(Retag::delete(kind, &name)?, dry_run),
     }
+}
 
+// the search arguments for a natural language query and which parser made
+// them. the ai provider is asked first, the offline parser steps in when ai
+// isn't set up, fails or isn't wanted
+async fn translate_query(natural_query: &str, local: bool) -> Result<Option<(String, String)>, store::StoreError> {
     let loading_style = Style::new().bold().cyan();
-    let success_style = Style::new().bold().green();
+    let warning_style = Style::new().yellow();
 
-    println!("{} translating your query with ai...", loading_style.apply_to("🤖"));
+    if !local {
+        match ai::AiClient::new() {
+            Ok(ai_client) if ai_client.is_configured() => {
+                println!("{} translating your query with {}...", loading_style.apply_to("🤖"), ai_client.model());
 
-    let search_args = match ai_client.parse_natural_command(&natural_query).await {
-        Ok(args) => args,
-        Err(e) => {
-            eprintln!("failed to translate query: {}", e);
-            return Ok(());
+                match ai_client.parse_natural_command(natural_query).await {
+                    Ok(args) => return Ok(Some((args, format!("ai, {}", ai_client.model())))),
+                    Err(e) => eprintln!("{} ai couldn't translate the query ({}), using the local parser", warning_style.apply_to("!"), e),
+                }
+            }
+            Ok(_) => eprintln!("{} no ai provider is set up, using the local parser", warning_style.apply_to("!")),
+            Err(e) => eprintln!("{} ai isn't available ({}), using the local parser", warning_style.apply_to("!"), e),
         }
+    }
+
+    let vocabulary = store::vocabulary()?;
+    Ok(natural::translate(natural_query, &vocabulary).map(|args| (args, "local parser".to_string())))
+}
+
+fn run_provider(action: ProviderCommands) -> Result<(), config::ConfigError> {
+    match action {
+        ProviderCommands::List => provider::list_providers(),
+        ProviderCommands::Add { name, base_url, model, api_key, headers, temperature, timeout, mock, response } => {
+            let defaults = if mock { config::ProviderConfig::mock() } else { config::ProviderConfig::default() };
+            let settings = config::ProviderConfig {
+                base_url: base_url.unwrap_or(defaults.base_url),
+                model: model.unwrap_or(defaults.model),
+                api_key,
+                headers: headers.iter().map(|header| provider::parse_header(header)).collect::<Result<_, _>>()?,
+                temperature,
+                timeout_secs: timeout,
+                response,
+                ..defaults
+            };
+            provider::add_provider(&name, settings)
+        }
+        ProviderCommands::Use { name } => provider::use_provider(&name),
+        ProviderCommands::Remove { name } => provider::remove_provider(&name),
+    }
+}
+
+async fn ai_search_cli(natural_query: &str, local: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let success_style = Style::new().bold().green();
+
+    let Some((search_args, parser)) = translate_query(natural_query, local).await? else {
+        eprintln!("couldn't make a search out of that");
+        eprintln!("try 'stash search' with words, #tags, +projects and created: dates");
+        return Ok(());
     };
 
-    println!("{} generated search: {}", success_style.apply_to("✓"), search_args);
+    println!("{} generated search ({}): {}", success_style.apply_to("✓"), parser, search_args);
     println!();
 
     let search_options = store::SearchOptions {
@@ -91,9 +294,15 @@ async fn ai_search_cli(natural_query: &str) -> Result<(), Box<dyn std::error::Er
         list_tags: false,
         list_projects: false,
         case_sensitive: false,
+        limit: None,
+        since: None,
+        until: None,
+        sort: SortOrder::Score,
+        fuzzy: false,
+        recency: true,
     };
 
-    if let Err(e) = store::search_notes_advanced(search_options) {
+    if let Err(e) = store::search_notes_advanced(search_options, store::SearchOutput::for_stdout()) {
         eprintln!("search error: {}", e);
     }
 
@@ -5,16 +5,30 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use thiserror::Error;
 
+use crate::tags;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Note {
     pub id: Uuid,
     pub title: Option<String>,
+    // every tag and project on the note: the explicit ones plus whatever the
+    // content mentions inline. kept up to date by merge_metadata
     pub tags: Vec<String>,
     #[serde(default)]
     pub projects: Vec<String>,
+    // assigned directly rather than written in the content, so they survive edits
+    #[serde(default)]
+    pub explicit_tags: Vec<String>,
+    #[serde(default)]
+    pub explicit_projects: Vec<String>,
+    #[serde(default)]
     pub links_to: Vec<Uuid>,
+    #[serde(default)]
+    pub unresolved_links: Vec<String>,
     pub created: DateTime<Utc>,
     pub updated: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
     pub source: NoteSource,
     pub content: String,
 }
@@ -26,9 +40,22 @@ struct NoteFrontMatter {
     pub tags: Vec<String>,
     #[serde(default)]
     pub projects: Vec<String>,
-    pub links_to: Vec<Uuid>,
+    // the tags and projects above that are only there because the content
+    // mentions them. anything else was assigned by hand, here or in another
+    // editor. missing in notes written before the two were told apart
+    #[serde(default)]
+    pub inline_tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub inline_projects: Option<Vec<String>>,
+    // older versions wrote raw [[titles]] here, so accept anything and sort it out on load
+    #[serde(default)]
+    pub links_to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unresolved_links: Vec<String>,
     pub created: DateTime<Utc>,
     pub updated: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
     pub source: NoteSource,
 }
 
@@ -52,6 +79,34 @@ pub enum NoteError {
 }
 
 impl Note {
+    // older versions marked soft-deleted notes with this tag instead of deleted_at
+    pub const DELETED_TAG: &'static str = "deleted";
+
+    // only an explicit legacy tag counts, `#deleted` in the text is just a word
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some() || self.explicit_tags.iter().any(|tag| tag == Self::DELETED_TAG)
+    }
+
+    // when the note went to the trash. legacy tagged notes fall back to their last update
+    pub fn trashed_at(&self) -> Option<DateTime<Utc>> {
+        if let Some(deleted_at) = self.deleted_at {
+            return Some(deleted_at);
+        }
+
+        if self.is_deleted() {
+            return Some(self.updated.unwrap_or(self.created));
+        }
+
+        None
+    }
+
+    // recomputes tags and projects from the explicit ones and the content.
+    // call after changing either
+    pub fn merge_metadata(&mut self) {
+        self.tags = merged(&self.explicit_tags, &tags::extract_tags(&self.content));
+        self.projects = merged(&self.explicit_projects, &tags::extract_projects(&self.content));
+    }
+
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Note, NoteError> {
         let content = fs::read_to_string(path)?;
         Self::from_markdown_string(&content)
@@ -62,30 +117,55 @@ impl Note {
 
         let frontmatter: NoteFrontMatter = serde_yaml::from_str(&frontmatter_str)?;
 
-        let note = Note {
+        let mut links_to = Vec::new();
+        let mut unresolved_links = frontmatter.unresolved_links;
+        for link in frontmatter.links_to {
+            match Uuid::parse_str(&link) {
+                Ok(id) => links_to.push(id),
+                Err(_) if !unresolved_links.contains(&link) => unresolved_links.push(link),
+                Err(_) => {}
+            }
+        }
+
+        let explicit_tags = explicit_entries(frontmatter.tags, frontmatter.inline_tags, || tags::extract_tags(markdown_content));
+        let explicit_projects = explicit_entries(frontmatter.projects, frontmatter.inline_projects, || tags::extract_projects(markdown_content));
+
+        let mut note = Note {
             id: frontmatter.id,
             title: frontmatter.title,
-            tags: frontmatter.tags,
-            projects: frontmatter.projects,
-            links_to: frontmatter.links_to,
+            tags: Vec::new(),
+            projects: Vec::new(),
+            explicit_tags,
+            explicit_projects,
+            links_to,
+            unresolved_links,
             created: frontmatter.created,
             updated: frontmatter.updated,
+            deleted_at: frontmatter.deleted_at,
             source: frontmatter.source,
             content: markdown_content.to_string(),
         };
+        note.merge_metadata();
 
         Ok(note)
     }
 
     pub fn to_markdown_string(&self) -> Result<String, NoteError> {
+        let inline_tags = tags::extract_tags(&self.content);
+        let inline_projects = tags::extract_projects(&self.content);
+
         let frontmatter = NoteFrontMatter {
             id: self.id,
             title: self.title.clone(),
-            tags: self.tags.clone(),
-            projects: self.projects.clone(),
-            links_to: self.links_to.clone(),
+            tags: merged(&self.explicit_tags, &inline_tags),
+            projects: merged(&self.explicit_projects, &inline_projects),
+            inline_tags: Some(inline_only(inline_tags, &self.explicit_tags)),
+            inline_projects: Some(inline_only(inline_projects, &self.explicit_projects)),
+            links_to: self.links_to.iter().map(|id| id.to_string()).collect(),
+            unresolved_links: self.unresolved_links.clone(),
             created: self.created,
             updated: self.updated,
+            deleted_at: self.deleted_at,
             source: self.source.clone(),
         };
 
@@ -102,6 +182,28 @@ impl Note {
     }
 }
 
+// explicit entries first, then inline ones that aren't already there
+fn merged(explicit: &[String], inline: &[String]) -> Vec<String> {
+    let mut all = explicit.to_vec();
+    for entry in inline {
+        if !all.contains(entry) {
+            all.push(entry.clone());
+        }
+    }
+    all
+}
+
+fn inline_only(inline: Vec<String>, explicit: &[String]) -> Vec<String> {
+    inline.into_iter().filter(|entry| !explicit.contains(entry)).collect()
+}
+
+// the saved entries that weren't only there because of the content. for
+// older notes, anything the content doesn't mention counts as explicit
+fn explicit_entries(saved: Vec<String>, inline: Option<Vec<String>>, extract: impl FnOnce() -> Vec<String>) -> Vec<String> {
+    let inline = inline.unwrap_or_else(extract);
+    saved.into_iter().filter(|entry| !inline.contains(entry)).collect()
+}
+
 fn split_frontmatter(content: &str) -> Result<(String, &str), NoteError> {
     if !content.starts_with("---\n") {
         return Err(NoteError::MissingFrontmatter);
@@ -117,4 +219,60 @@ fn split_frontmatter(content: &str) -> Result<(String, &str), NoteError> {
     } else {
         Err(NoteError::InvalidFrontmatter)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_deleted_at_and_the_legacy_tag_trash_a_note() {
+        let inline = Note::from_markdown_string("---\nid: 7d0f5d6e-8a3c-4a4e-9a55-2b8f1c9e0a11\ntitle: cleanup\ntags: [deleted]\ninline_tags: [deleted]\ncreated: 2024-05-01T09:00:00Z\nsource: QuickCapture\n---\nlist what got #deleted this week").unwrap();
+        assert!(!inline.is_deleted());
+
+        let legacy = Note::from_markdown_string("---\nid: 7d0f5d6e-8a3c-4a4e-9a55-2b8f1c9e0a12\ntitle: old\ntags: [deleted]\ncreated: 2024-05-01T09:00:00Z\nsource: QuickCapture\n---\nnothing to see").unwrap();
+        assert!(legacy.is_deleted());
+
+        let mut trashed = inline.clone();
+        trashed.deleted_at = Some(Utc::now());
+        assert!(trashed.is_deleted());
+    }
+
+    #[test]
+    fn legacy_title_links_move_to_unresolved_links() {
+        let markdown = "---\nid: 7d0f5d6e-8a3c-4a4e-9a55-2b8f1c9e0a13\ntitle: hub\ntags: []\nlinks_to:\n- 7d0f5d6e-8a3c-4a4e-9a55-2b8f1c9e0a14\n- Design Review\n- Later\nunresolved_links:\n- Later\ncreated: 2024-05-01T09:00:00Z\nsource: QuickCapture\n---\nsee [[Design Review]] and [[Later]]";
+        let note = Note::from_markdown_string(markdown).unwrap();
+
+        assert_eq!(note.links_to, vec![Uuid::parse_str("7d0f5d6e-8a3c-4a4e-9a55-2b8f1c9e0a14").unwrap()]);
+        assert_eq!(note.unresolved_links, vec!["Later".to_string(), "Design Review".to_string()]);
+
+        // and they are written back as ids only
+        let saved = note.to_markdown_string().unwrap();
+        assert!(saved.contains("links_to:\n- 7d0f5d6e-8a3c-4a4e-9a55-2b8f1c9e0a14\nunresolved_links:\n- Later\n- Design Review\n"));
+    }
+
+    #[test]
+    fn explicit_tags_survive_edits_to_the_content() {
+        let mut note = Note::from_markdown_string("---\nid: 7d0f5d6e-8a3c-4a4e-9a55-2b8f1c9e0a15\ntitle: plan\ntags: [work, rust]\ninline_tags: [rust]\nprojects: [acme]\ninline_projects: []\ncreated: 2024-05-01T09:00:00Z\nsource: QuickCapture\n---\nlearning #rust").unwrap();
+        assert_eq!(note.explicit_tags, vec!["work"]);
+        assert_eq!(note.explicit_projects, vec!["acme"]);
+        assert_eq!(note.tags, vec!["work", "rust"]);
+
+        note.content = "learning #go for +cli".to_string();
+        note.merge_metadata();
+        assert_eq!(note.tags, vec!["work", "go"]);
+        assert_eq!(note.projects, vec!["acme", "cli"]);
+
+        let saved = note.to_markdown_string().unwrap();
+        assert!(saved.contains("tags:\n- work\n- go\n"));
+        assert!(saved.contains("inline_tags:\n- go\n"));
+    }
+
+    #[test]
+    fn older_notes_treat_tags_the_content_never_mentions_as_explicit() {
+        let note = Note::from_markdown_string("---\nid: 7d0f5d6e-8a3c-4a4e-9a55-2b8f1c9e0a16\ntitle: old\ntags: [work, rust]\ncreated: 2024-05-01T09:00:00Z\nsource: QuickCapture\n---\nlearning #rust").unwrap();
+
+        assert_eq!(note.explicit_tags, vec!["work"]);
+        assert_eq!(note.tags, vec!["work", "rust"]);
+    }
+}
@@ -0,0 +1,401 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+
+use crate::dates;
+use crate::query;
+use crate::tags::Vocabulary;
+
+// words that carry no meaning for a search
+const FILLER: &[&str] = &[
+    "a", "all", "an", "and", "any", "are", "at", "be", "been", "but", "can", "could", "did", "do",
+    "does", "entries", "entry", "everything", "explain", "find", "for", "get", "give", "have",
+    "how", "i", "if", "in", "is", "it", "know", "list", "look", "me", "my", "note", "notes", "of",
+    "ones", "our", "please", "related", "search", "should", "show", "so", "some", "something",
+    "stuff", "tell", "that", "the", "there", "they", "thing", "things", "this", "to", "up", "was",
+    "we", "were", "what", "when", "where", "which", "who", "why", "will", "with", "would", "write",
+    "wrote", "written", "created", "made", "may", "might", "you",
+];
+
+// words that point at what the search is about, the words after them are kept
+const TOPIC: &[&str] = &["about", "on", "regarding", "mentioning", "containing", "concerning", "re"];
+
+const NEGATIONS: &[&str] = &["not", "without", "excluding", "exclude", "except", "minus"];
+
+// any of these puts dates on updated: instead of created:
+const UPDATED: &[&str] = &["updated", "changed", "edited", "modified", "touched"];
+
+const MONTHS: &[&str] = &[
+    "january", "february", "march", "april", "may", "june",
+    "july", "august", "september", "october", "november", "december",
+];
+
+// a point or stretch of time as it can sit behind created: or updated:
+#[derive(Debug, Clone, PartialEq)]
+enum When {
+    // a day, month, year or named period, usable with comparisons
+    Period(String),
+    // an age like 7d, meaning "within the last"
+    Within(String),
+}
+
+impl When {
+    fn plain(&self) -> String {
+        match self {
+            When::Period(value) => value.clone(),
+            When::Within(age) => format!("<{}", age),
+        }
+    }
+}
+
+// turns common phrasings into the search grammar without asking a model:
+// "rust notes from last week" becomes `#rust created:last-week` when #rust is
+// a tag in use. None when nothing searchable is left
+pub fn translate(input: &str, vocabulary: &Vocabulary) -> Option<String> {
+    translate_at(input, vocabulary, Local::now())
+}
+
+// like translate, but any of the plain words may match instead of all of
+// them, for finding the notes that might answer a question. tags, projects,
+// dates and exclusions still have to hold
+pub fn translate_loose(input: &str, vocabulary: &Vocabulary) -> Option<String> {
+    let (words, mut filters): (Vec<String>, Vec<String>) = terms(input, vocabulary, Local::now())
+        .into_iter()
+        .filter(|term| term != "OR")
+        .partition(|term| is_plain(term) && !term.starts_with('-'));
+
+    match words.len() {
+        0 => {}
+        1 => filters.extend(words),
+        _ => filters.push(format!("({})", words.join(" OR "))),
+    }
+
+    finish(filters)
+}
+
+fn translate_at(input: &str, vocabulary: &Vocabulary, now: DateTime<Local>) -> Option<String> {
+    finish(terms(input, vocabulary, now))
+}
+
+// the query, as long as there's something in it the search can run
+fn finish(terms: Vec<String>) -> Option<String> {
+    let query = terms.join(" ");
+    if query.is_empty() || query::parse(&query).is_err() {
+        return None;
+    }
+    Some(query)
+}
+
+fn terms(input: &str, vocabulary: &Vocabulary, now: DateTime<Local>) -> Vec<String> {
+    let words = split(input);
+    let field = if words.iter().any(|word| UPDATED.contains(&word.as_str())) { "updated" } else { "created" };
+
+    let mut terms: Vec<String> = Vec::new();
+    let mut negate = false;
+    let mut i = 0;
+
+    while i < words.len() {
+        if let Some((expr, used)) = date_phrase(&words[i..], now) {
+            push(&mut terms, format!("{}:{}", field, expr), &mut negate);
+            i += used;
+            continue;
+        }
+
+        let word = words[i].as_str();
+        i += 1;
+
+        match word {
+            "or" => {
+                if terms.last().is_some_and(|last| last != "OR") {
+                    terms.push("OR".to_string());
+                }
+                negate = false;
+            }
+            _ if NEGATIONS.contains(&word) => negate = true,
+            "tagged" | "tag" | "tags" | "hashtag" | "project" | "projects" => {
+                let sigil = if word.starts_with("project") { '+' } else { '#' };
+                let marker = |name: &str| {
+                    let found = if sigil == '#' { vocabulary.tag(name) } else { vocabulary.project(name) };
+                    format!("{}{}", sigil, found.unwrap_or(name))
+                };
+
+                // "tagged rust" and "project stash", or else "rust tag" and "the stash project"
+                while words.get(i).is_some_and(|next| matches!(next.as_str(), "with" | "as" | "the" | "a")) {
+                    i += 1;
+                }
+                match words.get(i).filter(|next| is_content(next)) {
+                    Some(next) => {
+                        i += 1;
+                        push(&mut terms, marker(next), &mut negate);
+                    }
+                    None => {
+                        if let Some(last) = terms.last_mut().filter(|last| is_plain(last)) {
+                            let (prefix, name) = match last.strip_prefix('-') {
+                                Some(name) => ("-", name.to_string()),
+                                None => ("", last.clone()),
+                            };
+                            *last = format!("{}{}", prefix, marker(&name));
+                        }
+                    }
+                }
+            }
+            "titled" | "called" | "named" => {
+                if let Some(next) = words.get(i) {
+                    i += 1;
+                    push(&mut terms, format!("title:{}", next), &mut negate);
+                }
+            }
+            "recent" | "recently" | "lately" => {
+                push(&mut terms, format!("{}:<7d", field), &mut negate);
+            }
+            _ if TOPIC.contains(&word) || FILLER.contains(&word) || UPDATED.contains(&word) => {}
+            _ => push(&mut terms, term_for(word, vocabulary), &mut negate),
+        }
+    }
+
+    while terms.last().is_some_and(|last| last == "OR") {
+        terms.pop();
+    }
+
+    terms
+}
+
+// words in lowercase, with punctuation trimmed off. quoted phrases stay whole
+fn split(input: &str) -> Vec<String> {
+    let mut words = Vec::new();
+
+    for (i, part) in input.split('"').enumerate() {
+        if i % 2 == 1 {
+            if !part.trim().is_empty() {
+                words.push(format!("\"{}\"", part.trim()));
+            }
+            continue;
+        }
+
+        for word in part.split_whitespace() {
+            let word = word
+                .trim_matches(|c: char| !c.is_alphanumeric() && !matches!(c, '#' | '+' | '-' | '/' | '\''))
+                .trim_end_matches(['-', '/'])
+                .to_lowercase();
+            // "rust's" is rust, "what's" and "i've" say nothing
+            let word = word.strip_suffix("'s").unwrap_or(&word);
+            if !word.is_empty() && !word.contains('\'') {
+                words.push(word.to_string());
+            }
+        }
+    }
+
+    words
+}
+
+// the term, with a `-` in front when a "not" came before it
+fn push(terms: &mut Vec<String>, term: String, negate: &mut bool) {
+    terms.push(if std::mem::take(negate) { format!("-{}", term) } else { term });
+}
+
+fn is_content(word: &str) -> bool {
+    !FILLER.contains(&word) && !TOPIC.contains(&word) && !NEGATIONS.contains(&word) && word != "or"
+}
+
+// an ordinary word, or a negated one, that a later "tag" or "project" can claim
+fn is_plain(term: &str) -> bool {
+    let word = term.strip_prefix('-').unwrap_or(term);
+    !word.is_empty() && word != "OR" && word.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/'))
+}
+
+// a tag or project in use wins over plain text, so "rust" searches #rust
+fn term_for(word: &str, vocabulary: &Vocabulary) -> String {
+    if word.starts_with(['#', '+', '"']) || word.contains(':') {
+        return word.to_string();
+    }
+
+    let singular = word.strip_suffix('s').filter(|stem| stem.len() > 2);
+    let candidates = std::iter::once(word).chain(singular);
+
+    for candidate in candidates {
+        if let Some(tag) = vocabulary.tag(candidate) {
+            return format!("#{}", tag);
+        }
+        if let Some(project) = vocabulary.project(candidate) {
+            return format!("+{}", project);
+        }
+    }
+
+    word.to_string()
+}
+
+// a date expression at the start of `words` and how many words it took
+fn date_phrase(words: &[String], now: DateTime<Local>) -> Option<(String, usize)> {
+    let first = words.first()?.as_str();
+
+    match first {
+        "since" | "after" | "before" | "until" | "till" => {
+            let (when, used) = date_value(&words[1..], true, now)?;
+            let expr = match (first, &when) {
+                ("since", When::Period(value)) => dates::since_expr(value),
+                ("until" | "till", When::Period(value)) => dates::until_expr(value),
+                ("after", When::Period(value)) => format!(">{}", value),
+                ("before", When::Period(value)) => format!("<{}", value),
+                ("since" | "after", When::Within(age)) => format!("<{}", age),
+                (_, When::Within(age)) => format!(">{}", age),
+                _ => return None,
+            };
+            Some((expr, used + 1))
+        }
+        "older" if words.get(1).is_some_and(|word| word == "than") => {
+            let (age, used) = age(&words[2..])?;
+            Some((format!(">{}", age), used + 2))
+        }
+        "in" | "from" | "during" | "on" | "over" | "within" | "of" => {
+            let (when, used) = date_value(&words[1..], true, now)?;
+            Some((when.plain(), used + 1))
+        }
+        _ => {
+            let (when, used) = date_value(words, false, now)?;
+            Some((when.plain(), used))
+        }
+    }
+}
+
+// "yesterday", "last week", "the past 3 days", "march 2025", "2 days ago"...
+// bare years and "may" only count after a preposition
+fn date_value(words: &[String], after_preposition: bool, now: DateTime<Local>) -> Option<(When, usize)> {
+    let skip = usize::from(words.first().is_some_and(|word| word == "the"));
+    let words = &words[skip..];
+    let word = |i: usize| words.get(i).map(String::as_str);
+    let today = now.date_naive();
+
+    let (when, used) = match (word(0)?, word(1)) {
+        ("today", _) => (When::Period("today".to_string()), 1),
+        ("yesterday", _) => (When::Period("yesterday".to_string()), 1),
+        ("this" | "last", Some(unit @ ("week" | "month" | "year"))) => {
+            (When::Period(format!("{}-{}", word(0)?, unit)), 2)
+        }
+        ("past" | "last", _) => {
+            // "past week", "last 3 days", "past few weeks"
+            match age(&words[1..]) {
+                Some((age, used)) => (When::Within(age), used + 1),
+                None => {
+                    let (age, used) = age_unit(word(1)?, 1)?;
+                    (When::Within(age), used + 1)
+                }
+            }
+        }
+        (value, _) if dates::parse_range(value, now).is_some() && value.contains('-') && value.starts_with(|c: char| c.is_ascii_digit()) => {
+            (When::Period(value.to_string()), 1)
+        }
+        (value, _) if after_preposition && value.len() == 4 && value.parse::<i32>().is_ok_and(|year| (1970..=2200).contains(&year)) => {
+            (When::Period(value.to_string()), 1)
+        }
+        (name, next) if MONTHS.contains(&name) => {
+            let month = MONTHS.iter().position(|month| *month == name)? as u32 + 1;
+            let year = next.and_then(|next| next.parse::<i32>().ok()).filter(|year| (1970..=2200).contains(year));
+
+            if name == "may" && !after_preposition && year.is_none() {
+                return None;
+            }
+
+            // a month on its own is the most recent one with that name
+            let year = year.unwrap_or(if month <= today.month() { today.year() } else { today.year() - 1 });
+            (When::Period(format!("{}-{:02}", year, month)), if next.is_some_and(|next| next.parse::<i32>().is_ok()) { 2 } else { 1 })
+        }
+        _ => {
+            // "3 days ago" is that day
+            let (count, used) = number(words)?;
+            if !matches!(word(used)?, "day" | "days") || word(used + 1)? != "ago" {
+                return None;
+            }
+            let day = today.checked_sub_signed(Duration::try_days(count)?)?;
+            (When::Period(iso_day(day)), used + 2)
+        }
+    };
+
+    Some((when, used + skip))
+}
+
+fn iso_day(day: NaiveDate) -> String {
+    day.format("%Y-%m-%d").to_string()
+}
+
+// "3 days", "a week", "few hours" as an age like 3d, and the words it took
+fn age(words: &[String]) -> Option<(String, usize)> {
+    let (count, used) = number(words)?;
+    let (age, unit_used) = age_unit(words.get(used)?, count)?;
+    Some((age, used + unit_used))
+}
+
+// None for ages the search grammar can't hold
+fn age_unit(unit: &str, count: i64) -> Option<(String, usize)> {
+    let age = match unit {
+        "hour" | "hours" | "h" => format!("{}h", count),
+        "day" | "days" | "d" => format!("{}d", count),
+        "week" | "weeks" | "w" => format!("{}w", count),
+        "month" | "months" => format!("{}d", count.checked_mul(30)?),
+        "year" | "years" => format!("{}d", count.checked_mul(365)?),
+        _ => return None,
+    };
+    dates::parse_duration(&age)?;
+    Some((age, 1))
+}
+
+// digits, or a small number written out, and the words it took
+fn number(words: &[String]) -> Option<(i64, usize)> {
+    const WORDS: &[&str] = &[
+        "zero", "one", "two", "three", "four", "five", "six",
+        "seven", "eight", "nine", "ten", "eleven", "twelve",
+    ];
+
+    let word = words.first()?.as_str();
+    if let Ok(count) = word.parse::<i64>() {
+        return Some((count, 1));
+    }
+
+    match word {
+        "a" | "an" => Some((1, 1)),
+        "few" => Some((3, 1)),
+        "couple" if words.get(1).is_some_and(|next| next == "of") => Some((2, 2)),
+        "couple" => Some((2, 1)),
+        _ => WORDS.iter().position(|name| *name == word).map(|count| (count as i64, 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_ages_and_days_ago() {
+        let vocabulary = Vocabulary::default();
+        let now = Local::now();
+        let day = iso_day(now.date_naive() - Duration::days(3));
+
+        assert_eq!(translate_at("notes from the last 2 weeks", &vocabulary, now).as_deref(), Some("created:<2w"));
+        assert_eq!(translate_at("notes from 3 days ago", &vocabulary, now), Some(format!("created:{}", day)));
+    }
+
+    #[test]
+    fn huge_numbers_are_not_dates() {
+        let vocabulary = Vocabulary::default();
+        let now = Local::now();
+
+        for input in [
+            "notes from 99999999999999 days ago",
+            "notes from the last 99999999999999999 years",
+            "notes from the last 99999999999 months",
+        ] {
+            let translated = translate_at(input, &vocabulary, now).unwrap_or_default();
+            assert!(!translated.contains("created:"), "{} became {}", input, translated);
+        }
+    }
+
+    #[test]
+    fn words_in_use_become_tags_and_projects() {
+        let vocabulary = Vocabulary {
+            tags: vec!["lang/rust".to_string(), "draft".to_string()],
+            projects: vec!["webapp".to_string()],
+        };
+        let now = Local::now();
+
+        assert_eq!(translate_at("rust notes in webapp but not drafts", &vocabulary, now).as_deref(), Some("#lang/rust +webapp -#draft"));
+        assert_eq!(translate_at("notes tagged go or python I changed last week", &vocabulary, now).as_deref(), Some("#go OR python updated:last-week"));
+        assert_eq!(translate_at("show me all my notes", &vocabulary, now), None);
+    }
+}
@@ -0,0 +1,332 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use chrono::Utc;
+use console::Style;
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use crate::ai::{AiClient, AiError};
+use crate::config::{Config, ConfigError};
+use crate::history;
+use crate::links;
+use crate::repository::{Change, FileRepository, NoteRepository};
+
+// the prompt every note can be rewritten with, shaped by the style picked in
+// the settings screen
+pub const BUILTIN_PROMPT: &str = "rewrite";
+
+#[derive(Error, Debug)]
+pub enum PromptError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("invalid prompt '{name}': {reason}")]
+    Invalid { name: String, reason: String },
+    #[error("no prompt named '{0}', run 'stash prompts' to see them")]
+    UnknownPrompt(String),
+}
+
+// a named set of instructions for the model, from a markdown file under
+// ~/.stash/prompts/. the file name is the prompt's name
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub name: String,
+    pub description: Option<String>,
+    pub instructions: String,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub builtin: bool,
+}
+
+#[derive(Deserialize, Default)]
+struct PromptFrontMatter {
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    provider: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    temperature: Option<f32>,
+}
+
+impl Prompt {
+    pub fn builtin(config: &Config) -> Self {
+        Self {
+            name: BUILTIN_PROMPT.to_string(),
+            description: Some(format!("clean up the note, {} style", config.ai_prompt_style)),
+            instructions: config.get_ai_system_prompt(),
+            provider: None,
+            model: None,
+            temperature: None,
+            builtin: true,
+        }
+    }
+
+    // the file's text, with optional frontmatter for the description and
+    // provider, model and temperature overrides
+    pub fn parse(name: &str, text: &str) -> Result<Self, PromptError> {
+        let invalid = |reason: String| PromptError::Invalid { name: name.to_string(), reason };
+        let text = text.replace("\r\n", "\n");
+
+        let (front, body) = match text.strip_prefix("---\n") {
+            Some(rest) => {
+                let end = if rest.starts_with("---") { Some(0) } else { rest.find("\n---").map(|end| end + 1) };
+                let end = end.ok_or_else(|| invalid("frontmatter is never closed with ---".to_string()))?;
+                let front = match rest[..end].trim() {
+                    "" => PromptFrontMatter::default(),
+                    yaml => serde_yaml::from_str(yaml).map_err(|e| invalid(e.to_string()))?,
+                };
+                (front, rest[end + 3..].to_string())
+            }
+            None => (PromptFrontMatter::default(), text),
+        };
+
+        let instructions = body.trim().to_string();
+        if instructions.is_empty() {
+            return Err(invalid("it has no instructions".to_string()));
+        }
+        if front.temperature.is_some_and(|temperature| !(0.0..=2.0).contains(&temperature)) {
+            return Err(invalid("temperature must be between 0 and 2".to_string()));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            description: front.description,
+            instructions,
+            provider: front.provider,
+            model: front.model,
+            temperature: front.temperature,
+            builtin: false,
+        })
+    }
+
+    // what's different about this prompt, for listings
+    pub fn summary(&self) -> String {
+        let mut parts: Vec<String> = self.description.iter().cloned().collect();
+        if let Some(provider) = &self.provider {
+            parts.push(format!("provider {}", provider));
+        }
+        if let Some(model) = &self.model {
+            parts.push(format!("model {}", model));
+        }
+        if let Some(temperature) = self.temperature {
+            parts.push(format!("temperature {}", temperature));
+        }
+        parts.join(" • ")
+    }
+}
+
+pub fn prompts_dir() -> Result<PathBuf, ConfigError> {
+    Ok(Config::default_stash_dir()?.join("prompts"))
+}
+
+// the built-in rewrite first, then every prompt file by name. files that
+// can't be read are returned as errors next to the ones that can
+pub fn load(config: &Config) -> Result<(Vec<Prompt>, Vec<PromptError>), PromptError> {
+    let mut prompts = Vec::new();
+    let mut errors = Vec::new();
+
+    let dir = prompts_dir()?;
+    if dir.exists() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("md") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            match fs::read_to_string(&path).map_err(PromptError::from).and_then(|text| Prompt::parse(name, &text)) {
+                Ok(prompt) => prompts.push(prompt),
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+
+    prompts.sort_by_key(|prompt| prompt.name.to_lowercase());
+    prompts.insert(0, Prompt::builtin(config));
+    Ok((prompts, errors))
+}
+
+// "meeting-minutes" finds "Meeting minutes.md"
+pub fn find(config: &Config, name: &str) -> Result<Prompt, PromptError> {
+    let (prompts, errors) = load(config)?;
+    pick(prompts, errors, name)
+}
+
+fn pick(prompts: Vec<Prompt>, errors: Vec<PromptError>, name: &str) -> Result<Prompt, PromptError> {
+    let wanted = normalize(name);
+
+    if let Some(prompt) = prompts.into_iter().find(|prompt| normalize(&prompt.name) == wanted) {
+        return Ok(prompt);
+    }
+
+    // a broken file with that name says more than "no such prompt"
+    match errors.into_iter().find(|error| matches!(error, PromptError::Invalid { name, .. } if normalize(name) == wanted)) {
+        Some(error) => Err(error),
+        None => Err(PromptError::UnknownPrompt(name.to_string())),
+    }
+}
+
+fn normalize(name: &str) -> String {
+    let name = name.trim().to_lowercase();
+    name.strip_suffix(".md").unwrap_or(&name).replace(['-', '_'], " ")
+}
+
+pub fn list() -> Result<(), PromptError> {
+    let config = Config::load()?;
+    let name_style = Style::new().bold().cyan();
+    let detail_style = Style::new().dim();
+    let (prompts, errors) = load(&config)?;
+
+    println!("\n{} prompts:", name_style.apply_to("✍️"));
+    println!("{}", "─".repeat(50));
+
+    for prompt in &prompts {
+        println!("  {} {}", name_style.apply_to(&prompt.name), detail_style.apply_to(prompt.summary()));
+    }
+    for error in &errors {
+        eprintln!("  ! {}", error);
+    }
+
+    println!("\nadd your own as markdown files in {}", prompts_dir()?.display());
+    Ok(())
+}
+
+// `stash ai-run <prompt> <note>`: streams the result to the terminal, and
+// saves it over the note with --apply. --preview prints the request instead
+pub async fn run(prompt_name: &str, note_query: &str, apply: bool, preview: bool) -> Result<(), AiError> {
+    let success_style = Style::new().bold().green();
+    let dim_style = Style::new().dim();
+
+    let config = Config::load()?;
+    let prompt = find(&config, prompt_name)?;
+
+    let mut repository = FileRepository::open()?;
+    let notes = repository.load()?.notes;
+    let note = links::find_note(&notes, note_query)?.clone();
+
+    let ai_client = AiClient::for_prompt(&prompt)?;
+    if !ai_client.is_configured() {
+        eprintln!("running prompts needs an ai provider, add an openai key in the tui settings or run 'stash provider add'");
+        return Ok(());
+    }
+
+    let outgoing = ai_client.prompt_request(&prompt, &note, None)?;
+    if preview {
+        println!("{}", ai_client.preview(&outgoing));
+        return Ok(());
+    }
+
+    eprintln!("{}", dim_style.apply_to(format!(
+        "running '{}' on '{}' with {}...",
+        prompt.name,
+        note.title.as_deref().unwrap_or("untitled"),
+        ai_client.model()
+    )));
+
+    // print the answer as it arrives
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let printer = async {
+        let mut stdout = std::io::stdout();
+        while let Some(piece) = rx.recv().await {
+            let _ = write!(stdout, "{}", piece);
+            let _ = stdout.flush();
+        }
+    };
+    let request = async {
+        let tx = tx;
+        ai_client.run_prompt(&prompt, &note, None, &tx).await
+    };
+    let (result, _) = tokio::join!(request, printer);
+    let output = result?;
+    println!();
+
+    if !apply {
+        return Ok(());
+    }
+
+    if output.trim() == note.content.trim() {
+        println!("\n{}", dim_style.apply_to("nothing changed, the note is left as it was"));
+        return Ok(());
+    }
+
+    let (added, removed) = history::line_changes(&note.content, &output);
+    let mut updated = note.clone();
+    updated.content = output;
+    updated.merge_metadata();
+    updated.updated = Some(Utc::now());
+    repository.update_as(&updated, Change::AiRewrite)?;
+
+    println!("\n{} saved over '{}' (+{} -{} lines), 'stash history {}' has the old version",
+        success_style.apply_to("✓"),
+        note.title.as_deref().unwrap_or("untitled"),
+        added,
+        removed,
+        links::short_id(note.id)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reason(result: Result<Prompt, PromptError>) -> String {
+        match result {
+            Err(PromptError::Invalid { reason, .. }) => reason,
+            other => panic!("expected an invalid prompt, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn frontmatter_sets_the_overrides() {
+        let prompt = Prompt::parse("minutes", "---\ndescription: tidy meeting notes\nmodel: gpt-4o\ntemperature: 0.2\n---\n\nTurn this into minutes.\n").unwrap();
+        assert_eq!(prompt.description.as_deref(), Some("tidy meeting notes"));
+        assert_eq!(prompt.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(prompt.provider, None);
+        assert_eq!(prompt.temperature, Some(0.2));
+        assert_eq!(prompt.instructions, "Turn this into minutes.");
+        assert_eq!(prompt.summary(), "tidy meeting notes • model gpt-4o • temperature 0.2");
+    }
+
+    #[test]
+    fn empty_or_missing_frontmatter_is_fine() {
+        let empty = Prompt::parse("plain", "---\n---\nJust do it.").unwrap();
+        assert_eq!(empty.instructions, "Just do it.");
+        assert_eq!(empty.description, None);
+
+        let bare = Prompt::parse("bare", "Summarize.\r\n").unwrap();
+        assert_eq!(bare.instructions, "Summarize.");
+        assert_eq!(bare.temperature, None);
+    }
+
+    #[test]
+    fn broken_prompts_say_why() {
+        assert_eq!(reason(Prompt::parse("hot", "---\ntemperature: 3.5\n---\nx")), "temperature must be between 0 and 2");
+        assert_eq!(reason(Prompt::parse("cold", "---\ntemperature: -0.1\n---\nx")), "temperature must be between 0 and 2");
+        assert_eq!(reason(Prompt::parse("open", "---\nmodel: x\nnever closed")), "frontmatter is never closed with ---");
+        assert_eq!(reason(Prompt::parse("empty", "---\nmodel: x\n---\n  \n")), "it has no instructions");
+    }
+
+    #[test]
+    fn find_ignores_case_separators_and_the_md_suffix() {
+        let prompts = || vec![Prompt::parse("Meeting minutes", "Minutes.").unwrap(), Prompt::parse("fix_typos", "Fix typos.").unwrap()];
+        let found = |name: &str| pick(prompts(), Vec::new(), name).map(|prompt| prompt.name);
+
+        assert_eq!(found("meeting-minutes").unwrap(), "Meeting minutes");
+        assert_eq!(found(" MEETING MINUTES.md ").unwrap(), "Meeting minutes");
+        assert_eq!(found("fix typos").unwrap(), "fix_typos");
+        assert!(matches!(found("summarize"), Err(PromptError::UnknownPrompt(_))));
+
+        // a broken file with the name explains itself instead
+        let broken = Prompt::parse("summarize", "---\ntemperature: 9\n---\nx").unwrap_err();
+        assert!(matches!(pick(prompts(), vec![broken], "Summarize.md"), Err(PromptError::Invalid { .. })));
+    }
+}
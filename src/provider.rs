@@ -0,0 +1,363 @@
+use std::future::Future;
+use std::pin::Pin;
+use console::Style;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::{timeout, Duration};
+
+use crate::ai::AiError;
+use crate::config::{Config, ConfigError, ProviderConfig, ProviderKind, DEFAULT_PROVIDER, MOCK_PROVIDER};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self { role: "system".to_string(), content: content.into() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: "user".to_string(), content: content.into() }
+    }
+}
+
+// one chat completion. temperature and timeout are what the calling feature
+// wants, the provider's own settings win when it has them
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub messages: Vec<ChatMessage>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    pub timeout: Duration,
+}
+
+pub type Completion<'a> = Pin<Box<dyn Future<Output = Result<String, AiError>> + Send + 'a>>;
+
+pub trait Provider: Send + Sync {
+    fn model(&self) -> &str;
+    fn complete<'a>(&'a self, request: &'a ChatRequest) -> Completion<'a>;
+    // like complete, but each piece of the answer is sent to `tokens` as it
+    // arrives. the whole answer is still returned at the end
+    fn stream<'a>(&'a self, request: &'a ChatRequest, tokens: &'a UnboundedSender<String>) -> Completion<'a>;
+    // the json body the request is sent as, for previews
+    fn payload(&self, request: &ChatRequest, stream: bool) -> String;
+}
+
+pub fn from_config(config: ProviderConfig) -> Box<dyn Provider> {
+    match config.kind {
+        ProviderKind::OpenAi => Box::new(OpenAiCompatible::new(config)),
+        ProviderKind::Mock => Box::new(MockProvider::new(config)),
+    }
+}
+
+#[derive(Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [ChatMessage],
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    choices: Vec<CompletionChoice>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChoice {
+    message: CompletionMessage,
+}
+
+#[derive(Deserialize)]
+struct CompletionMessage {
+    content: String,
+}
+
+// one server-sent event of a streamed completion
+#[derive(Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+}
+
+#[derive(Deserialize)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: StreamDelta,
+}
+
+#[derive(Deserialize, Default)]
+struct StreamDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+// the chat completions api, at whatever base url the provider points to
+pub struct OpenAiCompatible {
+    client: Client,
+    config: ProviderConfig,
+}
+
+impl OpenAiCompatible {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self { client: Client::new(), config }
+    }
+
+    fn body<'a>(&'a self, request: &'a ChatRequest, stream: bool) -> CompletionRequest<'a> {
+        CompletionRequest {
+            model: &self.config.model,
+            messages: &request.messages,
+            max_tokens: request.max_tokens,
+            temperature: self.config.temperature.unwrap_or(request.temperature),
+            stream,
+        }
+    }
+
+    async fn post(&self, request: &ChatRequest, stream: bool) -> Result<reqwest::Response, AiError> {
+        let url = format!("{}/chat/completions", self.config.base_url.trim_end_matches('/'));
+        let body = self.body(request, stream);
+
+        let mut builder = self.client.post(&url).json(&body);
+        if let Some(key) = self.config.api_key.as_deref().filter(|key| !key.is_empty()) {
+            builder = builder.bearer_auth(key);
+        }
+        for (name, value) in &self.config.headers {
+            builder = builder.header(name, value);
+        }
+
+        let limit = self.limit(request);
+        let response = timeout(limit, builder.send())
+            .await
+            .map_err(|_| AiError::Timeout(limit.as_secs()))?
+            .map_err(AiError::Http)?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(AiError::Api {
+                status,
+                message: error_text,
+            });
+        }
+
+        Ok(response)
+    }
+
+    fn limit(&self, request: &ChatRequest) -> Duration {
+        self.config.timeout_secs.map_or(request.timeout, Duration::from_secs)
+    }
+
+    async fn send(&self, request: &ChatRequest) -> Result<String, AiError> {
+        let response = self.post(request, false).await?;
+        let completion: CompletionResponse = response.json().await.map_err(AiError::Http)?;
+
+        completion
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content.trim().to_string())
+            .ok_or(AiError::InvalidResponse)
+    }
+
+    // reads the event stream line by line. the timeout applies to each wait
+    // for more data rather than the whole answer, so a long rewrite that keeps
+    // coming isn't cut off
+    async fn send_streaming(&self, request: &ChatRequest, tokens: &UnboundedSender<String>) -> Result<String, AiError> {
+        let mut response = self.post(request, true).await?;
+
+        // a server that ignores `stream` answers in one piece
+        let is_event_stream = response.headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        if !is_event_stream {
+            let completion: CompletionResponse = response.json().await.map_err(AiError::Http)?;
+            let content = completion
+                .choices
+                .into_iter()
+                .next()
+                .map(|choice| choice.message.content.trim().to_string())
+                .ok_or(AiError::InvalidResponse)?;
+            let _ = tokens.send(content.clone());
+            return Ok(content);
+        }
+
+        let limit = self.limit(request);
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut answer = String::new();
+
+        loop {
+            let chunk = timeout(limit, response.chunk())
+                .await
+                .map_err(|_| AiError::Timeout(limit.as_secs()))?
+                .map_err(AiError::Http)?;
+            let Some(chunk) = chunk else {
+                break;
+            };
+            buffer.extend_from_slice(&chunk);
+
+            while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    return Ok(answer.trim().to_string());
+                }
+
+                let event: StreamChunk = serde_json::from_str(data).map_err(|_| AiError::InvalidResponse)?;
+                let piece = event.choices.into_iter().next().and_then(|choice| choice.delta.content);
+                if let Some(piece) = piece.filter(|piece| !piece.is_empty()) {
+                    answer.push_str(&piece);
+                    let _ = tokens.send(piece);
+                }
+            }
+        }
+
+        Ok(answer.trim().to_string())
+    }
+}
+
+impl Provider for OpenAiCompatible {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn complete<'a>(&'a self, request: &'a ChatRequest) -> Completion<'a> {
+        Box::pin(self.send(request))
+    }
+
+    fn stream<'a>(&'a self, request: &'a ChatRequest, tokens: &'a UnboundedSender<String>) -> Completion<'a> {
+        Box::pin(self.send_streaming(request, tokens))
+    }
+
+    fn payload(&self, request: &ChatRequest, stream: bool) -> String {
+        serde_json::to_string_pretty(&self.body(request, stream)).unwrap_or_default()
+    }
+}
+
+// answers straight away without touching the network: the configured
+// response if there is one, otherwise the last thing the user said
+pub struct MockProvider {
+    config: ProviderConfig,
+}
+
+impl MockProvider {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self { config }
+    }
+
+    fn answer(&self, request: &ChatRequest) -> String {
+        if let Some(response) = &self.config.response {
+            return response.clone();
+        }
+
+        request.messages.iter()
+            .rev()
+            .find(|message| message.role == "user")
+            .map(|message| message.content.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Provider for MockProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn complete<'a>(&'a self, request: &'a ChatRequest) -> Completion<'a> {
+        let answer = self.answer(request);
+        Box::pin(async move { Ok(answer) })
+    }
+
+    // word by word, so the streaming path can be tried without a network
+    fn stream<'a>(&'a self, request: &'a ChatRequest, tokens: &'a UnboundedSender<String>) -> Completion<'a> {
+        let answer = self.answer(request);
+        Box::pin(async move {
+            for word in answer.split_inclusive(' ') {
+                let _ = tokens.send(word.to_string());
+            }
+            Ok(answer)
+        })
+    }
+
+    // what an openai compatible provider would be sent, nothing actually is
+    fn payload(&self, request: &ChatRequest, stream: bool) -> String {
+        let body = CompletionRequest {
+            model: &self.config.model,
+            messages: &request.messages,
+            max_tokens: request.max_tokens,
+            temperature: self.config.temperature.unwrap_or(request.temperature),
+            stream,
+        };
+        serde_json::to_string_pretty(&body).unwrap_or_default()
+    }
+}
+
+// "X-Team: notes" or "X-Team=notes" from the command line
+pub fn parse_header(header: &str) -> Result<(String, String), ConfigError> {
+    header
+        .split_once(['=', ':'])
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| ConfigError::InvalidHeader(header.to_string()))
+}
+
+pub fn list_providers() -> Result<(), ConfigError> {
+    let config = Config::load()?;
+    let name_style = Style::new().bold().cyan();
+    let detail_style = Style::new().dim();
+    let active_style = Style::new().bold().green();
+
+    let mut names: Vec<String> = vec![DEFAULT_PROVIDER.to_string(), MOCK_PROVIDER.to_string()];
+    names.extend(config.providers.keys().filter(|name| *name != DEFAULT_PROVIDER && *name != MOCK_PROVIDER).cloned());
+
+    println!("\n{} ai providers:", name_style.apply_to("🤖"));
+    println!("{}", "─".repeat(50));
+
+    for name in names {
+        let provider = config.provider(&name)?;
+        let marker = if name == config.active_provider_name() { active_style.apply_to("*").to_string() } else { " ".to_string() };
+        let detail = match provider.kind {
+            ProviderKind::Mock => "offline, no requests are sent".to_string(),
+            ProviderKind::OpenAi if provider.is_ready() => format!("{} at {}", provider.model, provider.base_url),
+            ProviderKind::OpenAi => format!("{} at {} (no api key)", provider.model, provider.base_url),
+        };
+        println!("{} {} {}", marker, name_style.apply_to(&name), detail_style.apply_to(detail));
+    }
+
+    Ok(())
+}
+
+pub fn add_provider(name: &str, provider: ProviderConfig) -> Result<(), ConfigError> {
+    let mut config = Config::load()?;
+    let summary = match provider.kind {
+        ProviderKind::Mock => "answering offline".to_string(),
+        ProviderKind::OpenAi => format!("{} at {}", provider.model, provider.base_url),
+    };
+    config.add_provider(name.to_string(), provider)?;
+    println!("provider '{}' saved, {}", name, summary);
+    println!("run 'stash provider use {}' to switch to it", name);
+    Ok(())
+}
+
+pub fn use_provider(name: &str) -> Result<(), ConfigError> {
+    let mut config = Config::load()?;
+    config.use_provider(name)?;
+    println!("now using ai provider '{}'", name);
+    Ok(())
+}
+
+pub fn remove_provider(name: &str) -> Result<(), ConfigError> {
+    let mut config = Config::load()?;
+    config.remove_provider(name)?;
+    println!("provider '{}' removed", name);
+    Ok(())
+}
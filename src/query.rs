@@ -0,0 +1,622 @@
+use std::collections::HashSet;
+use std::fmt;
+use chrono::Local;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::dates::{self, DateRange};
+use crate::index::SearchIndex;
+use crate::models::Note;
+use crate::tags;
+
+// search query language:
+//
+//   rust async             both words (AND is implicit)
+//   rust OR go             either word
+//   NOT draft, -draft      without the word
+//   (rust OR go) #cli      grouping
+//   "error handling"       exact phrase
+//   title:rust content:"x" tag:rust project:web
+//   #rust +web             shorthand for tag:rust project:web
+//   created:>2025-01-01    dates, see dates::parse_range for the full syntax
+//   updated:<7d created:2025-03 created:this-week
+//
+// OR binds looser than AND, so `a b OR c` is `(a b) OR c`. keywords must be
+// uppercase; a lowercase "or" is just a word
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{message} at position {position}")]
+pub struct QueryError {
+    // 0-based character offset into the query
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+
+    // the query with a caret under the offending character
+    pub fn pointer(&self, query: &str) -> String {
+        format!("  {}\n  {}^", query, " ".repeat(self.position))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    // title or content
+    Text,
+    Title,
+    Content,
+    Tag,
+    Project,
+}
+
+impl Field {
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "title" => Some(Field::Title),
+            "content" => Some(Field::Content),
+            "tag" => Some(Field::Tag),
+            "project" => Some(Field::Project),
+            _ => None,
+        }
+    }
+
+    pub fn is_text(self) -> bool {
+        matches!(self, Field::Text | Field::Title | Field::Content)
+    }
+}
+
+// how text terms are compared against a note, tags and projects always
+// match by path
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextMatch {
+    pub case_sensitive: bool,
+    // a line only needs the term's letters in order, so "borow chk" still
+    // finds "borrow checker"
+    pub fuzzy: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub field: Field,
+    pub value: String,
+    pub phrase: bool,
+}
+
+impl Term {
+    fn matches(&self, note: &Note, projects: &[String], text: TextMatch) -> bool {
+        match self.field {
+            Field::Text => {
+                note.title.as_deref().is_some_and(|title| contains(title, &self.value, text))
+                    || contains(&note.content, &self.value, text)
+            }
+            Field::Title => note.title.as_deref().is_some_and(|title| contains(title, &self.value, text)),
+            Field::Content => contains(&note.content, &self.value, text),
+            Field::Tag => note.tags.iter().any(|tag| tags::is_within(tag, &self.value)),
+            Field::Project => projects.iter().any(|project| tags::is_within(project, &self.value)),
+        }
+    }
+
+    fn candidates(&self, index: &SearchIndex) -> Option<HashSet<Uuid>> {
+        match self.field {
+            Field::Text | Field::Title | Field::Content => index.text_candidates(&self.value),
+            Field::Tag => Some(index.with_tag(&self.value)),
+            Field::Project => Some(index.with_project(&self.value)),
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field {
+            Field::Text => {}
+            Field::Title => write!(f, "title:")?,
+            Field::Content => write!(f, "content:")?,
+            Field::Tag => write!(f, "tag:")?,
+            Field::Project => write!(f, "project:")?,
+        }
+
+        if self.phrase {
+            write!(f, "\"{}\"", self.value)
+        } else {
+            write!(f, "{}", self.value)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    // notes that were never edited count as updated when they were created
+    Updated,
+}
+
+impl DateField {
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "created" => Some(DateField::Created),
+            "updated" => Some(DateField::Updated),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateFilter {
+    pub field: DateField,
+    pub range: DateRange,
+    // what the user typed after the colon, kept for display
+    expr: String,
+}
+
+impl DateFilter {
+    pub fn parse(field: DateField, expr: &str) -> Option<Self> {
+        Some(Self {
+            field,
+            range: dates::parse_range(expr, Local::now())?,
+            expr: expr.to_string(),
+        })
+    }
+
+    fn matches(&self, note: &Note) -> bool {
+        let at = match self.field {
+            DateField::Created => note.created,
+            DateField::Updated => note.updated.unwrap_or(note.created),
+        };
+        self.range.contains(at)
+    }
+}
+
+impl fmt::Display for DateFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.field {
+            DateField::Created => write!(f, "created:{}", self.expr),
+            DateField::Updated => write!(f, "updated:{}", self.expr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    // the empty query, matches every note
+    All,
+    Term(Term),
+    Date(DateFilter),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+impl Query {
+    pub fn matches(&self, note: &Note, projects: &[String], text: TextMatch) -> bool {
+        match self {
+            Query::All => true,
+            Query::Term(term) => term.matches(note, projects, text),
+            Query::Date(filter) => filter.matches(note),
+            Query::And(queries) => queries.iter().all(|query| query.matches(note, projects, text)),
+            Query::Or(queries) => queries.iter().any(|query| query.matches(note, projects, text)),
+            Query::Not(query) => !query.matches(note, projects, text),
+        }
+    }
+
+    // a superset of the notes that can match, or None when the index can't narrow it down
+    pub fn candidates(&self, index: &SearchIndex) -> Option<HashSet<Uuid>> {
+        match self {
+            Query::All | Query::Date(_) | Query::Not(_) => None,
+            Query::Term(term) => term.candidates(index),
+            Query::And(queries) => queries.iter()
+                .filter_map(|query| query.candidates(index))
+                .reduce(|a, b| a.intersection(&b).copied().collect()),
+            Query::Or(queries) => queries.iter()
+                .map(|query| query.candidates(index))
+                .try_fold(HashSet::new(), |mut all, ids| {
+                    all.extend(ids?);
+                    Some(all)
+                }),
+        }
+    }
+
+    // terms a matching note is expected to contain, i.e. everything not under a NOT
+    pub fn positive_terms(&self) -> Vec<&Term> {
+        let mut terms = Vec::new();
+        self.collect_positive_terms(&mut terms);
+        terms
+    }
+
+    fn collect_positive_terms<'a>(&'a self, terms: &mut Vec<&'a Term>) {
+        match self {
+            Query::All | Query::Date(_) | Query::Not(_) => {}
+            Query::Term(term) => terms.push(term),
+            Query::And(queries) | Query::Or(queries) => {
+                for query in queries {
+                    query.collect_positive_terms(terms);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Query::All => Ok(()),
+            Query::Term(term) => write!(f, "{}", term),
+            Query::Date(filter) => write!(f, "{}", filter),
+            Query::And(queries) => write_joined(f, queries, " "),
+            Query::Or(queries) => write_joined(f, queries, " OR "),
+            Query::Not(query) => match query.as_ref() {
+                Query::And(_) | Query::Or(_) => write!(f, "NOT ({})", query),
+                _ => write!(f, "NOT {}", query),
+            },
+        }
+    }
+}
+
+fn write_joined(f: &mut fmt::Formatter<'_>, queries: &[Query], separator: &str) -> fmt::Result {
+    for (i, query) in queries.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+
+        // an OR inside an AND needs its parentheses back
+        match query {
+            Query::Or(_) if separator == " " => write!(f, "({})", query)?,
+            _ => write!(f, "{}", query)?,
+        }
+    }
+    Ok(())
+}
+
+fn contains(haystack: &str, needle: &str, text: TextMatch) -> bool {
+    let (haystack, needle) = if text.case_sensitive {
+        (haystack.to_string(), needle.to_string())
+    } else {
+        (haystack.to_lowercase(), needle.to_lowercase())
+    };
+
+    if text.fuzzy {
+        let matcher = SkimMatcherV2::default().respect_case();
+        haystack.lines().any(|line| matcher.fuzzy_match(line, &needle).is_some())
+    } else {
+        haystack.contains(&needle)
+    }
+}
+
+pub fn parse(input: &str) -> Result<Query, QueryError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0, end: input.chars().count() };
+
+    if parser.tokens.is_empty() {
+        return Ok(Query::All);
+    }
+
+    let query = parser.parse_or()?;
+
+    match parser.peek() {
+        None => Ok(query),
+        Some((Token::RParen, position)) => Err(QueryError::new(position, "unmatched ')'")),
+        Some((_, position)) => Err(QueryError::new(position, "unexpected token")),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(Term),
+    Date(DateFilter),
+}
+
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '(' => {
+                tokens.push((Token::LParen, i));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::RParen, i));
+                i += 1;
+            }
+            '"' => {
+                let (value, next) = read_phrase(&chars, i)?;
+                tokens.push((Token::Term(Term { field: Field::Text, value, phrase: true }), i));
+                i = next;
+            }
+            // a leading '-' negates whatever follows it, as long as something does
+            '-' if chars.get(i + 1).is_some_and(|next| !next.is_whitespace() && *next != ')') => {
+                tokens.push((Token::Not, i));
+                i += 1;
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+
+                // field:"some phrase"
+                if word.ends_with(':') && chars.get(i) == Some(&'"') {
+                    if let Some(field) = Field::from_prefix(&word[..word.len() - 1]) {
+                        let (value, next) = read_phrase(&chars, i)?;
+                        tokens.push((Token::Term(Term { field, value, phrase: true }), start));
+                        i = next;
+                        continue;
+                    }
+                }
+
+                tokens.push((word_token(&word, start)?, start));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// reads a "quoted phrase" starting at the opening quote
+fn read_phrase(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let close = chars[start + 1..]
+        .iter()
+        .position(|c| *c == '"')
+        .ok_or_else(|| QueryError::new(start, "unterminated quote"))?;
+
+    let value: String = chars[start + 1..start + 1 + close].iter().collect();
+    if value.trim().is_empty() {
+        return Err(QueryError::new(start, "empty phrase"));
+    }
+
+    Ok((value, start + close + 2))
+}
+
+fn word_token(word: &str, position: usize) -> Result<Token, QueryError> {
+    match word {
+        "AND" => return Ok(Token::And),
+        "OR" => return Ok(Token::Or),
+        "NOT" => return Ok(Token::Not),
+        _ => {}
+    }
+
+    let term = |field, value: &str| {
+        if value.is_empty() {
+            Err(QueryError::new(position, format!("missing value after '{}'", &word[..word.len() - value.len()])))
+        } else {
+            Ok(Token::Term(Term { field, value: value.to_string(), phrase: false }))
+        }
+    };
+
+    if let Some(tag) = word.strip_prefix('#') {
+        return term(Field::Tag, tag);
+    }
+
+    if let Some(project) = word.strip_prefix('+') {
+        return term(Field::Project, project);
+    }
+
+    if let Some((prefix, value)) = word.split_once(':') {
+        if let Some(field) = Field::from_prefix(prefix) {
+            return term(field, value);
+        }
+
+        if let Some(field) = DateField::from_prefix(prefix) {
+            return DateFilter::parse(field, value)
+                .map(Token::Date)
+                .ok_or_else(|| QueryError::new(position + prefix.len() + 1, format!("invalid date '{}'", value)));
+        }
+    }
+
+    term(Field::Text, word)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    // position reported for errors at the end of the input
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(Token, usize)> {
+        self.tokens.get(self.pos).cloned()
+    }
+
+    fn advance(&mut self) -> Option<(Token, usize)> {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let mut queries = vec![self.parse_and()?];
+
+        while let Some((Token::Or, _)) = self.peek() {
+            self.advance();
+            queries.push(self.parse_and()?);
+        }
+
+        Ok(if queries.len() == 1 { queries.remove(0) } else { Query::Or(queries) })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, QueryError> {
+        let mut queries = vec![self.parse_unary()?];
+
+        loop {
+            match self.peek() {
+                Some((Token::And, _)) => {
+                    self.advance();
+                    queries.push(self.parse_unary()?);
+                }
+                Some((Token::Term(_) | Token::Date(_) | Token::Not | Token::LParen, _)) => {
+                    queries.push(self.parse_unary()?);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(if queries.len() == 1 { queries.remove(0) } else { Query::And(queries) })
+    }
+
+    fn parse_unary(&mut self) -> Result<Query, QueryError> {
+        if let Some((Token::Not, _)) = self.peek() {
+            self.advance();
+            return Ok(Query::Not(Box::new(self.parse_unary()?)));
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, QueryError> {
+        match self.advance() {
+            Some((Token::Term(term), _)) => Ok(Query::Term(term)),
+            Some((Token::Date(filter), _)) => Ok(Query::Date(filter)),
+            Some((Token::LParen, position)) => {
+                if let Some((Token::RParen, _)) = self.peek() {
+                    return Err(QueryError::new(position, "empty group"));
+                }
+
+                let query = self.parse_or()?;
+                match self.advance() {
+                    Some((Token::RParen, _)) => Ok(query),
+                    _ => Err(QueryError::new(position, "missing ')' for this '('")),
+                }
+            }
+            Some((Token::RParen, position)) => Err(QueryError::new(position, "unmatched ')'")),
+            Some((Token::And | Token::Or, position)) => Err(QueryError::new(position, "expected a search term before this operator")),
+            Some((Token::Not, position)) => Err(QueryError::new(position, "expected a search term after NOT")),
+            None => Err(QueryError::new(self.end, "expected a search term")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_note;
+
+    fn term(field: Field, value: &str) -> Query {
+        Query::Term(Term { field, value: value.to_string(), phrase: false })
+    }
+
+    fn word(value: &str) -> Query {
+        term(Field::Text, value)
+    }
+
+    fn phrase(field: Field, value: &str) -> Query {
+        Query::Term(Term { field, value: value.to_string(), phrase: true })
+    }
+
+    fn error(input: &str) -> (usize, String) {
+        let error = parse(input).unwrap_err();
+        (error.position, error.message)
+    }
+
+    #[test]
+    fn implicit_and_binds_tighter_than_or() {
+        assert_eq!(parse("a b OR c").unwrap(), Query::Or(vec![Query::And(vec![word("a"), word("b")]), word("c")]));
+        assert_eq!(parse("a OR b c").unwrap(), Query::Or(vec![word("a"), Query::And(vec![word("b"), word("c")])]));
+        assert_eq!(parse("a AND b").unwrap(), parse("a b").unwrap());
+
+        // keywords are uppercase only
+        assert_eq!(parse("a or b").unwrap(), Query::And(vec![word("a"), word("or"), word("b")]));
+    }
+
+    #[test]
+    fn not_and_dash_negate_the_next_term() {
+        assert_eq!(parse("NOT draft").unwrap(), Query::Not(Box::new(word("draft"))));
+        assert_eq!(parse("-draft").unwrap(), parse("NOT draft").unwrap());
+        assert_eq!(parse("rust -#draft").unwrap(), Query::And(vec![word("rust"), Query::Not(Box::new(term(Field::Tag, "draft")))]));
+
+        // a dash inside a word is just part of it
+        assert_eq!(parse("well-known").unwrap(), word("well-known"));
+    }
+
+    #[test]
+    fn parentheses_nest() {
+        let query = parse("(a OR (b c)) NOT (d OR e)").unwrap();
+        let expected = Query::And(vec![
+            Query::Or(vec![word("a"), Query::And(vec![word("b"), word("c")])]),
+            Query::Not(Box::new(Query::Or(vec![word("d"), word("e")]))),
+        ]);
+        assert_eq!(query, expected);
+        assert_eq!(query.to_string(), "(a OR b c) NOT (d OR e)");
+    }
+
+    #[test]
+    fn quoted_phrases_keep_their_spaces() {
+        assert_eq!(parse("\"error handling\" rust").unwrap(), Query::And(vec![phrase(Field::Text, "error handling"), word("rust")]));
+        assert_eq!(parse("title:\"big plan\"").unwrap(), phrase(Field::Title, "big plan"));
+
+        let note = test_note("errors", "good error handling matters");
+        assert!(parse("\"error handling\"").unwrap().matches(&note, &[], TextMatch::default()));
+        assert!(!parse("\"handling error\"").unwrap().matches(&note, &[], TextMatch::default()));
+    }
+
+    #[test]
+    fn prefixes_pick_the_field() {
+        let query = parse("title:rust content:borrow tag:lang/rust project:web #cli +api other:thing").unwrap();
+        assert_eq!(query, Query::And(vec![
+            term(Field::Title, "rust"),
+            term(Field::Content, "borrow"),
+            term(Field::Tag, "lang/rust"),
+            term(Field::Project, "web"),
+            term(Field::Tag, "cli"),
+            term(Field::Project, "api"),
+            word("other:thing"),
+        ]));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_character() {
+        assert_eq!(error("a b)"), (3, "unmatched ')'".to_string()));
+        assert_eq!(error("rust \"open"), (5, "unterminated quote".to_string()));
+        assert_eq!(error("rust OR"), (7, "expected a search term".to_string()));
+        assert_eq!(error("OR rust"), (0, "expected a search term before this operator".to_string()));
+        assert_eq!(error("x (a b"), (2, "missing ')' for this '('".to_string()));
+        assert_eq!(error("rust title:"), (5, "missing value after 'title:'".to_string()));
+        assert_eq!(parse("a b)").unwrap_err().pointer("a b)"), "  a b)\n     ^");
+    }
+
+    #[test]
+    fn text_terms_match_substrings_by_default() {
+        let note = test_note("rust notes", "the Borrow checker complains");
+        let text = TextMatch::default();
+
+        assert!(parse("borrow").unwrap().matches(&note, &[], text));
+        assert!(parse("check").unwrap().matches(&note, &[], text));
+        assert!(!parse("borow").unwrap().matches(&note, &[], text));
+        assert!(!parse("borrow").unwrap().matches(&note, &[], TextMatch { case_sensitive: true, fuzzy: false }));
+    }
+
+    #[test]
+    fn fuzzy_text_terms_forgive_missing_letters() {
+        let note = test_note("rust notes", "the borrow checker complains\nlifetimes");
+        let text = TextMatch { case_sensitive: false, fuzzy: true };
+
+        assert!(parse("borow chk").unwrap().matches(&note, &[], text));
+        assert!(parse("title:rst").unwrap().matches(&note, &[], text));
+        // the letters have to appear in order on one line
+        assert!(!parse("kcehc").unwrap().matches(&note, &[], text));
+        assert!(!parse("complainslifetimes").unwrap().matches(&note, &[], text));
+    }
+}
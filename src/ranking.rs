@@ -0,0 +1,187 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+
+use crate::index::{self, SearchIndex, TermCounts};
+use crate::models::Note;
+use crate::query::{Field, Term};
+
+// bm25 tuning, the usual defaults
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+// a word in the title counts as much as this many words in the body
+const TITLE_BOOST: f64 = 3.0;
+
+// a note's recency boost halves every this many days, down to RECENCY_FLOOR
+const RECENCY_HALF_LIFE_DAYS: f64 = 90.0;
+const RECENCY_FLOOR: f64 = 0.7;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    #[default]
+    Score,
+    Created,
+    Updated,
+    Title,
+}
+
+// which parts of a note a query word is scored against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Anywhere,
+    Title,
+    Content,
+}
+
+// bm25f over the title and content of every live note in the index. corpus
+// statistics are gathered once per search from the index's term counts, only
+// for the words in the query
+pub struct Ranker {
+    words: Vec<(Scope, String)>,
+    doc_count: f64,
+    avg_title_len: f64,
+    avg_content_len: f64,
+    doc_freq: HashMap<String, usize>,
+}
+
+impl Ranker {
+    pub fn new(index: &SearchIndex, terms: &[&Term]) -> Self {
+        let mut words = Vec::new();
+        for term in terms {
+            let scope = match term.field {
+                Field::Title => Scope::Title,
+                Field::Content => Scope::Content,
+                Field::Text => Scope::Anywhere,
+                Field::Tag | Field::Project => continue,
+            };
+            for word in index::tokenize(&term.value) {
+                if !words.contains(&(scope, word.clone())) {
+                    words.push((scope, word));
+                }
+            }
+        }
+
+        let query_words: HashSet<&str> = words.iter().map(|(_, word)| word.as_str()).collect();
+        let mut doc_freq: HashMap<String, usize> = HashMap::new();
+        let (mut doc_count, mut title_total, mut content_total) = (0usize, 0usize, 0usize);
+
+        for entry in index.entries().filter(|entry| !entry.note.is_deleted()) {
+            doc_count += 1;
+            title_total += entry.counts.title_len;
+            content_total += entry.counts.content_len;
+
+            for word in query_words.iter().filter(|word| entry.counts.contains(word)) {
+                *doc_freq.entry(word.to_string()).or_default() += 1;
+            }
+        }
+
+        let average = |total: usize| if doc_count == 0 { 0.0 } else { total as f64 / doc_count as f64 };
+
+        Self {
+            words,
+            doc_count: doc_count as f64,
+            avg_title_len: average(title_total),
+            avg_content_len: average(content_total),
+            doc_freq,
+        }
+    }
+
+    // 0.0 when none of the query words appear in the note as whole words
+    pub fn score(&self, counts: &TermCounts) -> f64 {
+        self.words.iter().map(|(scope, word)| {
+            let in_title = if *scope == Scope::Content { 0 } else { count(&counts.title, word) };
+            let in_content = if *scope == Scope::Title { 0 } else { count(&counts.content, word) };
+
+            let weighted = TITLE_BOOST * normalized(in_title, counts.title_len, self.avg_title_len)
+                + normalized(in_content, counts.content_len, self.avg_content_len);
+            if weighted == 0.0 {
+                return 0.0;
+            }
+
+            self.idf(word) * weighted / (K1 + weighted)
+        }).sum()
+    }
+
+    fn idf(&self, word: &str) -> f64 {
+        let df = self.doc_freq.get(word).copied().unwrap_or(0) as f64;
+        ((self.doc_count - df + 0.5) / (df + 0.5) + 1.0).ln()
+    }
+}
+
+fn count(counts: &BTreeMap<String, usize>, word: &str) -> usize {
+    counts.get(word).copied().unwrap_or(0)
+}
+
+// term frequency corrected for how long this field is compared to the average
+fn normalized(frequency: usize, len: usize, avg_len: f64) -> f64 {
+    if frequency == 0 {
+        return 0.0;
+    }
+    let relative_len = if avg_len > 0.0 { len as f64 / avg_len } else { 1.0 };
+    frequency as f64 / (1.0 - B + B * relative_len)
+}
+
+// 1.0 for a note touched just now, easing towards RECENCY_FLOOR as it ages
+pub fn recency_weight(note: &Note, now: DateTime<Utc>) -> f64 {
+    let touched = note.updated.unwrap_or(note.created);
+    let age_days = (now - touched).num_seconds().max(0) as f64 / 86_400.0;
+    RECENCY_FLOOR + (1.0 - RECENCY_FLOOR) * 0.5f64.powf(age_days / RECENCY_HALF_LIFE_DAYS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query;
+    use crate::store::test_note;
+
+    fn index_of(notes: &[(&str, &str)]) -> (SearchIndex, Vec<Note>) {
+        let dir = tempfile::tempdir().unwrap();
+        let notes: Vec<Note> = notes.iter()
+            .map(|(title, content)| test_note(title, content))
+            .collect();
+        for note in &notes {
+            note.save_to_file(dir.path().join(format!("{}.md", note.id))).unwrap();
+        }
+
+        let mut index = SearchIndex::default();
+        index.refresh(dir.path()).unwrap();
+        (index, notes)
+    }
+
+    fn score(index: &SearchIndex, ranker: &Ranker, note: &Note) -> f64 {
+        ranker.score(&index.get(note.id).unwrap().counts)
+    }
+
+    #[test]
+    fn scores_from_the_index_term_counts() {
+        let (index, notes) = index_of(&[
+            ("borrow checker", "fighting it again"),
+            ("lifetimes", "the borrow checker and borrow rules"),
+            ("shopping", "milk and eggs"),
+        ]);
+        let query = query::parse("borrow").unwrap();
+        let ranker = Ranker::new(&index, &query.positive_terms());
+
+        let title = score(&index, &ranker, &notes[0]);
+        let content = score(&index, &ranker, &notes[1]);
+        assert!(title > content, "{} <= {}", title, content);
+        assert!(content > 0.0);
+        assert_eq!(score(&index, &ranker, &notes[2]), 0.0);
+    }
+
+    #[test]
+    fn rare_words_count_more() {
+        let (index, notes) = index_of(&[
+            ("a", "rust rust async"),
+            ("b", "rust notes"),
+            ("c", "rust again"),
+        ]);
+        let rust = query::parse("rust").unwrap();
+        let async_ = query::parse("async").unwrap();
+
+        let common = score(&index, &Ranker::new(&index, &rust.positive_terms()), &notes[0]);
+        let rare = score(&index, &Ranker::new(&index, &async_.positive_terms()), &notes[0]);
+        assert!(rare > common, "{} <= {}", rare, common);
+    }
+}
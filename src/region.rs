@@ -0,0 +1,129 @@
+use std::ops::Range;
+
+// a markdown heading and everything under it up to the next heading of the
+// same or a higher level. `body` is the text after the heading line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub title: String,
+    pub level: usize,
+    pub body: Range<usize>,
+}
+
+// the heading sections of `content`, in order, as byte ranges. headings
+// inside fenced code blocks don't count
+pub fn sections(content: &str) -> Vec<Section> {
+    // (heading start, body start, level, title)
+    let mut headings: Vec<(usize, usize, usize, String)> = Vec::new();
+    let mut offset = 0;
+    let mut in_fence = false;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            if let Some((level, title)) = heading(trimmed) {
+                headings.push((offset, offset + line.len(), level, title));
+            }
+        }
+        offset += line.len();
+    }
+
+    headings.iter()
+        .enumerate()
+        .map(|(index, (_, body, level, title))| {
+            let end = headings[index + 1..].iter()
+                .find(|(_, _, other, _)| other <= level)
+                .map_or(content.len(), |(next, _, _, _)| *next);
+            Section { title: title.clone(), level: *level, body: *body..end }
+        })
+        .collect()
+}
+
+// "## some title" as (2, "some title"). a #tag at the start of a line isn't a
+// heading, markdown needs the space
+fn heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t', '\n', '\r'])) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim().to_string()))
+}
+
+// the byte range of a (row, column) selection in `lines` joined with
+// newlines, columns counted in characters the way the editor counts them
+pub fn selection_range(lines: &[String], start: (usize, usize), end: (usize, usize)) -> Range<usize> {
+    let offset = |(row, column): (usize, usize)| {
+        let before: usize = lines.iter().take(row).map(|line| line.len() + 1).sum();
+        let line = lines.get(row).map_or("", String::as_str);
+        before + line.char_indices().nth(column).map_or(line.len(), |(index, _)| index)
+    };
+    offset(start)..offset(end)
+}
+
+// `content` with `range` replaced by `replacement`. the region's own leading
+// and trailing whitespace is kept, since the model trims its answer
+pub fn splice(content: &str, range: Range<usize>, replacement: &str) -> String {
+    let region = &content[range.clone()];
+    let leading = &region[..region.len() - region.trim_start().len()];
+    let trailing = &region[region.trim_end().len()..];
+
+    let mut spliced = String::with_capacity(content.len() + replacement.len());
+    spliced.push_str(&content[..range.start]);
+    spliced.push_str(leading);
+    spliced.push_str(replacement.trim());
+    if !region.trim().is_empty() {
+        spliced.push_str(trailing);
+    }
+    spliced.push_str(&content[range.end..]);
+    spliced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str = "# Intro\ntext #tag\n```\n# not a heading\n```\n## Part\nmore\n#hashtag line\n";
+
+    #[test]
+    fn headings_in_fenced_code_are_not_sections() {
+        let titles: Vec<(usize, String)> = sections(NOTE).into_iter().map(|section| (section.level, section.title)).collect();
+        assert_eq!(titles, vec![(1, "Intro".to_string()), (2, "Part".to_string())]);
+    }
+
+    #[test]
+    fn the_last_section_runs_to_the_end() {
+        let found = sections(NOTE);
+        assert_eq!(&NOTE[found[1].body.clone()], "more\n#hashtag line\n");
+
+        // a deeper heading doesn't end the one above it
+        assert_eq!(found[0].body.end, NOTE.len());
+        assert!(NOTE[found[0].body.clone()].starts_with("text #tag\n"));
+    }
+
+    #[test]
+    fn selections_land_on_character_boundaries() {
+        let lines = vec!["naïve café".to_string(), "日本語 text".to_string()];
+        let content = lines.join("\n");
+
+        let range = selection_range(&lines, (0, 6), (1, 3));
+        assert!(content.is_char_boundary(range.start) && content.is_char_boundary(range.end));
+        assert_eq!(&content[range], "café\n日本語");
+
+        // a column past the end of the line stops at the line end
+        assert_eq!(selection_range(&lines, (0, 40), (0, 40)), 12..12);
+    }
+
+    #[test]
+    fn splicing_keeps_the_region_whitespace_and_round_trips() {
+        let content = "# A\n\nold text\n\n# B\nend\n";
+        let body = sections(content)[0].body.clone();
+
+        assert_eq!(splice(content, body.clone(), &content[body.clone()]), content);
+        assert_eq!(splice(content, body.clone(), "  new text \n"), "# A\n\nnew text\n\n# B\nend\n");
+
+        let (start, end) = (content.find("old").unwrap(), content.find("old").unwrap() + 3);
+        assert_eq!(splice(content, start..end, "new"), "# A\n\nnew text\n\n# B\nend\n");
+    }
+}
@@ -0,0 +1,472 @@
+#[cfg(test)]
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::git::GitBackend;
+use crate::history::{self, HistoryStore};
+use crate::index::SearchIndex;
+use crate::links;
+use crate::models::Note;
+use crate::store::StoreError;
+use crate::vault;
+
+#[derive(Debug, Clone)]
+pub enum DeletionType {
+    Soft,
+    Hard,
+}
+
+// what happened to a note, used to describe the change in the git log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Create,
+    Edit,
+    AiRewrite,
+    Revert,
+    Delete,
+    Restore,
+    Purge,
+}
+
+impl Change {
+    pub fn message(self, note: &Note) -> String {
+        let operation = match self {
+            Change::Create => "create",
+            Change::Edit => "edit",
+            Change::AiRewrite => "ai rewrite",
+            Change::Revert => "revert",
+            Change::Delete => "delete",
+            Change::Restore => "restore",
+            Change::Purge => "purge",
+        };
+
+        format!("{} '{}'", operation, note.title.as_deref().unwrap_or("untitled"))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LoadedNotes {
+    pub notes: Vec<Note>,
+    pub failures: Vec<PathBuf>,
+}
+
+// the single entry point for reading and writing notes. the cli and the tui
+// both go through this so they agree on where notes live and how errors surface
+pub trait NoteRepository {
+    // every note in the vault, including soft-deleted ones, plus the files that failed to parse
+    fn load(&mut self) -> Result<LoadedNotes, StoreError>;
+    fn get(&self, id: Uuid) -> Result<Note, StoreError>;
+    // persists a note as-is, creating it if needed
+    fn write(&mut self, note: &Note) -> Result<(), StoreError>;
+    fn remove(&mut self, id: Uuid) -> Result<(), StoreError>;
+
+    fn search_index(&self) -> Option<&SearchIndex> {
+        None
+    }
+
+    fn history(&self) -> Option<&HistoryStore> {
+        None
+    }
+
+    // called once per user-facing change, after all files are written
+    fn commit(&mut self, _message: &str) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    fn create(&mut self, mut note: Note) -> Result<Note, StoreError> {
+        let notes = self.load()?.notes;
+        links::resolve_note_links(&mut note, &notes);
+        self.write_linked(&note, notes)?;
+        self.commit(&Change::Create.message(&note))?;
+        Ok(note)
+    }
+
+    fn update(&mut self, note: &Note) -> Result<(), StoreError> {
+        self.update_as(note, Change::Edit)
+    }
+
+    fn update_as(&mut self, note: &Note, change: Change) -> Result<(), StoreError> {
+        if let (Some(history), Ok(previous)) = (self.history(), self.get(note.id)) {
+            if !history::same_version(&previous, note) {
+                history.record(&previous)?;
+            }
+        }
+
+        let notes = self.load()?.notes;
+        let mut note = note.clone();
+        links::resolve_note_links(&mut note, &notes);
+        self.write_linked(&note, notes)?;
+        self.commit(&change.message(&note))
+    }
+
+    // saves several edited notes as a single change, snapshotting each one's
+    // previous version first
+    fn update_all(&mut self, notes: &[Note], message: &str) -> Result<(), StoreError> {
+        if let Some(history) = self.history() {
+            for note in notes {
+                if let Ok(previous) = self.get(note.id) {
+                    if !history::same_version(&previous, note) {
+                        history.record(&previous)?;
+                    }
+                }
+            }
+        }
+
+        self.write_all(notes)?;
+        self.commit(message)
+    }
+
+    fn write_all(&mut self, notes: &[Note]) -> Result<(), StoreError> {
+        for note in notes {
+            self.write(note)?;
+        }
+        Ok(())
+    }
+
+    // writes `note` along with the other notes that were waiting on a [[link]]
+    // it now satisfies, in one batch
+    fn write_linked(&mut self, note: &Note, notes: Vec<Note>) -> Result<(), StoreError> {
+        let mut changed = vec![note.clone()];
+        for mut other in notes {
+            if other.id != note.id && links::resolve_pending_links(&mut other, note) {
+                changed.push(other);
+            }
+        }
+        self.write_all(&changed)
+    }
+
+    fn delete(&mut self, id: Uuid, deletion: DeletionType) -> Result<(), StoreError> {
+        match deletion {
+            DeletionType::Soft => {
+                let mut note = self.get(id)?;
+                if note.deleted_at.is_none() {
+                    note.deleted_at = Some(Utc::now());
+                    self.write(&note)?;
+                    self.commit(&Change::Delete.message(&note))?;
+                }
+                Ok(())
+            }
+            DeletionType::Hard => {
+                let note = self.get(id)?;
+                self.remove(id)?;
+                self.commit(&Change::Purge.message(&note))
+            }
+        }
+    }
+
+    fn restore(&mut self, id: Uuid) -> Result<(), StoreError> {
+        let mut note = self.get(id)?;
+        if note.is_deleted() {
+            note.deleted_at = None;
+            note.explicit_tags.retain(|tag| tag != Note::DELETED_TAG);
+            note.merge_metadata();
+            self.write(&note)?;
+            self.commit(&Change::Restore.message(&note))?;
+        }
+        Ok(())
+    }
+
+    // soft-deleted notes, most recently trashed first
+    fn trashed(&mut self) -> Result<Vec<Note>, StoreError> {
+        let mut notes = self.load()?.notes;
+        notes.retain(|note| note.is_deleted());
+        notes.sort_by_key(|note| std::cmp::Reverse(note.trashed_at()));
+        Ok(notes)
+    }
+
+    // permanently removes trashed notes, optionally only those trashed before `cutoff`
+    fn empty_trash(&mut self, cutoff: Option<DateTime<Utc>>) -> Result<usize, StoreError> {
+        let mut purged = 0;
+        for note in self.trashed()? {
+            if cutoff.is_some_and(|cutoff| note.trashed_at().is_some_and(|at| at > cutoff)) {
+                continue;
+            }
+            self.remove(note.id)?;
+            purged += 1;
+        }
+
+        if purged > 0 {
+            self.commit(&format!("empty trash ({} notes)", purged))?;
+        }
+        Ok(purged)
+    }
+}
+
+pub struct FileRepository {
+    notes_dir: PathBuf,
+    index: SearchIndex,
+    history: HistoryStore,
+    git: Option<GitBackend>,
+}
+
+impl FileRepository {
+    // the notes of the current vault, committed to git if it is enabled there
+    pub fn open() -> Result<Self, StoreError> {
+        let mut repository = Self::at(&vault::stash_dir()?)?;
+        repository.git = GitBackend::from_config(&Config::load()?)?;
+        Ok(repository)
+    }
+
+    // the notes of the stash rooted at `stash_dir`, without git
+    pub fn at(stash_dir: &Path) -> Result<Self, StoreError> {
+        let notes_dir = stash_dir.join(vault::NOTES_DIR);
+
+        Ok(Self {
+            index: SearchIndex::open_at(stash_dir.join(vault::INDEX_DIR), &notes_dir)?,
+            history: HistoryStore::at(stash_dir.join(vault::HISTORY_DIR)),
+            notes_dir,
+            git: None,
+        })
+    }
+
+    fn path_for(&self, id: Uuid) -> PathBuf {
+        self.index
+            .get(id)
+            .map(|entry| entry.path.clone())
+            .unwrap_or_else(|| self.notes_dir.join(format!("{}.md", id)))
+    }
+}
+
+impl NoteRepository for FileRepository {
+    fn load(&mut self) -> Result<LoadedNotes, StoreError> {
+        self.index.sync(&self.notes_dir)?;
+
+        Ok(LoadedNotes {
+            notes: self.index.entries().map(|entry| entry.note.clone()).collect(),
+            failures: self.index.skipped.clone(),
+        })
+    }
+
+    fn get(&self, id: Uuid) -> Result<Note, StoreError> {
+        match self.index.get(id) {
+            Some(entry) => Ok(entry.note.clone()),
+            None => Note::load_from_file(self.path_for(id)).map_err(|_| StoreError::NotFound(id)),
+        }
+    }
+
+    fn write(&mut self, note: &Note) -> Result<(), StoreError> {
+        fs::create_dir_all(&self.notes_dir)?;
+        note.save_to_file(self.path_for(note.id))?;
+        self.index.sync(&self.notes_dir)?;
+        Ok(())
+    }
+
+    // every note is written to a temporary file first and only moved into
+    // place once they all were. if writing fails nothing is touched, and if a
+    // move fails the notes already moved get their old content written back.
+    // either way no temporary files are left behind, but a crash halfway
+    // through the moves can still leave some notes changed and some not
+    fn write_all(&mut self, notes: &[Note]) -> Result<(), StoreError> {
+        fs::create_dir_all(&self.notes_dir)?;
+
+        let mut staged = Vec::new();
+        for note in notes {
+            let path = self.path_for(note.id);
+            let temp = path.with_extension("md.tmp");
+            let written = note.to_markdown_string()
+                .map_err(StoreError::from)
+                .and_then(|markdown| fs::write(&temp, markdown).map_err(StoreError::from));
+
+            if let Err(e) = written {
+                let _ = fs::remove_file(temp);
+                for (temp, _, _) in staged {
+                    let _ = fs::remove_file(temp);
+                }
+                return Err(e);
+            }
+            let previous = fs::read(&path).ok();
+            staged.push((temp, path, previous));
+        }
+
+        for (moved, (temp, path, _)) in staged.iter().enumerate() {
+            if let Err(e) = fs::rename(temp, path) {
+                for (_, path, previous) in &staged[..moved] {
+                    let _ = match previous {
+                        Some(previous) => fs::write(path, previous),
+                        None => fs::remove_file(path),
+                    };
+                }
+                for (temp, _, _) in &staged[moved..] {
+                    let _ = fs::remove_file(temp);
+                }
+                return Err(e.into());
+            }
+        }
+
+        self.index.sync(&self.notes_dir)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<(), StoreError> {
+        fs::remove_file(self.path_for(id))?;
+        self.history.remove(id)?;
+        self.index.sync(&self.notes_dir)?;
+        Ok(())
+    }
+
+    fn search_index(&self) -> Option<&SearchIndex> {
+        Some(&self.index)
+    }
+
+    fn history(&self) -> Option<&HistoryStore> {
+        Some(&self.history)
+    }
+
+    fn commit(&mut self, message: &str) -> Result<(), StoreError> {
+        if let Some(ref git) = self.git {
+            git.commit(message)?;
+        }
+        Ok(())
+    }
+}
+
+// keeps notes in memory only, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryRepository {
+    notes: BTreeMap<Uuid, Note>,
+}
+
+#[cfg(test)]
+impl MemoryRepository {
+    pub fn with_notes(notes: Vec<Note>) -> Self {
+        Self {
+            notes: notes.into_iter().map(|note| (note.id, note)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl NoteRepository for MemoryRepository {
+    fn load(&mut self) -> Result<LoadedNotes, StoreError> {
+        Ok(LoadedNotes {
+            notes: self.notes.values().cloned().collect(),
+            failures: Vec::new(),
+        })
+    }
+
+    fn get(&self, id: Uuid) -> Result<Note, StoreError> {
+        self.notes.get(&id).cloned().ok_or(StoreError::NotFound(id))
+    }
+
+    fn write(&mut self, note: &Note) -> Result<(), StoreError> {
+        self.notes.insert(note.id, note.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<(), StoreError> {
+        self.notes.remove(&id).map(|_| ()).ok_or(StoreError::NotFound(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::store::test_note;
+
+    #[test]
+    fn create_resolves_links_both_ways() {
+        let mut repository = MemoryRepository::default();
+        let early = repository.create(test_note("early", "see [[later]]")).unwrap();
+        assert_eq!(early.unresolved_links, vec!["later".to_string()]);
+
+        let later = repository.create(test_note("later", "back to [[early]]")).unwrap();
+        assert_eq!(later.links_to, vec![early.id]);
+
+        let early = repository.get(early.id).unwrap();
+        assert_eq!(early.links_to, vec![later.id]);
+        assert!(early.unresolved_links.is_empty());
+    }
+
+    #[test]
+    fn update_rewrites_the_note() {
+        let mut repository = MemoryRepository::default();
+        let mut saved = repository.create(test_note("draft", "first")).unwrap();
+        saved.content = "second".to_string();
+        repository.update(&saved).unwrap();
+
+        assert_eq!(repository.get(saved.id).unwrap().content, "second");
+        assert_eq!(repository.load().unwrap().notes.len(), 1);
+    }
+
+    #[test]
+    fn soft_delete_and_restore() {
+        let mut repository = MemoryRepository::default();
+        let saved = repository.create(test_note("keep", "text")).unwrap();
+
+        repository.delete(saved.id, DeletionType::Soft).unwrap();
+        assert!(repository.get(saved.id).unwrap().is_deleted());
+        assert_eq!(repository.trashed().unwrap().len(), 1);
+
+        repository.restore(saved.id).unwrap();
+        assert!(!repository.get(saved.id).unwrap().is_deleted());
+        assert!(repository.trashed().unwrap().is_empty());
+    }
+
+    #[test]
+    fn hard_delete_removes_the_note() {
+        let mut repository = MemoryRepository::default();
+        let saved = repository.create(test_note("temp", "text")).unwrap();
+
+        repository.delete(saved.id, DeletionType::Hard).unwrap();
+        assert!(matches!(repository.get(saved.id), Err(StoreError::NotFound(_))));
+    }
+
+    #[test]
+    fn empty_trash_respects_the_cutoff() {
+        let mut old = test_note("old", "a");
+        old.deleted_at = Some(Utc::now() - Duration::days(40));
+        let mut recent = test_note("recent", "b");
+        recent.deleted_at = Some(Utc::now() - Duration::days(1));
+        let mut repository = MemoryRepository::with_notes(vec![old.clone(), recent.clone()]);
+
+        let purged = repository.empty_trash(Some(Utc::now() - Duration::days(30))).unwrap();
+        assert_eq!(purged, 1);
+        assert!(repository.get(old.id).is_err());
+        assert!(repository.get(recent.id).is_ok());
+
+        assert_eq!(repository.empty_trash(None).unwrap(), 1);
+        assert!(repository.load().unwrap().notes.is_empty());
+    }
+
+    #[test]
+    fn update_all_writes_every_note() {
+        let mut repository = MemoryRepository::with_notes(vec![test_note("a", "one"), test_note("b", "two")]);
+        let mut notes = repository.load().unwrap().notes;
+        for note in &mut notes {
+            note.content.push_str(" edited");
+        }
+
+        repository.update_all(&notes, "edit both").unwrap();
+        assert!(repository.load().unwrap().notes.iter().all(|note| note.content.ends_with(" edited")));
+    }
+
+    #[test]
+    fn a_failed_batch_puts_moved_notes_back_and_leaves_no_temp_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let notes_dir = temp.path().join("notes");
+        let mut repository = FileRepository::at(temp.path()).unwrap();
+        let mut first = repository.create(test_note("first", "before")).unwrap();
+        let mut second = repository.create(test_note("second", "before")).unwrap();
+
+        // a directory in place of the second note makes moving it fail
+        let blocked = notes_dir.join(format!("{}.md", second.id));
+        fs::remove_file(&blocked).unwrap();
+        fs::create_dir_all(blocked.join("in-the-way")).unwrap();
+
+        first.content = "after".to_string();
+        second.content = "after".to_string();
+        assert!(repository.write_all(&[first.clone(), second]).is_err());
+
+        let on_disk = Note::load_from_file(notes_dir.join(format!("{}.md", first.id))).unwrap();
+        assert_eq!(on_disk.content, "before");
+        let temp_files = fs::read_dir(&notes_dir).unwrap().filter(|entry| entry.as_ref().unwrap().path().to_string_lossy().ends_with(".tmp")).count();
+        assert_eq!(temp_files, 0);
+    }
+}
@@ -0,0 +1,298 @@
+use chrono::Utc;
+use console::Style;
+
+use crate::history;
+use crate::models::Note;
+use crate::repository::{FileRepository, NoteRepository};
+use crate::store::StoreError;
+use crate::tags::{self, MarkerKind};
+
+// a vault-wide rename, merge or delete of tags or projects. each entry maps a
+// name, and everything nested under it, to a new name or to nothing
+#[derive(Debug, Clone)]
+pub struct Retag {
+    kind: MarkerKind,
+    mapping: Vec<(String, Option<String>)>,
+    description: String,
+}
+
+impl Retag {
+    pub fn rename(kind: MarkerKind, from: &str, to: &str) -> Result<Self, StoreError> {
+        let (from, to) = (clean(kind, from)?, clean(kind, to)?);
+        Ok(Self {
+            description: format!("rename {} {} to {}", kind.label(), show(kind, &from), show(kind, &to)),
+            mapping: vec![(from, Some(to))],
+            kind,
+        })
+    }
+
+    pub fn merge(kind: MarkerKind, sources: &[String], into: &str) -> Result<Self, StoreError> {
+        let into = clean(kind, into)?;
+        let mut mapping = Vec::new();
+        for source in sources {
+            let source = clean(kind, source)?;
+            if !source.eq_ignore_ascii_case(&into) {
+                mapping.push((source, Some(into.clone())));
+            }
+        }
+
+        let names: Vec<String> = mapping.iter().map(|(source, _)| show(kind, source)).collect();
+        Ok(Self {
+            description: format!("merge {} into {}", names.join(", "), show(kind, &into)),
+            mapping,
+            kind,
+        })
+    }
+
+    pub fn delete(kind: MarkerKind, name: &str) -> Result<Self, StoreError> {
+        let name = clean(kind, name)?;
+        Ok(Self {
+            description: format!("delete {} {}", kind.label(), show(kind, &name)),
+            mapping: vec![(name, None)],
+            kind,
+        })
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    // the new name for `name`, None when it is being deleted, or the name
+    // unchanged when this retag doesn't touch it
+    fn map(&self, name: &str) -> Option<Option<String>> {
+        self.mapping.iter()
+            .find(|(from, _)| tags::is_within(name, from))
+            .map(|(from, to)| to.as_ref().map(|to| {
+                // carry over what sits below `from` by component, lowercasing
+                // can change byte lengths so slicing at from.len() isn't safe
+                let rest: String = name.split('/').skip(from.split('/').count()).map(|part| format!("/{}", part)).collect();
+                format!("{}{}", to, rest)
+            }))
+    }
+
+    // the note with its inline mentions and explicit entries rewritten, or
+    // None when it has nothing this retag touches
+    pub fn apply(&self, note: &Note) -> Option<Note> {
+        let content = self.rewrite_content(&note.content);
+        let explicit = match self.kind {
+            MarkerKind::Tag => &note.explicit_tags,
+            MarkerKind::Project => &note.explicit_projects,
+        };
+        let mapped = self.rewrite_list(explicit);
+
+        if content == note.content && mapped == *explicit {
+            return None;
+        }
+
+        let mut updated = note.clone();
+        updated.content = content;
+        match self.kind {
+            MarkerKind::Tag => updated.explicit_tags = mapped,
+            MarkerKind::Project => updated.explicit_projects = mapped,
+        }
+        updated.merge_metadata();
+        updated.updated = Some(Utc::now());
+        Some(updated)
+    }
+
+    // renamed mentions get the new name, deleted ones lose their sigil and
+    // stay in the text as plain words
+    fn rewrite_content(&self, content: &str) -> String {
+        let mut rewritten = content.to_string();
+
+        for marker in tags::markers(content).into_iter().rev() {
+            if marker.kind != self.kind {
+                continue;
+            }
+
+            let replacement = match self.map(&marker.name) {
+                Some(Some(name)) => format!("{}{}", self.kind.sigil(), name),
+                Some(None) => marker.name.clone(),
+                None => continue,
+            };
+            rewritten.replace_range(marker.range, &replacement);
+        }
+
+        rewritten
+    }
+
+    fn rewrite_list(&self, names: &[String]) -> Vec<String> {
+        let mut mapped: Vec<String> = Vec::new();
+        for name in names {
+            let name = match self.map(name) {
+                Some(Some(renamed)) => renamed,
+                Some(None) => continue,
+                None => name.clone(),
+            };
+            if !mapped.contains(&name) {
+                mapped.push(name);
+            }
+        }
+        mapped
+    }
+}
+
+fn clean(kind: MarkerKind, name: &str) -> Result<String, StoreError> {
+    let name = name.trim().trim_start_matches(kind.sigil()).trim_end_matches('/');
+    if !tags::is_valid_name(name) {
+        return Err(StoreError::InvalidTagName(name.to_string()));
+    }
+    Ok(name.to_string())
+}
+
+fn show(kind: MarkerKind, name: &str) -> String {
+    format!("{}{}", kind.sigil(), name)
+}
+
+// the notes a retag would change, as (before, after) pairs. trashed notes
+// are included so restoring one doesn't bring back the old name
+pub fn plan(repository: &mut dyn NoteRepository, retag: &Retag) -> Result<Vec<(Note, Note)>, StoreError> {
+    let notes = repository.load()?.notes;
+    Ok(notes.into_iter()
+        .filter_map(|note| retag.apply(&note).map(|updated| (note, updated)))
+        .collect())
+}
+
+// applies a retag as one change and returns how many notes it touched
+pub fn apply(repository: &mut dyn NoteRepository, retag: &Retag) -> Result<usize, StoreError> {
+    let changes = plan(repository, retag)?;
+    if changes.is_empty() {
+        return Ok(0);
+    }
+
+    let updated: Vec<Note> = changes.into_iter().map(|(_, updated)| updated).collect();
+    repository.update_all(&updated, &format!("{} ({} notes)", retag.description(), updated.len()))?;
+    Ok(updated.len())
+}
+
+pub fn run(retag: Retag, dry_run: bool) -> Result<(), StoreError> {
+    let mut repository = FileRepository::open()?;
+    let heading_style = Style::new().bold().cyan();
+    let success_style = Style::new().bold().green();
+
+    if !dry_run {
+        let count = apply(&mut repository, &retag)?;
+        if count == 0 {
+            println!("nothing to do, no notes use those {}s", retag.kind.label());
+        } else {
+            println!("{} {} in {} note{}", success_style.apply_to("✓"), retag.description(), count, if count == 1 { "" } else { "s" });
+        }
+        return Ok(());
+    }
+
+    let changes = plan(&mut repository, &retag)?;
+    if changes.is_empty() {
+        println!("nothing to do, no notes use those {}s", retag.kind.label());
+        return Ok(());
+    }
+
+    for (before, after) in &changes {
+        println!("\n{}", heading_style.apply_to(before.title.as_deref().unwrap_or("untitled")));
+        print!("{}", history::render_diff(before, after, "before", "after"));
+    }
+
+    println!("\n{} would change {} note{}, run again without --dry-run to apply",
+        retag.description(),
+        changes.len(),
+        if changes.len() == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::MemoryRepository;
+    use crate::store::test_note;
+
+    fn only(repository: &mut MemoryRepository) -> Note {
+        repository.load().unwrap().notes.remove(0)
+    }
+
+    #[test]
+    fn renames_a_nested_tag_and_everything_under_it() {
+        let mut repository = MemoryRepository::with_notes(vec![test_note("study", "learning #lang/rust and #lang/rust/async, also #lang and #language")]);
+        let retag = Retag::rename(MarkerKind::Tag, "#lang/rust", "rust").unwrap();
+
+        assert_eq!(apply(&mut repository, &retag).unwrap(), 1);
+        let note = only(&mut repository);
+        assert_eq!(note.content, "learning #rust and #rust/async, also #lang and #language");
+        assert_eq!(note.tags, vec!["rust", "rust/async", "lang", "language"]);
+    }
+
+    #[test]
+    fn renames_non_ascii_tags_whatever_their_case() {
+        let mut repository = MemoryRepository::with_notes(vec![test_note("trip", "#Café/Paris and #café, see #日本語/文法")]);
+
+        apply(&mut repository, &Retag::rename(MarkerKind::Tag, "café", "coffee").unwrap()).unwrap();
+        apply(&mut repository, &Retag::rename(MarkerKind::Tag, "日本語", "japanese").unwrap()).unwrap();
+        assert_eq!(only(&mut repository).content, "#coffee/Paris and #coffee, see #japanese/文法");
+    }
+
+    #[test]
+    fn merging_into_an_existing_tag_leaves_no_duplicates() {
+        let mut draft = test_note("chores", "#todo and #todos and #to-do");
+        draft.explicit_tags = vec!["todos".to_string(), "todo".to_string()];
+        draft.merge_metadata();
+        let mut repository = MemoryRepository::with_notes(vec![draft]);
+
+        let retag = Retag::merge(MarkerKind::Tag, &["todos".to_string(), "to-do".to_string(), "todo".to_string()], "todo").unwrap();
+        assert_eq!(retag.description(), "merge #todos, #to-do into #todo");
+        apply(&mut repository, &retag).unwrap();
+
+        let note = only(&mut repository);
+        assert_eq!(note.content, "#todo and #todo and #todo");
+        assert_eq!(note.explicit_tags, vec!["todo"]);
+        assert_eq!(note.tags, vec!["todo"]);
+    }
+
+    #[test]
+    fn deleting_a_tag_leaves_the_plain_word() {
+        let mut repository = MemoryRepository::with_notes(vec![test_note("release", "ship it #urgent today, #urgent/now too")]);
+        apply(&mut repository, &Retag::delete(MarkerKind::Tag, "urgent").unwrap()).unwrap();
+
+        let note = only(&mut repository);
+        assert_eq!(note.content, "ship it urgent today, urgent/now too");
+        assert!(note.tags.is_empty());
+    }
+
+    #[test]
+    fn code_and_links_are_left_alone() {
+        let content = "#draft here, `#draft` inline\n```\n#draft\n```\n[#draft](https://example.com/#draft)";
+        let retag = Retag::rename(MarkerKind::Tag, "draft", "wip").unwrap();
+
+        let updated = retag.apply(&test_note("mixed", content)).unwrap();
+        assert_eq!(updated.content, content.replacen("#draft", "#wip", 1));
+    }
+
+    #[test]
+    fn rewrites_explicit_frontmatter_lists() {
+        let mut draft = test_note("invoice", "nothing inline");
+        draft.explicit_projects = vec!["client/acme".to_string(), "admin".to_string()];
+        draft.merge_metadata();
+
+        let retag = Retag::rename(MarkerKind::Project, "+client", "+customer").unwrap();
+        let updated = retag.apply(&draft).unwrap();
+        assert_eq!(updated.content, "nothing inline");
+        assert_eq!(updated.explicit_projects, vec!["customer/acme", "admin"]);
+        assert_eq!(updated.projects, vec!["customer/acme", "admin"]);
+
+        // tags with the same name are a different thing
+        assert!(Retag::rename(MarkerKind::Tag, "client", "customer").unwrap().apply(&draft).is_none());
+    }
+
+    #[test]
+    fn a_dry_run_plan_writes_nothing() {
+        let untouched = test_note("untouched", "no markers");
+        let mut repository = MemoryRepository::with_notes(vec![test_note("tagged", "see #old"), untouched.clone()]);
+        let retag = Retag::rename(MarkerKind::Tag, "old", "new").unwrap();
+
+        let changes = plan(&mut repository, &retag).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].1.content, "see #new");
+
+        let contents: Vec<String> = repository.load().unwrap().notes.into_iter().map(|note| note.content).collect();
+        assert!(contents.contains(&"see #old".to_string()));
+        assert!(contents.contains(&untouched.content));
+    }
+}
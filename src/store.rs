@@ -1,38 +1,85 @@
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io::{self, Write};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+use std::fmt;
 use chrono::{DateTime, Utc};
 use regex::Regex;
+use serde::Serialize;
 use thiserror::Error;
 use uuid::Uuid;
 use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use console::{Style, Term};
 
-use crate::models::{Note, NoteError};
+use crate::config::ConfigError;
+use crate::git::GitError;
+use crate::index::{IndexError, SearchIndex};
+use crate::models::{Note, NoteError, NoteSource};
+use crate::dates;
+use crate::query::{self, DateField, DateFilter, Field, Query, QueryError, TextMatch};
+use crate::ranking::{self, Ranker, SortOrder};
+use crate::tags::{self, TagNode, Vocabulary};
+use crate::repository::{FileRepository, NoteRepository};
+use crate::vault;
 
 #[derive(Error, Debug)]
 pub enum StoreError {
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
-    #[error("home directory not found")]
-    HomeNotFound,
+    #[error("config error: {0}")]
+    Config(#[from] ConfigError),
     #[error("note error: {0}")]
     Note(#[from] NoteError),
+    #[error("index error: {0}")]
+    Index(#[from] IndexError),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid query: {0}")]
+    Query(#[from] QueryError),
+    #[error("invalid date '{0}', expected something like 2025-03-14, 2025-03, 7d or yesterday")]
+    InvalidDate(String),
+    #[error("'{0}' is not a valid tag or project name, use letters, digits, _, - and /")]
+    InvalidTagName(String),
+    #[error("git error: {0}")]
+    Git(#[from] GitError),
+    #[error("note not found: {0}")]
+    NotFound(Uuid),
+    #[error("no note matches '{0}'")]
+    NoMatchingNote(String),
+    #[error("'{0}' matches more than one note")]
+    AmbiguousNote(String),
+    #[error("invalid age '{0}', expected something like 30d, 2w or 12h")]
+    InvalidAge(String),
+    #[error("revision {0} not found")]
+    RevisionNotFound(u32),
+    #[error("note {0} has no earlier revisions")]
+    NoRevisions(Uuid),
 }
 
 #[derive(Debug, Clone)]
 pub struct SearchResult {
     pub note: Note,
-    pub score: i64,
+    pub score: f64,
     pub title_match: bool,
-    pub content_snippets: Vec<String>,
+    pub content_snippets: Vec<Snippet>,
     pub file_path: PathBuf,
     pub tag_matches: Vec<String>,
     pub project_matches: Vec<String>,
 }
 
+// a matching line of note content, 1-based
+#[derive(Debug, Clone, Serialize)]
+pub struct Snippet {
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for Snippet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.text)
+    }
+}
+
 #[derive(Debug)]
 #[derive(Clone)]
 pub struct SearchOptions {
@@ -42,20 +89,104 @@ pub struct SearchOptions {
     pub list_tags: bool,
     pub list_projects: bool,
     pub case_sensitive: bool,
+    pub limit: Option<usize>,
+    // creation date bounds, same values as created: in a query
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub sort: SortOrder,
+    // rank by the best fuzzy line match instead of bm25
+    pub fuzzy: bool,
+    // let recently touched notes rank a little higher
+    pub recency: bool,
 }
 
-#[derive(Debug)]
-struct ParsedQuery {
-    text_query: String,
-    required_tags: Vec<String>,
-    required_projects: Vec<String>,
-    excluded_tags: Vec<String>,
-    excluded_projects: Vec<String>,
+#[derive(Debug, Clone)]
+pub enum SearchOutput {
+    // numbered results followed by a prompt to open one
+    Interactive,
+    // the same listing without the prompt
+    List,
+    Json,
+    Ndjson,
+    Paths,
+    // one line per result, e.g. "{path}:{title}"
+    Template(String),
 }
 
-pub fn search_notes_advanced(options: SearchOptions) -> Result<(), StoreError> {
+impl SearchOutput {
+    // scripts and pipes never get the prompt
+    pub fn for_stdout() -> Self {
+        if Term::stdout().is_term() {
+            SearchOutput::Interactive
+        } else {
+            SearchOutput::List
+        }
+    }
+
+    fn is_machine_readable(&self) -> bool {
+        !matches!(self, SearchOutput::Interactive | SearchOutput::List)
+    }
+}
+
+// the shape of a result in --json and --ndjson output
+#[derive(Serialize)]
+struct SearchResultRecord<'a> {
+    id: Uuid,
+    title: Option<&'a str>,
+    path: &'a Path,
+    score: f64,
+    title_match: bool,
+    snippets: &'a [Snippet],
+    tag_matches: &'a [String],
+    project_matches: &'a [String],
+    tags: &'a [String],
+    projects: Vec<String>,
+    created: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+}
+
+impl<'a> From<&'a SearchResult> for SearchResultRecord<'a> {
+    fn from(result: &'a SearchResult) -> Self {
+        Self {
+            id: result.note.id,
+            title: result.note.title.as_deref(),
+            path: &result.file_path,
+            score: result.score,
+            title_match: result.title_match,
+            snippets: &result.content_snippets,
+            tag_matches: &result.tag_matches,
+            project_matches: &result.project_matches,
+            tags: &result.note.tags,
+            projects: result.note.projects.clone(),
+            created: result.note.created,
+            updated: result.note.updated,
+        }
+    }
+}
+
+pub fn search_notes_advanced(options: SearchOptions, output: SearchOutput) -> Result<(), StoreError> {
     let stash_dir = get_stash_notes_dir()?;
 
+    let query = match query::parse(&options.query) {
+        Ok(query) => with_date_bounds(query, &options)?,
+        Err(e) => {
+            eprintln!("{}", e.pointer(&options.query));
+            return Err(e.into());
+        }
+    };
+
+    if options.list_tags || options.list_projects {
+        return list_vocabulary(&options, &output);
+    }
+
+    if output.is_machine_readable() {
+        let results = search_notes_return_results(options)?;
+        return match write_machine_output(&results, &output) {
+            Err(StoreError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            other => other,
+        };
+    }
+
     if !stash_dir.exists() {
         println!("no stash directory found at {:?}", stash_dir);
         println!("try creating some notes first with 'stash add \"your note content\"'");
@@ -65,12 +196,15 @@ pub fn search_notes_advanced(options: SearchOptions) -> Result<(), StoreError> {
     let results = search_notes_return_results(options.clone())?;
 
     if results.is_empty() {
-        let parsed_query = parse_search_query(&options.query);
-        display_no_results_help(&options.query, &parsed_query);
+        display_no_results_help(&query);
         return Ok(());
     }
 
     display_search_results_advanced(&results, &options)?;
+
+    if matches!(output, SearchOutput::Interactive) {
+        open_results_interactively(&results)?;
+    }
     Ok(())
 }
 
@@ -81,93 +215,65 @@ pub fn search_notes_return_results(options: SearchOptions) -> Result<Vec<SearchR
         return Ok(Vec::new());
     }
 
-    let all_notes = load_all_notes(&stash_dir)?;
-
-    if options.list_tags {
-        display_all_tags(&all_notes);
+    let repository = FileRepository::open()?;
+    let Some(index) = repository.search_index() else {
         return Ok(Vec::new());
-    }
+    };
 
-    if options.list_projects {
-        display_all_projects(&all_notes);
-        return Ok(Vec::new());
-    }
+    let query = with_date_bounds(query::parse(&options.query)?, &options)?;
+    let mut results = find_matching_notes_advanced(index, &query, &options)?;
 
-    let parsed_query = parse_search_query(&options.query);
-    let results = find_matching_notes_advanced(&all_notes, &parsed_query, &options)?;
+    if let Some(limit) = options.limit {
+        results.truncate(limit);
+    }
 
     Ok(results)
 }
 
-fn load_all_notes(stash_dir: &PathBuf) -> Result<Vec<(Note, PathBuf)>, StoreError> {
-    let mut notes = Vec::new();
-    let entries = fs::read_dir(stash_dir)?;
-
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.extension().and_then(|s| s.to_str()) == Some("md") {
-            if let Ok(note) = Note::load_from_file(&path) {
-                notes.push((note, path));
-            }
-        }
+// folds --since and --until into the query as created: filters
+fn with_date_bounds(query: Query, options: &SearchOptions) -> Result<Query, StoreError> {
+    let bounds = [
+        options.since.as_deref().map(|value| (value, dates::since_expr(value))),
+        options.until.as_deref().map(|value| (value, dates::until_expr(value))),
+    ];
+
+    let mut queries = vec![query];
+    for (value, expr) in bounds.into_iter().flatten() {
+        let filter = DateFilter::parse(DateField::Created, &expr)
+            .ok_or_else(|| StoreError::InvalidDate(value.to_string()))?;
+        queries.push(Query::Date(filter));
     }
 
-    Ok(notes)
+    Ok(if queries.len() == 1 { queries.remove(0) } else { Query::And(queries) })
 }
 
-fn parse_search_query(query: &str) -> ParsedQuery {
-    let mut required_tags = Vec::new();
-    let mut required_projects = Vec::new();
-    let mut excluded_tags = Vec::new();
-    let mut excluded_projects = Vec::new();
-
-    let tag_regex = Regex::new(r"(-?)#(\w+)").unwrap();
-    let project_regex = Regex::new(r"(-?)\+(\w+)").unwrap();
-
-    let mut remaining_text = query.to_string();
-
-    for cap in tag_regex.captures_iter(query) {
-        let is_excluded = &cap[1] == "-";
-        let tag = cap[2].to_string();
-
-        if is_excluded {
-            excluded_tags.push(tag);
-        } else {
-            required_tags.push(tag);
-        }
-
-        remaining_text = remaining_text.replace(&cap[0], "");
+// the tags and projects in use, empty when there are no notes yet
+pub fn vocabulary() -> Result<Vocabulary, StoreError> {
+    if !get_stash_notes_dir()?.exists() {
+        return Ok(Vocabulary::default());
     }
 
-    for cap in project_regex.captures_iter(query) {
-        let is_excluded = &cap[1] == "-";
-        let project = cap[2].to_string();
+    let repository = FileRepository::open()?;
+    Ok(repository
+        .search_index()
+        .map(|index| Vocabulary::from_notes(index.entries().map(|entry| &entry.note)))
+        .unwrap_or_default())
+}
 
-        if is_excluded {
-            excluded_projects.push(project);
-        } else {
-            required_projects.push(project);
-        }
+pub fn rebuild_index() -> Result<(), StoreError> {
+    let index = SearchIndex::rebuild()?;
 
-        remaining_text = remaining_text.replace(&cap[0], "");
+    println!("indexed {} note(s)", index.len());
+    for path in &index.skipped {
+        eprintln!("skipped unreadable note {:?}", path);
     }
 
-    let text_query = remaining_text.trim().to_string();
-
-    ParsedQuery {
-        text_query,
-        required_tags,
-        required_projects,
-        excluded_tags,
-        excluded_projects,
-    }
+    Ok(())
 }
 
 fn find_matching_notes_advanced(
-    notes: &[(Note, PathBuf)],
-    parsed_query: &ParsedQuery,
+    index: &SearchIndex,
+    query: &Query,
     options: &SearchOptions
 ) -> Result<Vec<SearchResult>, StoreError> {
     let matcher = SkimMatcherV2::default();
@@ -183,232 +289,359 @@ fn find_matching_notes_advanced(
         .map(|projects| projects.split(',').map(|p| p.trim().to_lowercase()).collect())
         .unwrap_or_default();
 
-    for (note, path) in notes {
-        let note_tags: HashSet<String> = note.tags.iter().map(|t| t.to_lowercase()).collect();
-        let note_projects = extract_projects(&note.content);
-        let note_projects_set: HashSet<String> = note_projects.iter().map(|p| p.to_lowercase()).collect();
+    // the index only knows whole substrings, a fuzzy term can match anywhere
+    let candidates = if options.fuzzy { None } else { query.candidates(index) };
+    let text_match = TextMatch { case_sensitive: options.case_sensitive, fuzzy: options.fuzzy };
+    let positive_terms = query.positive_terms();
+    let text_terms: Vec<&query::Term> = positive_terms.iter().copied().filter(|term| term.field.is_text()).collect();
+    let ranker = (!options.fuzzy).then(|| Ranker::new(index, &text_terms));
+    let now = Utc::now();
 
-        if !filter_tags.is_empty() && !filter_tags.iter().any(|tag| note_tags.contains(tag)) {
+    for entry in index.entries() {
+        if entry.note.is_deleted() {
             continue;
         }
 
-        if !filter_projects.is_empty() && !filter_projects.iter().any(|proj| note_projects_set.contains(proj)) {
+        if candidates.as_ref().is_some_and(|ids| !ids.contains(&entry.note.id)) {
             continue;
         }
 
-        if !parsed_query.required_tags.is_empty() {
-            let required_tags_lower: HashSet<String> = parsed_query.required_tags.iter().map(|t| t.to_lowercase()).collect();
-            if !required_tags_lower.iter().all(|tag| note_tags.contains(tag)) {
-                continue;
-            }
-        }
+        let (note, path) = (&entry.note, &entry.path);
+        let has_tag = |wanted: &str| note.tags.iter().any(|tag| tags::is_within(tag, wanted));
+        let has_project = |wanted: &str| entry.projects.iter().any(|project| tags::is_within(project, wanted));
 
-        if !parsed_query.required_projects.is_empty() {
-            let required_projects_lower: HashSet<String> = parsed_query.required_projects.iter().map(|p| p.to_lowercase()).collect();
-            if !required_projects_lower.iter().all(|proj| note_projects_set.contains(proj)) {
-                continue;
-            }
+        if !filter_tags.is_empty() && !filter_tags.iter().any(|tag| has_tag(tag)) {
+            continue;
         }
 
-        if !parsed_query.excluded_tags.is_empty() {
-            let excluded_tags_lower: HashSet<String> = parsed_query.excluded_tags.iter().map(|t| t.to_lowercase()).collect();
-            if excluded_tags_lower.iter().any(|tag| note_tags.contains(tag)) {
-                continue;
-            }
+        if !filter_projects.is_empty() && !filter_projects.iter().any(|proj| has_project(proj)) {
+            continue;
         }
 
-        if !parsed_query.excluded_projects.is_empty() {
-            let excluded_projects_lower: HashSet<String> = parsed_query.excluded_projects.iter().map(|p| p.to_lowercase()).collect();
-            if excluded_projects_lower.iter().any(|proj| note_projects_set.contains(proj)) {
-                continue;
-            }
+        if !query.matches(note, &entry.projects, text_match) {
+            continue;
         }
 
         let mut best_score = 0i64;
         let mut title_match = false;
-        let mut content_snippets = Vec::new();
+        let mut content_snippets: Vec<Snippet> = Vec::new();
         let mut tag_matches = Vec::new();
         let mut project_matches = Vec::new();
 
-        if !parsed_query.text_query.is_empty() {
-            if let Some(title) = &note.title {
-                let title_to_search = if options.case_sensitive { title.clone() } else { title.to_lowercase() };
-                let query_to_use = if options.case_sensitive { parsed_query.text_query.clone() } else { parsed_query.text_query.to_lowercase() };
+        let prepare = |text: &str| if options.case_sensitive { text.to_string() } else { text.to_lowercase() };
+        let line_score = |line: &str, value: &str| if options.fuzzy {
+            matcher.fuzzy_match(line, value)
+        } else {
+            line.contains(value).then(|| matcher.fuzzy_match(line, value).unwrap_or(1))
+        };
+
+        for term in &text_terms {
+            let value = prepare(&term.value);
 
-                if let Some(score) = matcher.fuzzy_match(&title_to_search, &query_to_use) {
-                    best_score = best_score.max(score);
-                    title_match = true;
+            if matches!(term.field, Field::Text | Field::Title) {
+                if let Some(title) = &note.title {
+                    if let Some(score) = line_score(&prepare(title), &value) {
+                        best_score = best_score.max(score);
+                        title_match = true;
+                    }
                 }
             }
 
-            let content_lines: Vec<&str> = note.content.lines().collect();
-            for (line_num, line) in content_lines.iter().enumerate() {
-                let line_to_search = if options.case_sensitive { line.to_string() } else { line.to_lowercase() };
-                let query_to_use = if options.case_sensitive { parsed_query.text_query.clone() } else { parsed_query.text_query.to_lowercase() };
-
-                if let Some(score) = matcher.fuzzy_match(&line_to_search, &query_to_use) {
-                    best_score = best_score.max(score);
-                    content_snippets.push(format!("Line {}: {}", line_num + 1, line.trim()));
+            if matches!(term.field, Field::Text | Field::Content) {
+                for (line_num, line) in note.content.lines().enumerate() {
+                    if let Some(score) = line_score(&prepare(line), &value) {
+                        best_score = best_score.max(score);
+                        if !content_snippets.iter().any(|snippet| snippet.line == line_num + 1) {
+                            content_snippets.push(Snippet { line: line_num + 1, text: line.trim().to_string() });
+                        }
+                    }
                 }
             }
-        } else {
-            best_score = 100;
         }
 
-        for tag in &parsed_query.required_tags {
-            if note_tags.contains(&tag.to_lowercase()) {
-                tag_matches.push(tag.clone());
-            }
+        if text_terms.is_empty() {
+            best_score = 100;
         }
 
-        for project in &parsed_query.required_projects {
-            if note_projects_set.contains(&project.to_lowercase()) {
-                project_matches.push(project.clone());
+        for term in &positive_terms {
+            match term.field {
+                Field::Tag if has_tag(&term.value) => tag_matches.push(term.value.clone()),
+                Field::Project if has_project(&term.value) => project_matches.push(term.value.clone()),
+                _ => {}
             }
         }
 
-        if best_score > 0 || !tag_matches.is_empty() || !project_matches.is_empty() {
-            content_snippets.truncate(3);
-            results.push(SearchResult {
-                note: note.clone(),
-                score: best_score,
-                title_match,
-                content_snippets,
-                file_path: path.clone(),
-                tag_matches,
-                project_matches,
-            });
-        }
+        // notes that only match inside longer words ("rust" in "rustacean")
+        // get no bm25 score and fall back to a scaled down fuzzy score, after the rest
+        let (word_match, score) = match &ranker {
+            Some(ranker) => {
+                let boost = if options.recency { ranking::recency_weight(note, now) } else { 1.0 };
+                let bm25 = ranker.score(&entry.counts);
+                if bm25 > 0.0 {
+                    (true, bm25 * boost)
+                } else if text_terms.is_empty() {
+                    (true, boost)
+                } else {
+                    (false, best_score as f64 / 1000.0)
+                }
+            }
+            None => (true, best_score as f64),
+        };
+
+        content_snippets.sort_by_key(|snippet| snippet.line);
+        content_snippets.truncate(3);
+        results.push((word_match, SearchResult {
+            note: note.clone(),
+            score,
+            title_match,
+            content_snippets,
+            file_path: path.clone(),
+            tag_matches,
+            project_matches,
+        }));
     }
 
-    results.sort_by(|a, b| {
+    results.sort_by(|(a_word_match, a), (b_word_match, b)| {
         let a_special_matches = a.tag_matches.len() + a.project_matches.len();
         let b_special_matches = b.tag_matches.len() + b.project_matches.len();
 
-        if a_special_matches != b_special_matches {
-            b_special_matches.cmp(&a_special_matches)
-        } else {
-            b.score.cmp(&a.score)
-        }
+        b_special_matches.cmp(&a_special_matches)
+            .then(b_word_match.cmp(a_word_match))
+            .then(b.score.total_cmp(&a.score))
     });
 
+    let mut results: Vec<SearchResult> = results.into_iter().map(|(_, result)| result).collect();
+
+    // stable, so equal dates and titles keep their relevance order
+    match options.sort {
+        SortOrder::Score => {}
+        SortOrder::Created => results.sort_by_key(|result| std::cmp::Reverse(result.note.created)),
+        SortOrder::Updated => results.sort_by_key(|result| std::cmp::Reverse(result.note.updated.unwrap_or(result.note.created))),
+        SortOrder::Title => results.sort_by_key(|result| result.note.title.as_ref().map(|title| title.to_lowercase()).unwrap_or_default()),
+    }
+
     Ok(results)
 }
 
-fn display_all_tags(notes: &[(Note, PathBuf)]) {
-    let mut tag_counts: HashMap<String, usize> = HashMap::new();
+// --list-tags and --list-projects, as a tree for people or as one record per
+// tag or project for --json and friends
+fn list_vocabulary(options: &SearchOptions, output: &SearchOutput) -> Result<(), StoreError> {
+    let repository = if get_stash_notes_dir()?.exists() { Some(FileRepository::open()?) } else { None };
+    let entries = repository.as_ref().and_then(|repository| repository.search_index()).into_iter().flat_map(|index| index.entries());
+
+    let notes = entries.filter(|entry| !entry.note.is_deleted());
+    let tree = if options.list_tags {
+        tags::tree(notes.map(|entry| entry.note.tags.as_slice()))
+    } else {
+        tags::tree(notes.map(|entry| entry.projects.as_slice()))
+    };
+
+    if !output.is_machine_readable() {
+        if options.list_tags {
+            display_all_tags(&tree);
+        } else {
+            display_all_projects(&tree);
+        }
+        return Ok(());
+    }
+
+    let mut records = Vec::new();
+    flatten_tag_tree(&tree, &mut records);
+    match write_vocabulary_output(&records, output) {
+        Err(StoreError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => other,
+    }
+}
+
+// the shape of a tag or project in --json and --ndjson output
+#[derive(Serialize)]
+struct TagRecord<'a> {
+    name: &'a str,
+    notes: usize,
+}
+
+fn flatten_tag_tree<'a>(nodes: &'a [TagNode], records: &mut Vec<TagRecord<'a>>) {
+    for node in nodes {
+        records.push(TagRecord { name: &node.path, notes: node.count });
+        flatten_tag_tree(&node.children, records);
+    }
+}
+
+fn write_vocabulary_output(records: &[TagRecord], output: &SearchOutput) -> Result<(), StoreError> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
 
-    for (note, _) in notes {
-        for tag in &note.tags {
-            *tag_counts.entry(tag.clone()).or_insert(0) += 1;
+    match output {
+        SearchOutput::Json => writeln!(out, "{}", serde_json::to_string_pretty(records)?)?,
+        SearchOutput::Ndjson => {
+            for record in records {
+                writeln!(out, "{}", serde_json::to_string(record)?)?;
+            }
+        }
+        _ => {
+            for record in records {
+                writeln!(out, "{}", record.name)?;
+            }
         }
     }
 
-    if tag_counts.is_empty() {
+    out.flush()?;
+    Ok(())
+}
+
+fn display_all_tags(tree: &[TagNode]) {
+    if tree.is_empty() {
         println!("no tags found in your notes.");
         println!("add tags to your notes using #tagname syntax.");
         return;
     }
 
-    let mut sorted_tags: Vec<_> = tag_counts.into_iter().collect();
-    sorted_tags.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
-
     let tag_style = Style::new().bold().cyan();
-    let count_style = Style::new().dim();
 
     println!("\n{} available tags:", tag_style.apply_to("📋"));
     println!("{}", "─".repeat(50));
-
-    for (tag, count) in sorted_tags {
-        println!("#{} {}",
-            tag_style.apply_to(&tag),
-            count_style.apply_to(format!("({} note{})", count, if count == 1 { "" } else { "s" }))
-        );
-    }
+    print_tag_tree(tree, '#', &tag_style);
 
     println!("\n💡 usage examples:");
     println!("  stash search \"#rust\"           - find notes with rust tag");
+    println!("  stash search \"#lang\"           - also finds #lang/rust, #lang/go/...");
     println!("  stash search \"#rust #web\"      - find notes with both tags");
     println!("  stash search \"#rust -#old\"     - find rust notes, exclude old ones");
     println!("  stash search --tags rust,web    - filter by specific tags");
 }
 
-fn display_all_projects(notes: &[(Note, PathBuf)]) {
-    let mut project_counts: HashMap<String, usize> = HashMap::new();
-
-    for (note, _) in notes {
-        let projects = extract_projects(&note.content);
-        for project in projects {
-            *project_counts.entry(project).or_insert(0) += 1;
-        }
-    }
-
-    if project_counts.is_empty() {
+fn display_all_projects(tree: &[TagNode]) {
+    if tree.is_empty() {
         println!("no projects found in your notes.");
         println!("add projects to your notes using +projectname syntax.");
         return;
     }
 
-    let mut sorted_projects: Vec<_> = project_counts.into_iter().collect();
-    sorted_projects.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
-
     let project_style = Style::new().bold().green();
-    let count_style = Style::new().dim();
 
     println!("\n{} available projects:", project_style.apply_to("📁"));
     println!("{}", "─".repeat(50));
-
-    for (project, count) in sorted_projects {
-        println!("+{} {}",
-            project_style.apply_to(&project),
-            count_style.apply_to(format!("({} note{})", count, if count == 1 { "" } else { "s" }))
-        );
-    }
+    print_tag_tree(tree, '+', &project_style);
 
     println!("\n💡 usage examples:");
     println!("  stash search \"+myapp\"          - find notes for myapp project");
+    println!("  stash search \"+client\"         - also finds +client/acme, +client/acme/billing...");
     println!("  stash search \"+web +backend\"   - find notes for web and backend");
     println!("  stash search \"+web -+old\"      - find web notes, exclude old project");
     println!("  stash search --projects web,api  - filter by specific projects");
 }
 
-fn display_no_results_help(_original_query: &str, parsed_query: &ParsedQuery) {
+// top level entries with their sigil, nested ones drawn as a tree under
+// them. counts include everything further down
+fn print_tag_tree(nodes: &[TagNode], sigil: char, style: &Style) {
+    let count_style = Style::new().dim();
+
+    for node in nodes {
+        println!("{}{} {}", sigil, style.apply_to(&node.name), count_style.apply_to(note_count(node.count)));
+        print_tag_subtree(&node.children, "", style);
+    }
+}
+
+fn print_tag_subtree(nodes: &[TagNode], prefix: &str, style: &Style) {
+    let count_style = Style::new().dim();
+
+    for (i, node) in nodes.iter().enumerate() {
+        let (branch, indent) = if i + 1 == nodes.len() { ("└── ", "    ") } else { ("├── ", "│   ") };
+        println!("{}{}{} {}", prefix, branch, style.apply_to(&node.name), count_style.apply_to(note_count(node.count)));
+        print_tag_subtree(&node.children, &format!("{}{}", prefix, indent), style);
+    }
+}
+
+fn note_count(count: usize) -> String {
+    format!("({} note{})", count, if count == 1 { "" } else { "s" })
+}
+
+fn display_no_results_help(query: &Query) {
     let help_style = Style::new().bold().yellow();
     let suggestion_style = Style::new().cyan();
 
     println!("no notes found matching your search criteria.");
     println!();
 
-    if !parsed_query.text_query.is_empty() {
-        println!("🔍 text search: \"{}\"", parsed_query.text_query);
+    if *query != Query::All {
+        println!("🔍 query: {}", query);
     }
-
-    if !parsed_query.required_tags.is_empty() {
-        println!("🏷️  required tags: {}", parsed_query.required_tags.iter().map(|t| format!("#{}", t)).collect::<Vec<_>>().join(" "));
-    }
-
-    if !parsed_query.required_projects.is_empty() {
-        println!("📁 required projects: {}", parsed_query.required_projects.iter().map(|p| format!("+{}", p)).collect::<Vec<_>>().join(" "));
-    }
-
     println!();
     println!("{}", help_style.apply_to("💡 search tips:"));
     println!("  • use {} to search by tags", suggestion_style.apply_to("#tagname"));
     println!("  • use {} to search by projects", suggestion_style.apply_to("+projectname"));
     println!("  • use {} to exclude tags/projects", suggestion_style.apply_to("-#tag or -+project"));
     println!("  • combine: {} searches for rust notes in web project", suggestion_style.apply_to("\"#rust +web API\""));
+    println!("  • use {} for either and {} or {} to exclude", suggestion_style.apply_to("OR"), suggestion_style.apply_to("NOT"), suggestion_style.apply_to("-"));
+    println!("  • group with {} and match exact phrases with {}", suggestion_style.apply_to("(a OR b)"), suggestion_style.apply_to("\"quotes\""));
+    println!("  • search one field with {}", suggestion_style.apply_to("title: content: tag: project:"));
     println!("  • try {} to see all available tags", suggestion_style.apply_to("stash search \"\" --list-tags"));
     println!("  • try {} to see all available projects", suggestion_style.apply_to("stash search \"\" --list-projects"));
 }
 
+fn write_machine_output(results: &[SearchResult], output: &SearchOutput) -> Result<(), StoreError> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    match output {
+        SearchOutput::Json => {
+            let records: Vec<SearchResultRecord> = results.iter().map(SearchResultRecord::from).collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&records)?)?;
+        }
+        SearchOutput::Ndjson => {
+            for result in results {
+                writeln!(out, "{}", serde_json::to_string(&SearchResultRecord::from(result))?)?;
+            }
+        }
+        SearchOutput::Paths => {
+            for result in results {
+                writeln!(out, "{}", result.file_path.display())?;
+            }
+        }
+        SearchOutput::Template(template) => {
+            for result in results {
+                writeln!(out, "{}", render_template(template, result))?;
+            }
+        }
+        SearchOutput::Interactive | SearchOutput::List => {}
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+// fills in {id}, {short_id}, {title}, {path}, {score}, {created}, {updated},
+// {tags}, {projects} and {snippet}. \t and \n are unescaped so templates can
+// be passed without shell quoting tricks; unknown placeholders are left alone
+pub fn render_template(template: &str, result: &SearchResult) -> String {
+    let placeholder_regex = Regex::new(r"\{(\w+)\}").unwrap();
+    let note = &result.note;
+
+    let rendered = placeholder_regex.replace_all(template, |cap: &regex::Captures| {
+        match &cap[1] {
+            "id" => note.id.to_string(),
+            "short_id" => crate::links::short_id(note.id),
+            "title" => note.title.clone().unwrap_or_else(|| "untitled".to_string()),
+            "path" => result.file_path.display().to_string(),
+            "score" => format!("{:.2}", result.score),
+            "created" => note.created.to_rfc3339(),
+            "updated" => note.updated.map(|updated| updated.to_rfc3339()).unwrap_or_default(),
+            "tags" => note.tags.join(","),
+            "projects" => note.projects.join(","),
+            "snippet" => result.content_snippets.first().map(|snippet| snippet.text.clone()).unwrap_or_default(),
+            _ => cap[0].to_string(),
+        }
+    });
+
+    rendered.replace("\\t", "\t").replace("\\n", "\n")
+}
+
 fn display_search_results_advanced(results: &[SearchResult], options: &SearchOptions) -> Result<(), StoreError> {
-    let _term = Term::stdout();
     let title_style = Style::new().bold().cyan();
     let snippet_style = Style::new().dim();
     let match_style = Style::new().bold().yellow();
     let tag_style = Style::new().bold().blue();
     let project_style = Style::new().bold().green();
-    let prompt_style = Style::new().bold().magenta();
 
     println!("\n{} found {} note(s):",
         match_style.apply_to("🔍"),
@@ -468,7 +701,7 @@ fn display_search_results_advanced(results: &[SearchResult], options: &SearchOpt
             );
         }
 
-        let projects = extract_projects(&result.note.content);
+        let projects = &result.note.projects;
         if !projects.is_empty() {
             println!("   all projects: {}",
                 projects.iter().map(|p| project_style.apply_to(format!("+{}", p)).to_string()).collect::<Vec<_>>().join(" ")
@@ -478,6 +711,12 @@ fn display_search_results_advanced(results: &[SearchResult], options: &SearchOpt
         println!();
     }
 
+    Ok(())
+}
+
+fn open_results_interactively(results: &[SearchResult]) -> Result<(), StoreError> {
+    let prompt_style = Style::new().bold().magenta();
+
     loop {
         print!("{}", prompt_style.apply_to("enter note number to open, 'h' for help, or 'q' to quit: "));
         io::stdout().flush()?;
@@ -524,7 +763,7 @@ fn display_interactive_help() {
     println!();
 }
 
-fn display_note_content_advanced(note: &Note, file_path: &PathBuf) -> Result<(), StoreError> {
+fn display_note_content_advanced(note: &Note, file_path: &Path) -> Result<(), StoreError> {
     let term = Term::stdout();
     let title_style = Style::new().bold().cyan();
     let content_style = Style::new().white();
@@ -546,7 +785,7 @@ fn display_note_content_advanced(note: &Note, file_path: &PathBuf) -> Result<(),
         );
     }
 
-    let projects = extract_projects(&note.content);
+    let projects = &note.projects;
     if !projects.is_empty() {
         println!("{} {}",
             separator_style.apply_to("📁 projects:"),
@@ -570,94 +809,82 @@ fn display_note_content_advanced(note: &Note, file_path: &PathBuf) -> Result<(),
     Ok(())
 }
 
-
-
 pub fn save_quick_note(content: String, title: Option<String>) -> Result<(), StoreError> {
-    let stash_dir = get_stash_notes_dir()?;
-    ensure_directory_exists(&stash_dir)?;
-
-    let note_id = Uuid::new_v4();
-    let tags = extract_tags(&content);
-    let projects = extract_projects(&content);
-    let links_to = extract_links(&content);
-    let created = Utc::now();
-
-    let frontmatter = create_frontmatter(&note_id, &title, &tags, &projects, &links_to, &created);
-    let file_content = format!("{}\n{}", frontmatter, content);
-
-    let file_path = stash_dir.join(format!("{}.md", note_id));
-    fs::write(file_path, file_content)?;
-
+    let mut repository = FileRepository::open()?;
+    repository.create(new_note(content, title, NoteSource::QuickCapture))?;
     Ok(())
 }
 
-fn get_stash_notes_dir() -> Result<PathBuf, StoreError> {
-    let home = dirs::home_dir().ok_or(StoreError::HomeNotFound)?;
-    Ok(home.join(".stash").join("notes"))
+pub fn new_note(content: String, title: Option<String>, source: NoteSource) -> Note {
+    let mut note = Note {
+        id: Uuid::new_v4(),
+        title,
+        tags: Vec::new(),
+        projects: Vec::new(),
+        explicit_tags: Vec::new(),
+        explicit_projects: Vec::new(),
+        links_to: Vec::new(),
+        unresolved_links: Vec::new(),
+        created: Utc::now(),
+        updated: None,
+        deleted_at: None,
+        source,
+        content,
+    };
+    note.merge_metadata();
+    note
 }
 
-fn ensure_directory_exists(path: &PathBuf) -> Result<(), StoreError> {
-    fs::create_dir_all(path)?;
-    Ok(())
+// a titled quick capture note, the starting point for most tests
+#[cfg(test)]
+pub fn test_note(title: &str, content: &str) -> Note {
+    new_note(content.to_string(), Some(title.to_string()), NoteSource::QuickCapture)
 }
 
-pub fn extract_tags(content: &str) -> Vec<String> {
-    let tag_regex = Regex::new(r"#(\w+)").unwrap();
-    tag_regex
-        .captures_iter(content)
-        .map(|cap| cap[1].to_string())
-        .collect()
-}
-
-pub fn extract_projects(content: &str) -> Vec<String> {
-    let project_regex = Regex::new(r"\+(\w+)").unwrap();
-    project_regex
-        .captures_iter(content)
-        .map(|cap| cap[1].to_string())
-        .collect()
-}
-
-fn extract_links(content: &str) -> Vec<String> {
-    let link_regex = Regex::new(r"\[\[([^\]]+)\]\]").unwrap();
-    link_regex
-        .captures_iter(content)
-        .map(|cap| cap[1].to_string())
-        .collect()
-}
-
-fn create_frontmatter(
-    id: &Uuid,
-    title: &Option<String>,
-    tags: &[String],
-    projects: &[String],
-    links_to: &[String],
-    created: &DateTime<Utc>,
-) -> String {
-    let tags_yaml = if tags.is_empty() {
-        "[]".to_string()
-    } else {
-        format!("[{}]", tags.iter().map(|t| format!("\"{}\"", t)).collect::<Vec<_>>().join(", "))
-    };
+pub fn get_stash_notes_dir() -> Result<PathBuf, StoreError> {
+    Ok(vault::notes_dir()?)
+}
 
-    let projects_yaml = if projects.is_empty() {
-        "[]".to_string()
-    } else {
-        format!("[{}]", projects.iter().map(|p| format!("\"{}\"", p)).collect::<Vec<_>>().join(", "))
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(note: Note) -> SearchResult {
+        SearchResult {
+            file_path: PathBuf::from(format!("/stash/notes/{}.md", note.id)),
+            note,
+            score: 7.0,
+            title_match: true,
+            content_snippets: vec![Snippet { line: 2, text: "the borrow checker".to_string() }],
+            tag_matches: vec!["rust".to_string()],
+            project_matches: Vec::new(),
+        }
+    }
 
-    let links_yaml = if links_to.is_empty() {
-        "[]".to_string()
-    } else {
-        format!("[{}]", links_to.iter().map(|l| format!("\"{}\"", l)).collect::<Vec<_>>().join(", "))
-    };
+    #[test]
+    fn templates_fill_in_known_placeholders_only() {
+        let result = result(test_note("Lifetimes", "notes on\nthe borrow checker #rust +compiler"));
 
-    let title_yaml = match title {
-        Some(t) => format!("\"{}\"", t),
-        None => "null".to_string(),
-    };
+        assert_eq!(render_template("{title}\\t{tags}\\t{projects}", &result), "Lifetimes\trust\tcompiler");
+        assert_eq!(render_template("{path}:{score}", &result), format!("/stash/notes/{}.md:7.00", result.note.id));
+        assert_eq!(render_template("{short_id} {snippet}", &result), format!("{} the borrow checker", crate::links::short_id(result.note.id)));
+        assert_eq!(render_template("{updated}|{nope}", &result), "|{nope}");
+    }
 
-    format!(
-        "---\nid: {}\ntitle: {}\ntags: {}\nprojects: {}\nlinks_to: {}\ncreated: {}\nupdated: null\nsource: \"QuickCapture\"\n---",
-        id, title_yaml, tags_yaml, projects_yaml, links_yaml, created.format("%Y-%m-%dT%H:%M:%S%.3fZ")
-    )
-}
\ No newline at end of file
+    #[test]
+    fn json_records_carry_the_note_and_match_details() {
+        let result = result(test_note("Lifetimes", "notes on\nthe borrow checker #rust +compiler"));
+        let record = serde_json::to_value(SearchResultRecord::from(&result)).unwrap();
+
+        assert_eq!(record["id"], result.note.id.to_string());
+        assert_eq!(record["title"], "Lifetimes");
+        assert_eq!(record["path"], format!("/stash/notes/{}.md", result.note.id));
+        assert_eq!(record["score"], 7.0);
+        assert_eq!(record["snippets"][0]["line"], 2);
+        assert_eq!(record["snippets"][0]["text"], "the borrow checker");
+        assert_eq!(record["tag_matches"], serde_json::json!(["rust"]));
+        assert_eq!(record["tags"], serde_json::json!(["rust"]));
+        assert_eq!(record["projects"], serde_json::json!(["compiler"]));
+        assert!(record["updated"].is_null());
+    }
+}
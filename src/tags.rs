@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+use crate::models::Note;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerKind {
+    Tag,
+    Project,
+}
+
+impl MarkerKind {
+    pub fn sigil(self) -> char {
+        match self {
+            MarkerKind::Tag => '#',
+            MarkerKind::Project => '+',
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MarkerKind::Tag => "tag",
+            MarkerKind::Project => "project",
+        }
+    }
+}
+
+// an inline #tag or +project in note content. `range` covers the sigil and
+// the name, as byte offsets into the content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub kind: MarkerKind,
+    pub name: String,
+    pub range: Range<usize>,
+}
+
+// every #tag and +project in the prose of a markdown document. code spans,
+// code blocks, links, images and html are skipped, and a marker has to start
+// a word, so `C#`, `C++`, `a+b` and `page#section` don't count. names may use
+// letters in any script, digits, `_`, `-` and `/`, but can't be all digits,
+// which keeps issue numbers like #123 out
+pub fn markers(content: &str) -> Vec<Marker> {
+    let mut markers = Vec::new();
+    let mut skip_depth = 0usize;
+    let mut run: Option<Range<usize>> = None;
+
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+
+    for (event, range) in Parser::new_ext(content, options).into_offset_iter() {
+        match event {
+            // the parser splits text around things like `_` and `[`, so
+            // neighbouring text events are scanned as one run
+            Event::Text(_) if skip_depth == 0 => {
+                run = match run {
+                    Some(current) if current.end == range.start => Some(current.start..range.end),
+                    Some(current) => {
+                        scan(content, current, &mut markers);
+                        Some(range)
+                    }
+                    None => Some(range),
+                };
+                continue;
+            }
+            Event::Start(Tag::CodeBlock(_) | Tag::Link { .. } | Tag::Image { .. } | Tag::HtmlBlock) => skip_depth += 1,
+            Event::End(TagEnd::CodeBlock | TagEnd::Link | TagEnd::Image | TagEnd::HtmlBlock) => {
+                skip_depth = skip_depth.saturating_sub(1)
+            }
+            _ => {}
+        }
+
+        if let Some(current) = run.take() {
+            scan(content, current, &mut markers);
+        }
+    }
+
+    if let Some(current) = run {
+        scan(content, current, &mut markers);
+    }
+
+    markers
+}
+
+pub fn extract_tags(content: &str) -> Vec<String> {
+    names(content, MarkerKind::Tag)
+}
+
+pub fn extract_projects(content: &str) -> Vec<String> {
+    names(content, MarkerKind::Project)
+}
+
+// distinct names of one kind, in order of first appearance
+fn names(content: &str, kind: MarkerKind) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for marker in markers(content) {
+        if marker.kind == kind && !names.contains(&marker.name) {
+            names.push(marker.name);
+        }
+    }
+    names
+}
+
+fn scan(content: &str, range: Range<usize>, markers: &mut Vec<Marker>) {
+    let text = &content[range.clone()];
+    let mut previous: Option<char> = content[..range.start].chars().next_back();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        let kind = match c {
+            '#' => Some(MarkerKind::Tag),
+            '+' => Some(MarkerKind::Project),
+            _ => None,
+        };
+
+        if let Some(kind) = kind.filter(|_| starts_word(previous)) {
+            let rest = &text[offset + 1..];
+            let name = marker_name(rest);
+
+            if !name.is_empty() && !name.chars().all(|c| c.is_ascii_digit()) {
+                let start = range.start + offset;
+                markers.push(Marker {
+                    kind,
+                    name: name.to_string(),
+                    range: start..start + 1 + name.len(),
+                });
+
+                while chars.peek().is_some_and(|(next, _)| *next < offset + 1 + name.len()) {
+                    chars.next();
+                }
+                previous = name.chars().next_back();
+                continue;
+            }
+        }
+
+        previous = Some(c);
+    }
+}
+
+// a marker follows whitespace, opening punctuation or emphasis, never a word or an escape
+fn starts_word(previous: Option<char>) -> bool {
+    match previous {
+        None => true,
+        Some(c) => c.is_whitespace() || matches!(c, '(' | '[' | '{' | '"' | '\'' | ',' | ';' | '|' | '*' | '~'),
+    }
+}
+
+// whether `name` would be read back as a whole #tag or +project name
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && marker_name(name) == name && !name.chars().all(|c| c.is_ascii_digit())
+}
+
+// the longest run of name characters, without trailing `-` or `/` so that
+// "see #ci-cd/" or "#rust-" at the end of a phrase keep just the name
+fn marker_name(text: &str) -> &str {
+    let end = text
+        .char_indices()
+        .find(|(_, c)| !is_name_char(*c))
+        .map_or(text.len(), |(i, _)| i);
+
+    let name = text[..end].trim_end_matches(['-', '/']);
+    if name.starts_with(['-', '/']) {
+        return "";
+    }
+    name
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '/')
+}
+
+// true when `name` is `ancestor` or nested under it, so #lang/rust covers
+// #lang/rust/async but not #lang/rustic. components are compared with unicode
+// lowercasing so #Café sits under #café
+pub fn is_within(name: &str, ancestor: &str) -> bool {
+    let mut parts = name.split('/');
+    ancestor.trim_end_matches('/').split('/').all(|wanted| parts.next().is_some_and(|part| part.to_lowercase() == wanted.to_lowercase()))
+}
+
+// "a/b/c" -> "a", "a/b", "a/b/c"
+pub fn ancestors(name: &str) -> impl Iterator<Item = &str> {
+    name.match_indices('/')
+        .map(move |(i, _)| &name[..i])
+        .chain(std::iter::once(name))
+}
+
+// one level of a tag or project hierarchy. `count` is the number of notes
+// tagged with this path or anything under it, each note counted once
+#[derive(Debug, Clone)]
+pub struct TagNode {
+    pub name: String,
+    pub path: String,
+    pub count: usize,
+    pub children: Vec<TagNode>,
+}
+
+// builds the hierarchy from each note's tags (or projects), busiest first
+pub fn tree<'a>(notes: impl IntoIterator<Item = &'a [String]>) -> Vec<TagNode> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+
+    for names in notes {
+        let paths: BTreeSet<&str> = names.iter().flat_map(|name| ancestors(name)).collect();
+        for path in paths {
+            *counts.entry(path.to_string()).or_default() += 1;
+        }
+    }
+
+    let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+    for path in counts.keys() {
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        children.entry(parent).or_default().push(path);
+    }
+
+    build_level("", &counts, &children)
+}
+
+fn build_level(parent: &str, counts: &BTreeMap<String, usize>, children: &HashMap<&str, Vec<&str>>) -> Vec<TagNode> {
+    let mut nodes: Vec<TagNode> = children.get(parent).into_iter().flatten().map(|path| TagNode {
+        name: path.rsplit('/').next().unwrap_or(path).to_string(),
+        path: path.to_string(),
+        count: counts[*path],
+        children: build_level(path, counts, children),
+    }).collect();
+
+    nodes.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(&b.name)));
+    nodes
+}
+
+// every tag and project in use across live notes
+#[derive(Debug, Clone, Default)]
+pub struct Vocabulary {
+    pub tags: Vec<String>,
+    pub projects: Vec<String>,
+}
+
+impl Vocabulary {
+    pub fn from_notes<'a>(notes: impl IntoIterator<Item = &'a Note>) -> Self {
+        let (mut tags, mut projects) = (BTreeSet::new(), BTreeSet::new());
+        for note in notes.into_iter().filter(|note| !note.is_deleted()) {
+            tags.extend(note.tags.iter().cloned());
+            projects.extend(note.projects.iter().cloned());
+        }
+
+        Self {
+            tags: tags.into_iter().collect(),
+            projects: projects.into_iter().collect(),
+        }
+    }
+
+    pub fn tag(&self, word: &str) -> Option<&str> {
+        lookup(&self.tags, word)
+    }
+
+    pub fn project(&self, word: &str) -> Option<&str> {
+        lookup(&self.projects, word)
+    }
+}
+
+// a name matching `word` outright, or else the only one whose last segment
+// does, so "rust" finds #lang/rust
+fn lookup<'a>(names: &'a [String], word: &str) -> Option<&'a str> {
+    if let Some(name) = names.iter().find(|name| name.eq_ignore_ascii_case(word)) {
+        return Some(name);
+    }
+
+    let mut nested = names.iter().filter(|name| {
+        name.rsplit_once('/').is_some_and(|(_, last)| last.eq_ignore_ascii_case(word))
+    });
+    match (nested.next(), nested.next()) {
+        (Some(name), None) => Some(name),
+        _ => None,
+    }
+}
+
+// the nodes to show, depth first, descending only into nodes `open` allows
+pub fn visible<'a>(nodes: &'a [TagNode], open: &dyn Fn(&TagNode) -> bool) -> Vec<(usize, &'a TagNode)> {
+    let mut rows = Vec::new();
+    collect_visible(nodes, 0, open, &mut rows);
+    rows
+}
+
+fn collect_visible<'a>(nodes: &'a [TagNode], depth: usize, open: &dyn Fn(&TagNode) -> bool, rows: &mut Vec<(usize, &'a TagNode)>) {
+    for node in nodes {
+        rows.push((depth, node));
+        if open(node) {
+            collect_visible(&node.children, depth + 1, open, rows);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_tags_and_projects_in_prose() {
+        let content = "planning #rust work for +client/acme, see (#lang/go) and *#bold*";
+
+        assert_eq!(extract_tags(content), vec!["rust", "lang/go", "bold"]);
+        assert_eq!(extract_projects(content), vec!["client/acme"]);
+    }
+
+    #[test]
+    fn ranges_cover_the_sigil_and_name() {
+        let content = "a #rust note";
+        let found = markers(content);
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(&content[found[0].range.clone()], "#rust");
+        assert_eq!(found[0].kind, MarkerKind::Tag);
+    }
+
+    #[test]
+    fn skips_code_spans_and_fenced_blocks() {
+        let content = "#real and `#span`\n\n```\n#fenced +block\n```\n\n    #indented\n";
+
+        assert_eq!(extract_tags(content), vec!["real"]);
+        assert!(extract_projects(content).is_empty());
+    }
+
+    #[test]
+    fn skips_links_and_url_fragments() {
+        let content = "[#linked](https://example.com) https://example.com/page#section <https://example.com/#top>";
+
+        assert!(extract_tags(content).is_empty());
+    }
+
+    #[test]
+    fn skips_issue_numbers_languages_and_emails() {
+        let content = "fixes #123, written in C# and C++, mail me+stash@example.com or a+b";
+
+        assert!(extract_tags(content).is_empty());
+        assert!(extract_projects(content).is_empty());
+    }
+
+    #[test]
+    fn keeps_nested_names_without_trailing_separators() {
+        assert_eq!(extract_tags("#a/b/c and #ci-cd/ and #rust-"), vec!["a/b/c", "ci-cd", "rust"]);
+        assert_eq!(ancestors("a/b/c").collect::<Vec<_>>(), vec!["a", "a/b", "a/b/c"]);
+    }
+
+    #[test]
+    fn finds_non_ascii_names() {
+        let content = "coffee at #café, notes in #日本語/文法 and +東京/旅行";
+
+        assert_eq!(extract_tags(content), vec!["café", "日本語/文法"]);
+        assert_eq!(extract_projects(content), vec!["東京/旅行"]);
+    }
+
+    #[test]
+    fn nested_names_are_within_their_ancestors() {
+        assert!(is_within("lang/rust/async", "lang/rust"));
+        assert!(is_within("Lang/Rust", "lang/"));
+        assert!(!is_within("lang/rustic", "lang/rust"));
+        assert!(!is_within("lang", "lang/rust"));
+        assert!(is_within("Café/Paris", "café"));
+        assert!(is_within("CAFÉ", "café"));
+        assert!(!is_within("cafés", "café"));
+        assert!(is_within("日本語/文法", "日本語"));
+    }
+
+    #[test]
+    fn trees_count_each_note_once_per_level() {
+        let notes = [vec!["lang/rust".to_string(), "lang/rust/async".to_string()], vec!["lang/go".to_string()], vec!["cli".to_string()]];
+        let nodes = tree(notes.iter().map(Vec::as_slice));
+        assert_eq!(nodes.iter().map(|node| (node.path.as_str(), node.count)).collect::<Vec<_>>(), vec![("lang", 2), ("cli", 1)]);
+
+        let rows = visible(&nodes, &|node| node.path == "lang");
+        assert_eq!(rows.iter().map(|(depth, node)| (*depth, node.path.as_str())).collect::<Vec<_>>(), vec![(0, "lang"), (1, "lang/go"), (1, "lang/rust"), (0, "cli")]);
+    }
+
+    #[test]
+    fn validates_names() {
+        assert!(is_valid_name("lang/rust"));
+        assert!(is_valid_name("café"));
+        assert!(is_valid_name("日本語/文法"));
+        assert!(!is_valid_name("123"));
+        assert!(!is_valid_name("two words"));
+        assert!(!is_valid_name("/leading"));
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::{Duration, Utc};
+use console::Style;
+
+use crate::dates;
+use crate::links::{self, short_id};
+use crate::repository::{FileRepository, NoteRepository};
+use crate::store::StoreError;
+
+pub fn list_trash() -> Result<(), StoreError> {
+    let mut repository = FileRepository::open()?;
+    let trashed = repository.trashed()?;
+    let title_style = Style::new().bold();
+    let dim_style = Style::new().dim();
+
+    if trashed.is_empty() {
+        println!("trash is empty");
+        return Ok(());
+    }
+
+    println!("\n{} {} note(s) in trash:", Style::new().bold().red().apply_to("🗑️"), trashed.len());
+    println!("{}", "─".repeat(50));
+
+    for note in &trashed {
+        let trashed_at = note.trashed_at()
+            .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+
+        println!("  {} {} {}",
+            dim_style.apply_to(short_id(note.id)),
+            title_style.apply_to(note.title.as_deref().unwrap_or("untitled")),
+            dim_style.apply_to(format!("(deleted {})", trashed_at))
+        );
+    }
+
+    println!("\n💡 restore with: stash trash restore <id>");
+    Ok(())
+}
+
+pub fn restore_from_trash(query: &str) -> Result<(), StoreError> {
+    let mut repository = FileRepository::open()?;
+    let trashed = repository.trashed()?;
+    let note = links::find_note(&trashed, query)?;
+
+    repository.restore(note.id)?;
+    println!("restored '{}'", note.title.as_deref().unwrap_or("untitled"));
+    Ok(())
+}
+
+pub fn empty_trash(older_than: Option<&str>) -> Result<(), StoreError> {
+    let cutoff = match older_than {
+        Some(age) => Some(dates::ago(parse_age(age)?, Utc::now()).ok_or_else(|| StoreError::InvalidAge(age.to_string()))?),
+        None => None,
+    };
+
+    let mut repository = FileRepository::open()?;
+    let purged = repository.empty_trash(cutoff)?;
+    println!("permanently deleted {} note(s)", purged);
+    Ok(())
+}
+
+// parses ages like "30d", "2w" or "12h"
+pub fn parse_age(age: &str) -> Result<Duration, StoreError> {
+    dates::parse_duration(age.trim()).ok_or_else(|| StoreError::InvalidAge(age.to_string()))
+}
@@ -1,7 +1,9 @@
+use std::collections::HashSet;
 use std::io;
-use std::fs;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyEventKind},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -12,14 +14,27 @@ use ratatui::{
 };
 use tui_textarea::TextArea;
 
-use crate::models::Note;
-use crate::store;
+use crate::models::{Note, NoteSource};
+use crate::store::{self, StoreError};
 use crate::config::Config;
 use crate::ai::AiClient;
-use super::state::{AppMode, EditorMode, ActiveField, AiState};
+use crate::ask::{self, Source};
+use crate::enrich::{self, Suggestion};
+use crate::history::{self, Revision};
+use crate::hunks::{self, Hunk};
+use crate::links::{self, LinkTarget};
+use crate::prompts::{self, Prompt};
+use crate::query::{self, TextMatch};
+use crate::region::{self, Section};
+use crate::retag::{self, Retag};
+use crate::tags::{self, MarkerKind, TagNode, Vocabulary};
+use crate::repository::{Change, DeletionType, FileRepository, NoteRepository};
+use super::state::{AppMode, EditorMode, ActiveField, AiState, LinkDirection, RetagPrompt, RewriteTarget};
 use super::handlers::InputHandler;
 use super::components::Renderer;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
 
 pub struct App {
     pub mode: AppMode,
@@ -36,11 +51,41 @@ pub struct App {
     pub status_message: Option<String>,
     pub extracted_tags: Vec<String>,
     pub extracted_projects: Vec<String>,
+    // tags and projects assigned in the editor rather than written in the content
+    pub explicit_tags: Vec<String>,
+    pub explicit_projects: Vec<String>,
+    pub metadata_input: String,
     pub config: Config,
     pub ai_client: Option<AiClient>,
     pub ai_state: AiState,
     pub api_key_input: String,
     pub ai_result_receiver: Option<mpsc::UnboundedReceiver<Result<String, String>>>,
+    // the rewrite as it streams in, and the task writing it so esc can stop it
+    pub ai_token_receiver: Option<mpsc::UnboundedReceiver<String>>,
+    pub ai_task: Option<JoinHandle<()>>,
+    pub ai_streamed: String,
+    pub ai_token_count: usize,
+    pub ai_started: Option<Instant>,
+    pub ai_elapsed: Option<Duration>,
+    // the finished rewrite split into hunks, each accepted or rejected on its own
+    pub rewrite_hunks: Vec<Hunk>,
+    pub accepted_hunks: Vec<bool>,
+    pub selected_hunk: usize,
+    // the part of the text being rewritten, None for all of it
+    pub rewrite_region: Option<Range<usize>>,
+    // where an editor rewrite goes back to, the add or edit screen
+    pub rewrite_return: Option<AppMode>,
+    // picking a heading section of the viewed note to rewrite
+    pub note_sections: Vec<Section>,
+    pub section_picker: Option<usize>,
+    // picking a prompt to run, and what it will run on
+    pub prompts: Vec<Prompt>,
+    pub selected_prompt: usize,
+    pub prompt_target: Option<RewriteTarget>,
+    pub rewrite_prompt_name: String,
+    // the exact request the selected prompt would send, before it's sent
+    pub payload_preview: Option<String>,
+    pub preview_scroll: u16,
     pub prompt_style_index: usize,
     pub custom_prompt_input: String,
     pub search_input: String,
@@ -50,16 +95,58 @@ pub struct App {
     pub current_tag_filter: Option<String>,
     pub current_project_filter: Option<String>,
     pub deletion_preference: DeletionType,
+    pub repository: Box<dyn NoteRepository>,
+    pub selected_link: usize,
+    pub view_history: Vec<Uuid>,
+    // the links and backlinks of the viewed note, worked out when it is
+    // opened or the notes are reloaded instead of on every frame
+    pub view_links: Option<(Uuid, Vec<(LinkDirection, Uuid)>)>,
+    pub trashed_notes: Vec<Note>,
+    pub selected_trash: usize,
+    // a trashed note waiting on y/n before it's deleted for good
+    pub purge_confirm: Option<Uuid>,
+    pub revisions: Vec<Revision>,
+    pub selected_revision: usize,
+    // the tag or project tree on the filter screens. no row is selected until
+    // one is picked or typed for, so enter on its own clears the filter
+    pub expanded_filter_paths: HashSet<String>,
+    pub selected_filter_row: Option<usize>,
+    pub retag_prompt: Option<RetagPrompt>,
+    // asking a question of the notes
+    pub ask_input: String,
+    pub ask_sources: Vec<Source>,
+    pub ask_answer: Option<String>,
+    pub ask_cited: Vec<usize>,
+    pub selected_source: usize,
+    pub ask_receiver: Option<mpsc::UnboundedReceiver<Result<String, String>>>,
+    // a note opened from an answer goes back to the answer when closed
+    pub return_to_ask: bool,
+    // ai suggestions for the note in the editor, accepted or dismissed one by one
+    pub suggestions: Vec<Suggestion>,
+    pub selected_suggestion: usize,
+    pub suggestion_receiver: Option<mpsc::UnboundedReceiver<Result<Vec<Suggestion>, String>>>,
 }
 
+// a line of the tag or project tree on the filter screens
 #[derive(Debug, Clone)]
-pub enum DeletionType {
-    Soft,
-    Hard,
+pub struct FilterRow {
+    pub depth: usize,
+    pub path: String,
+    pub name: String,
+    pub count: usize,
+    pub has_children: bool,
+    pub expanded: bool,
 }
 
-impl Default for App {
-    fn default() -> App {
+impl App {
+    pub fn new() -> Result<App, StoreError> {
+        let repository = FileRepository::open()?;
+        let mut app = App::with_repository(Box::new(repository));
+        app.load_existing_notes();
+        Ok(app)
+    }
+
+    pub fn with_repository(repository: Box<dyn NoteRepository>) -> App {
         let content_editor = TextArea::default();
         let mut notes_list_state = ListState::default();
         notes_list_state.select(Some(0));
@@ -88,11 +175,33 @@ impl Default for App {
             status_message: None,
             extracted_tags: Vec::new(),
             extracted_projects: Vec::new(),
+            explicit_tags: Vec::new(),
+            explicit_projects: Vec::new(),
+            metadata_input: String::new(),
             config,
             ai_client,
             ai_state: AiState::Idle,
             api_key_input: String::new(),
             ai_result_receiver: None,
+            ai_token_receiver: None,
+            ai_task: None,
+            ai_streamed: String::new(),
+            ai_token_count: 0,
+            ai_started: None,
+            ai_elapsed: None,
+            rewrite_hunks: Vec::new(),
+            accepted_hunks: Vec::new(),
+            selected_hunk: 0,
+            rewrite_region: None,
+            rewrite_return: None,
+            note_sections: Vec::new(),
+            section_picker: None,
+            prompts: Vec::new(),
+            selected_prompt: 0,
+            prompt_target: None,
+            rewrite_prompt_name: String::new(),
+            payload_preview: None,
+            preview_scroll: 0,
             prompt_style_index,
             custom_prompt_input: String::new(),
             search_input: String::new(),
@@ -102,37 +211,46 @@ impl Default for App {
             current_tag_filter: None,
             current_project_filter: None,
             deletion_preference: DeletionType::Soft,
+            repository,
+            selected_link: 0,
+            view_history: Vec::new(),
+            view_links: None,
+            trashed_notes: Vec::new(),
+            selected_trash: 0,
+            purge_confirm: None,
+            revisions: Vec::new(),
+            selected_revision: 0,
+            expanded_filter_paths: HashSet::new(),
+            selected_filter_row: None,
+            retag_prompt: None,
+            ask_input: String::new(),
+            ask_sources: Vec::new(),
+            ask_answer: None,
+            ask_cited: Vec::new(),
+            selected_source: 0,
+            ask_receiver: None,
+            return_to_ask: false,
+            suggestions: Vec::new(),
+            selected_suggestion: 0,
+            suggestion_receiver: None,
         }
     }
-}
-
-impl App {
-    pub fn new() -> App {
-        let mut app = App::default();
-        app.load_existing_notes();
-        app
-    }
 
     pub fn load_existing_notes(&mut self) {
         self.all_notes.clear();
-        if let Some(home) = dirs::home_dir() {
-            let notes_dir = home.join(".stash").join("notes");
-            if let Ok(entries) = fs::read_dir(notes_dir) {
-                for entry in entries.flatten() {
-                    if let Some(extension) = entry.path().extension() {
-                        if extension == "md" {
-                            match Note::load_from_file(entry.path()) {
-                                Ok(note) => {
-                                    self.all_notes.push(note);
-                                }
-                                Err(e) => {
-                                    eprintln!("Failed to load note {:?}: {}", entry.path(), e);
-                                }
-                            }
-                        }
-                    }
+        self.view_links = None;
+
+        match self.repository.load() {
+            Ok(loaded) => {
+                self.all_notes = loaded.notes;
+                self.all_notes.sort_by_key(|n| std::cmp::Reverse(n.created));
+
+                if !loaded.failures.is_empty() {
+                    self.status_message = Some(format!("failed to load {} note(s)", loaded.failures.len()));
                 }
-                self.all_notes.sort_by(|a, b| b.created.cmp(&a.created));
+            }
+            Err(e) => {
+                self.status_message = Some(format!("error loading notes: {}", e));
             }
         }
 
@@ -142,35 +260,47 @@ impl App {
     pub fn apply_filters(&mut self) {
         self.notes = self.all_notes.clone();
 
-        self.notes.retain(|note| {
-            !note.tags.contains(&"deleted".to_string())
-        });
+        self.notes.retain(|note| !note.is_deleted());
 
-        if let Some(ref search_term) = self.current_search {
-            if !search_term.trim().is_empty() {
-                self.notes.retain(|note| {
-                    let content_match = note.content.to_lowercase().contains(&search_term.to_lowercase());
-                    let title_match = note.title.as_ref()
-                        .map(|t| t.to_lowercase().contains(&search_term.to_lowercase()))
-                        .unwrap_or(false);
-                    content_match || title_match
-                });
-            }
+        let index = self.repository.search_index();
+
+        // the search box only accepts queries that parse, see handle_search_input
+        if let Some(query) = self.current_search.as_deref().and_then(|search| query::parse(search).ok()) {
+            let candidates = index.and_then(|index| query.candidates(index));
+            self.notes.retain(|note| {
+                if candidates.as_ref().is_some_and(|ids| !ids.contains(&note.id)) {
+                    return false;
+                }
+
+                query.matches(note, &note.projects, TextMatch::default())
+            });
         }
 
+        // a tag or project filter also takes in everything nested under it
         if let Some(ref tag_filter) = self.current_tag_filter {
-            if !tag_filter.trim().is_empty() {
+            let tag_filter = tag_filter.trim();
+            if !tag_filter.is_empty() {
+                let candidates = index.map(|index| index.with_tag(tag_filter));
                 self.notes.retain(|note| {
-                    note.tags.iter().any(|tag| tag.to_lowercase().contains(&tag_filter.to_lowercase()))
+                    if candidates.as_ref().is_some_and(|ids| !ids.contains(&note.id)) {
+                        return false;
+                    }
+
+                    note.tags.iter().any(|tag| tags::is_within(tag, tag_filter))
                 });
             }
         }
 
         if let Some(ref project_filter) = self.current_project_filter {
-            if !project_filter.trim().is_empty() {
+            let project_filter = project_filter.trim();
+            if !project_filter.is_empty() {
+                let candidates = index.map(|index| index.with_project(project_filter));
                 self.notes.retain(|note| {
-                    let projects = store::extract_projects(&note.content);
-                    projects.iter().any(|project| project.to_lowercase().contains(&project_filter.to_lowercase()))
+                    if candidates.as_ref().is_some_and(|ids| !ids.contains(&note.id)) {
+                        return false;
+                    }
+
+                    note.projects.iter().any(|project| tags::is_within(project, project_filter))
                 });
             }
         }
@@ -183,6 +313,137 @@ impl App {
         }
     }
 
+    // the tag tree on the tag filter screen, the project tree on the project
+    // one. typing narrows it to matching paths, shown fully expanded
+    pub fn filter_rows(&self) -> Vec<FilterRow> {
+        let (projects, needle) = match self.mode {
+            AppMode::ProjectFilter => (true, self.project_filter_input.trim().to_lowercase()),
+            _ => (false, self.tag_filter_input.trim().to_lowercase()),
+        };
+
+        let notes = self.all_notes.iter()
+            .filter(|note| !note.is_deleted())
+            .map(|note| if projects { note.projects.as_slice() } else { note.tags.as_slice() });
+        let tree = tags::tree(notes);
+
+        let open = |node: &TagNode| !needle.is_empty() || self.expanded_filter_paths.contains(&node.path);
+        tags::visible(&tree, &open)
+            .into_iter()
+            .filter(|(_, node)| needle.is_empty() || subtree_mentions(node, &needle))
+            .map(|(depth, node)| FilterRow {
+                depth,
+                path: node.path.clone(),
+                name: node.name.clone(),
+                count: node.count,
+                has_children: !node.children.is_empty(),
+                expanded: !node.children.is_empty() && open(node),
+            })
+            .collect()
+    }
+
+    // after typing, selects the first row that matches the text itself rather
+    // than an ancestor shown for context, or nothing once the text is gone
+    pub fn select_first_filter_match(&mut self) {
+        let needle = match self.mode {
+            AppMode::ProjectFilter => self.project_filter_input.trim().to_lowercase(),
+            _ => self.tag_filter_input.trim().to_lowercase(),
+        };
+
+        self.selected_filter_row = if needle.is_empty() {
+            None
+        } else {
+            self.filter_rows().iter().position(|row| row.path.to_lowercase().contains(&needle))
+        };
+    }
+
+    pub fn next_filter_row(&mut self) {
+        let rows = self.filter_rows().len();
+        if rows > 0 {
+            self.selected_filter_row = Some(self.selected_filter_row.map_or(0, |row| (row + 1) % rows));
+        }
+    }
+
+    pub fn previous_filter_row(&mut self) {
+        let rows = self.filter_rows().len();
+        if rows > 0 {
+            self.selected_filter_row = Some(self.selected_filter_row.map_or(rows - 1, |row| (row + rows - 1) % rows));
+        }
+    }
+
+    pub fn expand_filter_row(&mut self) {
+        if let Some(row) = self.selected_filter_row.and_then(|row| self.filter_rows().get(row).cloned()) {
+            if row.has_children {
+                self.expanded_filter_paths.insert(row.path.clone());
+            }
+        }
+    }
+
+    // collapses the selected row, or moves up to its parent if it's already closed
+    pub fn collapse_filter_row(&mut self) {
+        let rows = self.filter_rows();
+        let Some(row) = self.selected_filter_row.and_then(|row| rows.get(row)) else {
+            return;
+        };
+
+        if row.expanded {
+            self.expanded_filter_paths.remove(&row.path);
+        } else if let Some((parent, _)) = row.path.rsplit_once('/') {
+            if let Some(position) = rows.iter().position(|candidate| candidate.path == parent) {
+                self.selected_filter_row = Some(position);
+                self.expanded_filter_paths.remove(parent);
+            }
+        }
+    }
+
+    pub fn selected_filter_path(&self) -> Option<String> {
+        self.selected_filter_row.and_then(|row| self.filter_rows().get(row).map(|row| row.path.clone()))
+    }
+
+    // ctrl-r renames the selected tag or project (into an existing one merges
+    // them), ctrl-d deletes it
+    pub fn start_retag_prompt(&mut self, key: KeyCode) {
+        let Some(path) = self.selected_filter_path() else {
+            return;
+        };
+
+        self.retag_prompt = match key {
+            KeyCode::Char('r') => Some(RetagPrompt::Rename { input: path.clone(), path }),
+            KeyCode::Char('d') => Some(RetagPrompt::Delete { path }),
+            _ => None,
+        };
+    }
+
+    pub fn run_retag_prompt(&mut self) {
+        let Some(prompt) = self.retag_prompt.take() else {
+            return;
+        };
+
+        let kind = match self.mode {
+            AppMode::ProjectFilter => MarkerKind::Project,
+            _ => MarkerKind::Tag,
+        };
+
+        let retag = match prompt {
+            RetagPrompt::Rename { path, input } => Retag::rename(kind, &path, &input),
+            RetagPrompt::Delete { path } => Retag::delete(kind, &path),
+        };
+
+        let result = retag.and_then(|retag| {
+            retag::apply(self.repository.as_mut(), &retag).map(|count| (retag, count))
+        });
+
+        match result {
+            Ok((retag, count)) => {
+                self.status_message = Some(format!("{} in {} note{}", retag.description(), count, if count == 1 { "" } else { "s" }));
+                self.selected_filter_row = None;
+                self.load_existing_notes();
+            }
+            Err(e) => {
+                self.status_message = Some(format!("error: {}", e));
+            }
+        }
+    }
+
     pub fn clear_filters(&mut self) {
         self.current_search = None;
         self.current_tag_filter = None;
@@ -203,44 +464,242 @@ impl App {
     }
 
     pub fn soft_delete_note(&mut self, note_id: uuid::Uuid) {
-        if let Some(note) = self.all_notes.iter_mut().find(|n| n.id == note_id) {
-            if !note.tags.contains(&"deleted".to_string()) {
-                note.tags.push("deleted".to_string());
-                note.updated = Some(chrono::Utc::now());
-
-                if let Some(home) = dirs::home_dir() {
-                    let notes_dir = home.join(".stash").join("notes");
-                    let file_path = notes_dir.join(format!("{}.md", note.id));
-                    if let Err(e) = note.save_to_file(&file_path) {
-                        self.status_message = Some(format!("error saving note: {}", e));
-                        return;
-                    }
-                }
-
+        match self.repository.delete(note_id, DeletionType::Soft) {
+            Ok(()) => {
                 self.status_message = Some("note moved to trash (soft delete)".to_string());
                 self.load_existing_notes();
             }
+            Err(e) => {
+                self.status_message = Some(format!("error saving note: {}", e));
+            }
         }
         self.mode = AppMode::Home;
     }
 
     pub fn hard_delete_note(&mut self, note_id: uuid::Uuid) {
-        if let Some(home) = dirs::home_dir() {
-            let notes_dir = home.join(".stash").join("notes");
-            let filename = format!("{}.md", note_id);
-            let file_path = notes_dir.join(filename);
+        match self.repository.delete(note_id, DeletionType::Hard) {
+            Ok(()) => {
+                self.status_message = Some("note permanently deleted".to_string());
+                self.load_existing_notes();
+            }
+            Err(e) => {
+                self.status_message = Some(format!("error deleting note: {}", e));
+            }
+        }
+        self.mode = AppMode::Home;
+    }
+
+    pub fn view_note(&mut self, note_id: Uuid) {
+        self.view_history.clear();
+        self.selected_link = 0;
+        self.section_picker = None;
+        self.mode = AppMode::ViewNote(note_id);
+    }
+
+    // works out the links of `note_id` unless they are already known
+    pub fn refresh_note_links(&mut self, note_id: Uuid) {
+        if self.view_links.as_ref().is_some_and(|(id, _)| *id == note_id) {
+            return;
+        }
+
+        let Some(note) = self.all_notes.iter().find(|n| n.id == note_id) else {
+            self.view_links = Some((note_id, Vec::new()));
+            return;
+        };
+
+        let mut outgoing: Vec<Uuid> = note.links_to.clone();
+        for link in &note.unresolved_links {
+            if let LinkTarget::Found(id) = links::resolve_target(link, &self.all_notes) {
+                if !outgoing.contains(&id) {
+                    outgoing.push(id);
+                }
+            }
+        }
+
+        let mut entries: Vec<(LinkDirection, Uuid)> = outgoing.into_iter()
+            .filter(|id| self.all_notes.iter().any(|linked| linked.id == *id && !linked.is_deleted()))
+            .map(|id| (LinkDirection::Outgoing, id))
+            .collect();
+
+        entries.extend(
+            links::backlinks(&self.all_notes, note_id)
+                .into_iter()
+                .map(|linking| (LinkDirection::Incoming, linking.id))
+        );
+
+        self.view_links = Some((note_id, entries));
+    }
 
-            match fs::remove_file(&file_path) {
+    // outgoing links first, then backlinks. deleted notes are left out
+    pub fn note_links(&self, note_id: Uuid) -> Vec<(LinkDirection, &Note)> {
+        let Some((_, entries)) = self.view_links.as_ref().filter(|(id, _)| *id == note_id) else {
+            return Vec::new();
+        };
+
+        entries.iter()
+            .filter_map(|(direction, id)| self.all_notes.iter().find(|n| n.id == *id).map(|linked| (*direction, linked)))
+            .collect()
+    }
+
+    pub fn next_link(&mut self) {
+        if let AppMode::ViewNote(note_id) = self.mode {
+            self.refresh_note_links(note_id);
+            let count = self.note_links(note_id).len();
+            if count > 0 {
+                self.selected_link = (self.selected_link + 1) % count;
+            }
+        }
+    }
+
+    pub fn previous_link(&mut self) {
+        if let AppMode::ViewNote(note_id) = self.mode {
+            self.refresh_note_links(note_id);
+            let count = self.note_links(note_id).len();
+            if count > 0 {
+                self.selected_link = if self.selected_link == 0 { count - 1 } else { self.selected_link - 1 };
+            }
+        }
+    }
+
+    pub fn follow_selected_link(&mut self) {
+        if let AppMode::ViewNote(note_id) = self.mode {
+            self.refresh_note_links(note_id);
+            let target = self.note_links(note_id).get(self.selected_link).map(|(_, linked)| linked.id);
+            if let Some(target) = target {
+                self.view_history.push(note_id);
+                self.selected_link = 0;
+                self.mode = AppMode::ViewNote(target);
+            }
+        }
+    }
+
+    pub fn go_back_note(&mut self) {
+        match self.view_history.pop() {
+            Some(previous) => {
+                self.selected_link = 0;
+                self.mode = AppMode::ViewNote(previous);
+            }
+            None => self.status_message = Some("no previous note".to_string()),
+        }
+    }
+
+    pub fn open_trash(&mut self) {
+        match self.repository.trashed() {
+            Ok(trashed) => {
+                self.trashed_notes = trashed;
+                self.selected_trash = self.selected_trash.min(self.trashed_notes.len().saturating_sub(1));
+                self.mode = AppMode::Trash;
+            }
+            Err(e) => {
+                self.status_message = Some(format!("error loading trash: {}", e));
+            }
+        }
+    }
+
+    pub fn next_trashed_note(&mut self) {
+        if !self.trashed_notes.is_empty() {
+            self.selected_trash = (self.selected_trash + 1) % self.trashed_notes.len();
+        }
+    }
+
+    pub fn previous_trashed_note(&mut self) {
+        if !self.trashed_notes.is_empty() {
+            self.selected_trash = if self.selected_trash == 0 { self.trashed_notes.len() - 1 } else { self.selected_trash - 1 };
+        }
+    }
+
+    pub fn restore_selected_trash(&mut self) {
+        if let Some(note_id) = self.trashed_notes.get(self.selected_trash).map(|n| n.id) {
+            match self.repository.restore(note_id) {
+                Ok(()) => {
+                    self.status_message = Some("note restored".to_string());
+                    self.load_existing_notes();
+                    self.open_trash();
+                }
+                Err(e) => {
+                    self.status_message = Some(format!("error restoring note: {}", e));
+                }
+            }
+        }
+    }
+
+    pub fn confirm_purge_selected_trash(&mut self) {
+        self.purge_confirm = self.trashed_notes.get(self.selected_trash).map(|n| n.id);
+    }
+
+    pub fn purge_confirmed_trash(&mut self) {
+        if let Some(note_id) = self.purge_confirm.take() {
+            match self.repository.delete(note_id, DeletionType::Hard) {
                 Ok(()) => {
                     self.status_message = Some("note permanently deleted".to_string());
                     self.load_existing_notes();
+                    self.open_trash();
                 }
                 Err(e) => {
                     self.status_message = Some(format!("error deleting note: {}", e));
                 }
             }
         }
-        self.mode = AppMode::Home;
+    }
+
+    pub fn open_history(&mut self, note_id: Uuid) {
+        let revisions = match self.repository.history() {
+            Some(history) => history.revisions(note_id),
+            None => Ok(Vec::new()),
+        };
+
+        match revisions {
+            Ok(mut revisions) => {
+                // newest first, like the cli
+                revisions.reverse();
+                self.revisions = revisions;
+                self.selected_revision = 0;
+                self.mode = AppMode::History { note_id };
+            }
+            Err(e) => {
+                self.status_message = Some(format!("error loading history: {}", e));
+            }
+        }
+    }
+
+    pub fn next_revision(&mut self) {
+        if !self.revisions.is_empty() {
+            self.selected_revision = (self.selected_revision + 1) % self.revisions.len();
+        }
+    }
+
+    pub fn previous_revision(&mut self) {
+        if !self.revisions.is_empty() {
+            self.selected_revision = if self.selected_revision == 0 { self.revisions.len() - 1 } else { self.selected_revision - 1 };
+        }
+    }
+
+    pub fn restore_selected_revision(&mut self) {
+        let AppMode::History { note_id } = self.mode else {
+            return;
+        };
+        let Some(revision) = self.revisions.get(self.selected_revision).cloned() else {
+            return;
+        };
+        let Some(note) = self.all_notes.iter().find(|n| n.id == note_id).cloned() else {
+            return;
+        };
+
+        if history::same_version(&revision.note, &note) {
+            self.status_message = Some(format!("note already matches revision {}", revision.number));
+            return;
+        }
+
+        match history::revert_note(self.repository.as_mut(), &note, &revision) {
+            Ok(_) => {
+                self.status_message = Some(format!("restored revision {}", revision.number));
+                self.load_existing_notes();
+                self.mode = AppMode::ViewNote(note_id);
+            }
+            Err(e) => {
+                self.status_message = Some(format!("error restoring revision: {}", e));
+            }
+        }
     }
 
     pub fn toggle_deletion_preference(&mut self) {
@@ -270,8 +729,165 @@ impl App {
 
     pub fn update_extracted_metadata(&mut self) {
         let content = self.content_editor.lines().join("\n");
-        self.extracted_tags = crate::store::extract_tags(&content);
-        self.extracted_projects = crate::store::extract_projects(&content);
+        self.extracted_tags = tags::extract_tags(&content);
+        self.extracted_projects = tags::extract_projects(&content);
+    }
+
+    // applies the metadata field: `#tag` or a bare word adds a tag, `+project`
+    // adds a project, and a leading `-` removes either
+    pub fn apply_metadata_input(&mut self) {
+        let input = std::mem::take(&mut self.metadata_input);
+
+        for word in input.split_whitespace() {
+            let (remove, word) = match word.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, word),
+            };
+
+            let (list, name) = match word.strip_prefix('+') {
+                Some(project) => (&mut self.explicit_projects, project),
+                None => (&mut self.explicit_tags, word.strip_prefix('#').unwrap_or(word)),
+            };
+
+            if name.is_empty() {
+                continue;
+            }
+
+            if remove {
+                list.retain(|existing| existing != name);
+            } else if !list.iter().any(|existing| existing == name) {
+                list.push(name.to_string());
+            }
+        }
+    }
+
+    // the note as it stands in the editor, saved or not
+    fn draft_note(&self) -> Note {
+        let title = Some(self.title_input.clone()).filter(|title| !title.is_empty());
+        let mut note = store::new_note(self.content_editor.lines().join("\n"), title, NoteSource::UI);
+        if let AppMode::EditNote(note_id) = self.mode {
+            note.id = note_id;
+        }
+        note.explicit_tags = self.explicit_tags.clone();
+        note.explicit_projects = self.explicit_projects.clone();
+        note.merge_metadata();
+        note
+    }
+
+    // asks the model for a title, tags and projects, from the ones already in use
+    pub fn start_enrich(&mut self) {
+        if !self.ai_client.as_ref().is_some_and(|client| client.is_configured()) {
+            self.status_message = Some("please configure your openai api key first (press 's' for settings)".to_string());
+            return;
+        }
+
+        self.apply_metadata_input();
+        let note = self.draft_note();
+        if note.content.trim().is_empty() {
+            self.status_message = Some("write something first".to_string());
+            return;
+        }
+
+        let ai_client = match AiClient::new() {
+            Ok(client) => client,
+            Err(e) => {
+                self.status_message = Some(format!("failed to create ai client: {}", e));
+                return;
+            }
+        };
+
+        let vocabulary = Vocabulary::from_notes(&self.all_notes);
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.suggestion_receiver = Some(rx);
+        self.clear_suggestions();
+        self.status_message = Some("asking for suggestions...".to_string());
+
+        tokio::spawn(async move {
+            let result = ai_client.suggest_metadata(&note, &vocabulary).await.map_err(|e| e.to_string());
+            let _ = tx.send(result);
+        });
+    }
+
+    pub fn check_suggestion_result(&mut self) {
+        let Some(receiver) = &mut self.suggestion_receiver else {
+            return;
+        };
+
+        if let Ok(result) = receiver.try_recv() {
+            self.suggestion_receiver = None;
+
+            // the editor was closed while waiting
+            if !matches!(self.mode, AppMode::AddNote | AppMode::EditNote(_)) {
+                return;
+            }
+
+            match result {
+                Ok(suggestions) if suggestions.is_empty() => {
+                    self.status_message = Some("no suggestions for this note".to_string());
+                }
+                Ok(suggestions) => {
+                    self.suggestions = suggestions;
+                    self.selected_suggestion = 0;
+                }
+                Err(e) => self.status_message = Some(format!("error: {}", e)),
+            }
+        }
+    }
+
+    pub fn clear_suggestions(&mut self) {
+        self.suggestions.clear();
+        self.selected_suggestion = 0;
+    }
+
+    pub fn next_suggestion(&mut self) {
+        if !self.suggestions.is_empty() {
+            self.selected_suggestion = (self.selected_suggestion + 1) % self.suggestions.len();
+        }
+    }
+
+    pub fn previous_suggestion(&mut self) {
+        let count = self.suggestions.len();
+        if count > 0 {
+            self.selected_suggestion = if self.selected_suggestion == 0 { count - 1 } else { self.selected_suggestion - 1 };
+        }
+    }
+
+    pub fn accept_suggestion(&mut self) {
+        if self.selected_suggestion < self.suggestions.len() {
+            let suggestion = self.suggestions.remove(self.selected_suggestion);
+            self.use_suggestion(suggestion);
+            self.selected_suggestion = self.selected_suggestion.min(self.suggestions.len().saturating_sub(1));
+        }
+    }
+
+    pub fn dismiss_suggestion(&mut self) {
+        if self.selected_suggestion < self.suggestions.len() {
+            self.suggestions.remove(self.selected_suggestion);
+            self.selected_suggestion = self.selected_suggestion.min(self.suggestions.len().saturating_sub(1));
+        }
+    }
+
+    pub fn accept_all_suggestions(&mut self) {
+        for suggestion in std::mem::take(&mut self.suggestions) {
+            self.use_suggestion(suggestion);
+        }
+        self.selected_suggestion = 0;
+    }
+
+    fn use_suggestion(&mut self, suggestion: Suggestion) {
+        let note = enrich::apply(&self.draft_note(), std::slice::from_ref(&suggestion));
+        self.title_input = note.title.unwrap_or_default();
+        self.explicit_tags = note.explicit_tags;
+        self.explicit_projects = note.explicit_projects;
+    }
+
+    // forgets the tags and projects of the note that was being written
+    pub fn reset_metadata_fields(&mut self) {
+        self.extracted_tags.clear();
+        self.extracted_projects.clear();
+        self.explicit_tags.clear();
+        self.explicit_projects.clear();
+        self.metadata_input.clear();
     }
 
     pub fn start_new_note(&mut self) {
@@ -280,11 +896,12 @@ impl App {
         self.active_field = ActiveField::Content;
         self.content_editor = tui_textarea::TextArea::default();
         self.title_input.clear();
-        self.extracted_tags.clear();
-        self.extracted_projects.clear();
+        self.reset_metadata_fields();
+        self.clear_suggestions();
     }
 
     pub fn save_note(&mut self) {
+        self.apply_metadata_input();
         let content = self.content_editor.lines().join("\n");
         if !content.trim().is_empty() {
             let title = if self.title_input.is_empty() {
@@ -293,16 +910,20 @@ impl App {
                 Some(self.title_input.clone())
             };
 
-            match store::save_quick_note(content, title) {
-                Ok(()) => {
+            let mut note = store::new_note(content, title, NoteSource::UI);
+            note.explicit_tags = self.explicit_tags.clone();
+            note.explicit_projects = self.explicit_projects.clone();
+            note.merge_metadata();
+
+            match self.repository.create(note) {
+                Ok(_) => {
                     self.status_message = Some("note saved successfully".to_string());
                     self.load_existing_notes();
                     self.mode = AppMode::Home;
                     self.editor_mode = EditorMode::Command;
                     self.content_editor = TextArea::default();
                     self.title_input.clear();
-                    self.extracted_tags.clear();
-                    self.extracted_projects.clear();
+                    self.reset_metadata_fields();
                 }
                 Err(e) => {
                     self.status_message = Some(format!("error saving note: {}", e));
@@ -314,13 +935,17 @@ impl App {
     }
 
     pub fn start_edit_note(&mut self, note_id: uuid::Uuid) {
-        if let Some(note) = self.notes.iter().find(|n| n.id == note_id) {
+        if let Some(note) = self.all_notes.iter().find(|n| n.id == note_id) {
             self.mode = AppMode::EditNote(note_id);
             self.editor_mode = EditorMode::Insert;
             self.active_field = ActiveField::Content;
 
             self.content_editor = tui_textarea::TextArea::from(note.content.lines().collect::<Vec<_>>());
             self.title_input = note.title.clone().unwrap_or_default();
+            self.explicit_tags = note.explicit_tags.clone();
+            self.explicit_projects = note.explicit_projects.clone();
+            self.metadata_input.clear();
+            self.clear_suggestions();
 
             self.update_extracted_metadata();
             self.status_message = Some("editing note".to_string());
@@ -328,11 +953,12 @@ impl App {
     }
 
     pub fn save_edited_note(&mut self) {
+        self.apply_metadata_input();
         if let AppMode::EditNote(note_id) = self.mode {
             let content = self.content_editor.lines().join("\n");
 
             if !content.trim().is_empty() {
-                if let Some(note) = self.notes.iter_mut().find(|n| n.id == note_id) {
+                if let Some(mut note) = self.all_notes.iter().find(|n| n.id == note_id).cloned() {
                     note.content = content;
                     note.title = if self.title_input.is_empty() {
                         None
@@ -340,27 +966,22 @@ impl App {
                         Some(self.title_input.clone())
                     };
                     note.updated = Some(chrono::Utc::now());
-                    note.tags = crate::store::extract_tags(&note.content);
-                    note.projects = crate::store::extract_projects(&note.content);
-
-                    if let Some(home) = dirs::home_dir() {
-                        let notes_dir = home.join(".stash").join("notes");
-                        let file_path = notes_dir.join(format!("{}.md", note.id));
-
-                        match note.save_to_file(&file_path) {
-                            Ok(()) => {
-                                self.status_message = Some("note updated successfully".to_string());
-                                self.load_existing_notes();
-                                self.mode = AppMode::ViewNote(note_id);
-                                self.editor_mode = EditorMode::Command;
-                                self.content_editor = TextArea::default();
-                                self.title_input.clear();
-                                self.extracted_tags.clear();
-                                self.extracted_projects.clear();
-                            }
-                            Err(e) => {
-                                self.status_message = Some(format!("error saving note: {}", e));
-                            }
+                    note.explicit_tags = self.explicit_tags.clone();
+                    note.explicit_projects = self.explicit_projects.clone();
+                    note.merge_metadata();
+
+                    match self.repository.update(&note) {
+                        Ok(()) => {
+                            self.status_message = Some("note updated successfully".to_string());
+                            self.load_existing_notes();
+                            self.mode = AppMode::ViewNote(note_id);
+                            self.editor_mode = EditorMode::Command;
+                            self.content_editor = TextArea::default();
+                            self.title_input.clear();
+                            self.reset_metadata_fields();
+                        }
+                        Err(e) => {
+                            self.status_message = Some(format!("error saving note: {}", e));
                         }
                     }
                 }
@@ -384,8 +1005,167 @@ impl App {
         }
     }
 
-    pub fn start_ai_rewrite(&mut self, note_id: uuid::Uuid) {
-        if let Some(note) = self.notes.iter().find(|n| n.id == note_id) {
+    pub fn open_section_picker(&mut self, note_id: Uuid) {
+        let Some(note) = self.all_notes.iter().find(|n| n.id == note_id) else {
+            return;
+        };
+
+        self.note_sections = region::sections(&note.content);
+        if self.note_sections.is_empty() {
+            self.status_message = Some("no headings in this note, select text in the editor to rewrite part of it".to_string());
+        } else {
+            self.section_picker = Some(0);
+        }
+    }
+
+    pub fn next_section(&mut self) {
+        if let Some(selected) = self.section_picker {
+            self.section_picker = Some((selected + 1) % self.note_sections.len());
+        }
+    }
+
+    pub fn previous_section(&mut self) {
+        if let Some(selected) = self.section_picker {
+            let count = self.note_sections.len();
+            self.section_picker = Some(if selected == 0 { count - 1 } else { selected - 1 });
+        }
+    }
+
+    pub fn rewrite_selected_section(&mut self, note_id: Uuid) {
+        let section = self.section_picker.take().and_then(|selected| self.note_sections.get(selected).cloned());
+        let Some(section) = section else {
+            return;
+        };
+
+        let empty = self.all_notes.iter()
+            .find(|n| n.id == note_id)
+            .and_then(|note| note.content.get(section.body.clone()))
+            .is_none_or(|body| body.trim().is_empty());
+        if empty {
+            self.status_message = Some("nothing under that heading to rewrite".to_string());
+        } else {
+            self.open_prompt_picker(RewriteTarget::Note { note_id, region: Some(section.body) });
+        }
+    }
+
+    // offers the prompt library for `target`. with no prompts of their own
+    // the built-in rewrite runs straight away
+    pub fn open_prompt_picker(&mut self, target: RewriteTarget) {
+        let (prompts, errors) = match prompts::load(&self.config) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                self.status_message = Some(format!("couldn't load prompts: {}", e));
+                return;
+            }
+        };
+
+        if !errors.is_empty() {
+            self.status_message = Some(format!("skipped {} prompt file{} that couldn't be read, see 'stash prompts'", errors.len(), if errors.len() == 1 { "" } else { "s" }));
+        }
+
+        self.prompts = prompts;
+        self.selected_prompt = 0;
+        if self.prompts.len() > 1 {
+            self.prompt_target = Some(target);
+        } else if self.config.privacy.preview {
+            self.prompt_target = Some(target);
+            self.preview_selected_prompt();
+            if self.payload_preview.is_none() {
+                self.prompt_target = None;
+            }
+        } else {
+            self.run_prompt_on(target, self.prompts[0].clone());
+        }
+    }
+
+    pub fn next_prompt(&mut self) {
+        if !self.prompts.is_empty() {
+            self.selected_prompt = (self.selected_prompt + 1) % self.prompts.len();
+        }
+    }
+
+    pub fn previous_prompt(&mut self) {
+        let count = self.prompts.len();
+        if count > 0 {
+            self.selected_prompt = if self.selected_prompt == 0 { count - 1 } else { self.selected_prompt - 1 };
+        }
+    }
+
+    pub fn run_selected_prompt(&mut self) {
+        let (Some(target), Some(prompt)) = (self.prompt_target.take(), self.prompts.get(self.selected_prompt).cloned()) else {
+            return;
+        };
+        self.run_prompt_on(target, prompt);
+    }
+
+    // enter in the picker, which goes by way of the preview when the privacy
+    // settings ask for one
+    pub fn confirm_selected_prompt(&mut self) {
+        if self.config.privacy.preview {
+            self.preview_selected_prompt();
+        } else {
+            self.run_selected_prompt();
+        }
+    }
+
+    // the note a prompt would run on, and the part of it
+    fn prompt_subject(&self, target: &RewriteTarget) -> Option<(Note, Option<Range<usize>>)> {
+        match target {
+            RewriteTarget::Editor => Some((self.draft_note(), self.editor_selection())),
+            RewriteTarget::Note { note_id, region } => self.all_notes.iter()
+                .find(|note| note.id == *note_id)
+                .map(|note| (note.clone(), region.clone())),
+        }
+    }
+
+    pub fn preview_selected_prompt(&mut self) {
+        let (Some(target), Some(prompt)) = (&self.prompt_target, self.prompts.get(self.selected_prompt)) else {
+            return;
+        };
+        let Some((note, region)) = self.prompt_subject(target) else {
+            return;
+        };
+
+        let preview = AiClient::for_prompt(prompt)
+            .and_then(|client| Ok(client.preview(&client.prompt_request(prompt, &note, region)?)));
+        match preview {
+            Ok(text) => {
+                self.payload_preview = Some(text);
+                self.preview_scroll = 0;
+            }
+            Err(e) => self.status_message = Some(e.to_string()),
+        }
+    }
+
+    pub fn send_previewed_prompt(&mut self) {
+        self.payload_preview = None;
+        self.run_selected_prompt();
+    }
+
+    pub fn close_payload_preview(&mut self) {
+        self.payload_preview = None;
+        // with only the built-in prompt there's no picker to go back to
+        if self.prompts.len() == 1 {
+            self.prompt_target = None;
+        }
+    }
+
+    fn run_prompt_on(&mut self, target: RewriteTarget, prompt: Prompt) {
+        match target {
+            RewriteTarget::Editor => self.start_ai_rewrite_draft(prompt),
+            RewriteTarget::Note { note_id, region } => self.start_ai_rewrite(note_id, region, prompt),
+        }
+    }
+
+    // the editor's selection as a byte range into its text, if anything is selected
+    pub fn editor_selection(&self) -> Option<Range<usize>> {
+        let (start, end) = self.content_editor.selection_range()?;
+        let range = region::selection_range(self.content_editor.lines(), start, end);
+        (!range.is_empty()).then_some(range)
+    }
+
+    pub fn start_ai_rewrite(&mut self, note_id: uuid::Uuid, region: Option<Range<usize>>, prompt: Prompt) {
+        if let Some(note) = self.all_notes.iter().find(|n| n.id == note_id) {
             if let Some(ai_client) = &self.ai_client {
                 if !ai_client.is_configured() {
                     self.status_message = Some("please configure your openai api key first (press 's' for settings)".to_string());
@@ -398,11 +1178,8 @@ impl App {
                     rewritten_content: None
                 };
 
-                let (tx, rx) = mpsc::unbounded_channel();
-                self.ai_result_receiver = Some(rx);
-
                 let note_clone = note.clone();
-                let ai_client = match AiClient::new() {
+                let ai_client = match AiClient::for_prompt(&prompt) {
                     Ok(client) => client,
                     Err(e) => {
                         self.ai_state = AiState::Error(format!("Failed to create AI client: {}", e));
@@ -410,25 +1187,200 @@ impl App {
                     }
                 };
 
-                tokio::spawn(async move {
-                    let result = match ai_client.rewrite_note(&note_clone).await {
-                        Ok(content) => Ok(content),
-                        Err(e) => Err(e.to_string()),
-                    };
-                    let _ = tx.send(result);
-                });
+                self.spawn_rewrite(ai_client, note_clone, region, prompt);
             } else {
                 self.status_message = Some("ai client not available. please check your configuration.".to_string());
             }
         }
     }
 
+    pub fn open_ask(&mut self) {
+        self.mode = AppMode::Ask;
+        if self.ask_receiver.is_none() && self.ask_answer.is_none() {
+            self.ai_state = AiState::Idle;
+        }
+    }
+
+    pub fn new_question(&mut self) {
+        self.ask_input.clear();
+        self.ask_sources.clear();
+        self.ask_answer = None;
+        self.ask_cited.clear();
+        self.selected_source = 0;
+        self.ask_receiver = None;
+        self.ai_state = AiState::Idle;
+    }
+
+    pub fn start_ask(&mut self) {
+        let question = self.ask_input.trim().to_string();
+        if question.is_empty() {
+            return;
+        }
+
+        if !self.ai_client.as_ref().is_some_and(|client| client.is_configured()) {
+            self.ai_state = AiState::Error("answering needs an ai provider, press 's' to add an openai key or run 'stash provider add'".to_string());
+            return;
+        }
+
+        let notes = match ask::retrieve(&question, ask::DEFAULT_SOURCES) {
+            Ok(notes) => notes,
+            Err(e) => {
+                self.ai_state = AiState::Error(format!("search failed: {}", e));
+                return;
+            }
+        };
+
+        if notes.is_empty() {
+            self.ai_state = AiState::Error("no notes look related to that question".to_string());
+            return;
+        }
+
+        let ai_client = match AiClient::new() {
+            Ok(client) => client,
+            Err(e) => {
+                self.ai_state = AiState::Error(format!("Failed to create AI client: {}", e));
+                return;
+            }
+        };
+
+        // private notes are left out rather than refusing the whole question
+        let (notes, held_back) = ai_client.privacy().allowed(notes);
+        if notes.is_empty() {
+            self.ai_state = AiState::Error("only private notes look related to that question, nothing was sent".to_string());
+            return;
+        }
+        if held_back > 0 {
+            self.status_message = Some(format!("left out {} private note{}", held_back, if held_back == 1 { "" } else { "s" }));
+        }
+
+        let context = ask::pack(notes, ask::DEFAULT_BUDGET);
+        self.ask_sources = context.sources.clone();
+        self.ask_answer = None;
+        self.ask_cited.clear();
+        self.selected_source = 0;
+        self.ai_state = AiState::Processing;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.ask_receiver = Some(rx);
+
+        tokio::spawn(async move {
+            let result = ai_client.answer_question(&question, &context).await.map_err(|e| e.to_string());
+            let _ = tx.send(result);
+        });
+    }
+
+    pub fn check_ask_result(&mut self) {
+        let Some(receiver) = &mut self.ask_receiver else {
+            return;
+        };
+
+        if let Ok(result) = receiver.try_recv() {
+            match result {
+                Ok(answer) => {
+                    self.ask_cited = ask::citations(&answer, self.ask_sources.len());
+                    self.ask_answer = Some(answer);
+                    self.ai_state = AiState::Success;
+                }
+                Err(error) => self.ai_state = AiState::Error(error),
+            }
+            self.ask_receiver = None;
+        }
+    }
+
+    pub fn next_source(&mut self) {
+        if !self.ask_sources.is_empty() {
+            self.selected_source = (self.selected_source + 1) % self.ask_sources.len();
+        }
+    }
+
+    pub fn previous_source(&mut self) {
+        let count = self.ask_sources.len();
+        if count > 0 {
+            self.selected_source = if self.selected_source == 0 { count - 1 } else { self.selected_source - 1 };
+        }
+    }
+
+    pub fn open_selected_source(&mut self) {
+        let Some(source) = self.ask_sources.get(self.selected_source) else {
+            return;
+        };
+
+        let note_id = source.note.id;
+        if !self.all_notes.iter().any(|note| note.id == note_id) {
+            self.status_message = Some("that note is gone".to_string());
+            return;
+        }
+
+        self.view_note(note_id);
+        self.return_to_ask = true;
+    }
+
+    fn spawn_rewrite(&mut self, ai_client: AiClient, note: Note, region: Option<Range<usize>>, prompt: Prompt) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (token_tx, token_rx) = mpsc::unbounded_channel();
+        self.ai_result_receiver = Some(rx);
+        self.ai_token_receiver = Some(token_rx);
+        self.ai_streamed.clear();
+        self.ai_token_count = 0;
+        self.ai_started = Some(Instant::now());
+        self.ai_elapsed = None;
+        self.rewrite_region = region.clone();
+        self.rewrite_prompt_name = prompt.name.clone();
+
+        self.ai_task = Some(tokio::spawn(async move {
+            let result = ai_client.run_prompt(&prompt, &note, region, &token_tx).await.map_err(|e| e.to_string());
+            let _ = tx.send(result);
+        }));
+    }
+
+    // stops a rewrite that is still being written
+    fn cancel_ai_rewrite(&mut self) -> bool {
+        let running = self.ai_result_receiver.is_some();
+        if let Some(task) = self.ai_task.take() {
+            task.abort();
+        }
+        self.ai_result_receiver = None;
+        self.ai_token_receiver = None;
+        running
+    }
+
+    // how long the current rewrite has taken, frozen once it's done
+    pub fn ai_elapsed(&self) -> Duration {
+        self.ai_elapsed.or_else(|| self.ai_started.map(|started| started.elapsed())).unwrap_or_default()
+    }
+
     pub fn check_ai_result(&mut self) {
+        // every piece is sent before the result, so draining them first
+        // means the streamed text is complete by the time it's replaced
+        if let Some(receiver) = &mut self.ai_token_receiver {
+            while let Ok(piece) = receiver.try_recv() {
+                self.ai_streamed.push_str(&piece);
+                self.ai_token_count += 1;
+            }
+        }
+
         if let Some(receiver) = &mut self.ai_result_receiver {
             if let Ok(result) = receiver.try_recv() {
+                self.ai_elapsed = Some(self.ai_elapsed());
+                self.ai_task = None;
+                self.ai_token_receiver = None;
                 match result {
                     Ok(rewritten_content) => {
                         if let AppMode::AiRewrite { original_note_id, .. } = self.mode {
+                            let original = self.rewrite_original(original_note_id).unwrap_or_default();
+                            // a rewritten part goes back where it came from
+                            let rewritten_content = match self.rewrite_region.clone() {
+                                Some(region) if original.get(region.clone()).is_some() => region::splice(&original, region, &rewritten_content),
+                                Some(_) => {
+                                    self.ai_state = AiState::Error("the note changed while it was being rewritten".to_string());
+                                    self.ai_result_receiver = None;
+                                    return;
+                                }
+                                None => rewritten_content,
+                            };
+                            self.rewrite_hunks = hunks::hunks(&original, &rewritten_content);
+                            self.accepted_hunks = vec![true; self.rewrite_hunks.len()];
+                            self.selected_hunk = 0;
                             self.mode = AppMode::AiRewrite {
                                 original_note_id,
                                 rewritten_content: Some(rewritten_content)
@@ -445,33 +1397,80 @@ impl App {
         }
     }
 
+    // the text being rewritten: the editor for a draft, otherwise the saved note
+    pub fn rewrite_original(&self, note_id: Uuid) -> Option<String> {
+        if note_id == Uuid::nil() {
+            Some(self.content_editor.lines().join("\n"))
+        } else {
+            self.all_notes.iter().find(|n| n.id == note_id).map(|note| note.content.clone())
+        }
+    }
+
+    pub fn next_hunk(&mut self) {
+        if !self.rewrite_hunks.is_empty() {
+            self.selected_hunk = (self.selected_hunk + 1) % self.rewrite_hunks.len();
+        }
+    }
+
+    pub fn previous_hunk(&mut self) {
+        let count = self.rewrite_hunks.len();
+        if count > 0 {
+            self.selected_hunk = if self.selected_hunk == 0 { count - 1 } else { self.selected_hunk - 1 };
+        }
+    }
+
+    pub fn set_hunk(&mut self, accepted: bool) {
+        if let Some(decision) = self.accepted_hunks.get_mut(self.selected_hunk) {
+            *decision = accepted;
+        }
+    }
+
+    pub fn toggle_hunk(&mut self) {
+        if let Some(decision) = self.accepted_hunks.get_mut(self.selected_hunk) {
+            *decision = !*decision;
+        }
+    }
+
+    pub fn set_all_hunks(&mut self, accepted: bool) {
+        self.accepted_hunks.iter_mut().for_each(|decision| *decision = accepted);
+    }
+
+    // applies the accepted hunks, the rest of the note stays as it was
     pub fn accept_ai_rewrite(&mut self) {
-        if let AppMode::AiRewrite { original_note_id, rewritten_content: Some(ref content) } = &self.mode {
+        if let AppMode::AiRewrite { original_note_id, rewritten_content: Some(ref rewritten) } = &self.mode {
+            if !self.accepted_hunks.contains(&true) {
+                self.reject_ai_rewrite();
+                self.status_message = Some("no changes accepted, note left as it was".to_string());
+                return;
+            }
+
+            let original = self.rewrite_original(*original_note_id).unwrap_or_default();
+            let content = hunks::merge(&original, rewritten, &self.rewrite_hunks, &self.accepted_hunks);
+            let partial = self.accepted_hunks.contains(&false);
+
             if *original_note_id == uuid::Uuid::nil() {
-                // this is a draft rewrite - update the content editor and go back to AddNote mode
+                // this is a draft rewrite - update the content editor and go back to the editor
                 self.content_editor = tui_textarea::TextArea::from(content.lines().collect::<Vec<_>>());
                 self.update_extracted_metadata();
 
-                self.status_message = Some("draft updated with ai rewrite".to_string());
-                self.mode = AppMode::AddNote;
+                self.status_message = Some(if partial { "draft updated with part of the ai rewrite" } else { "draft updated with ai rewrite" }.to_string());
+                self.mode = self.rewrite_return.take().unwrap_or(AppMode::AddNote);
                 self.ai_state = AiState::Idle;
             } else {
                 // this is a saved note rewrite - update the saved note
-                if let Some(note) = self.notes.iter_mut().find(|n| n.id == *original_note_id) {
+                let note_id = *original_note_id;
+                if let Some(mut note) = self.all_notes.iter().find(|n| n.id == note_id).cloned() {
                     note.content = content.clone();
                     note.updated = Some(chrono::Utc::now());
 
-                    if let Some(home) = dirs::home_dir() {
-                        let notes_dir = home.join(".stash").join("notes");
-                        let file_path = notes_dir.join(format!("{}.md", note.id));
-                        if let Err(e) = note.save_to_file(&file_path) {
-                            self.status_message = Some(format!("error saving note: {}", e));
-                            return;
-                        }
+                    if let Err(e) = self.repository.update_as(&note, Change::AiRewrite) {
+                        self.status_message = Some(format!("error saving note: {}", e));
+                        return;
                     }
 
-                    self.status_message = Some("note updated with ai rewrite".to_string());
-                    self.mode = AppMode::ViewNote(*original_note_id);
+                    self.load_existing_notes();
+                    self.status_message = Some(if partial { "note updated with part of the ai rewrite" } else { "note updated with ai rewrite" }.to_string());
+                    self.mode = AppMode::ViewNote(note_id);
                     self.ai_state = AiState::Idle;
                 }
             }
@@ -480,18 +1479,21 @@ impl App {
 
     pub fn reject_ai_rewrite(&mut self) {
         if let AppMode::AiRewrite { original_note_id, .. } = self.mode {
+            let cancelled = self.cancel_ai_rewrite();
             if original_note_id == uuid::Uuid::nil() {
-                self.mode = AppMode::AddNote;
+                self.mode = self.rewrite_return.take().unwrap_or(AppMode::AddNote);
             } else {
                 self.mode = AppMode::ViewNote(original_note_id);
             }
             self.ai_state = AiState::Idle;
-            self.status_message = Some("ai rewrite rejected".to_string());
+            self.status_message = Some(if cancelled { "ai rewrite cancelled" } else { "ai rewrite rejected" }.to_string());
         }
     }
 
-    pub fn start_ai_rewrite_draft(&mut self) {
+    // rewrites what's in the editor, or just the selected part of it
+    pub fn start_ai_rewrite_draft(&mut self, prompt: Prompt) {
         let current_content = self.content_editor.lines().join("\n");
+        let region = self.editor_selection();
 
         if current_content.trim().is_empty() {
             self.status_message = Some("cannot rewrite empty content".to_string());
@@ -505,28 +1507,19 @@ impl App {
             }
 
             self.ai_state = AiState::Processing;
+            self.rewrite_return = Some(self.mode.clone());
             self.mode = AppMode::AiRewrite {
                 original_note_id: uuid::Uuid::nil(), // use nil UUID to indicate this is a draft
                 rewritten_content: None
             };
 
-            let (tx, rx) = mpsc::unbounded_channel();
-            self.ai_result_receiver = Some(rx);
-
-            // create a temporary note for AI processing
-            let temp_note = Note {
-                id: uuid::Uuid::nil(),
-                title: if self.title_input.is_empty() { None } else { Some(self.title_input.clone()) },
-                tags: self.extracted_tags.clone(),
-                projects: self.extracted_projects.clone(),
-                links_to: Vec::new(),
-                created: chrono::Utc::now(),
-                updated: None,
-                source: crate::models::NoteSource::UI,
-                content: current_content,
-            };
+            // a temporary note for AI processing, with the same tags and
+            // projects the note would be saved with so the privacy check sees them
+            self.apply_metadata_input();
+            let mut temp_note = self.draft_note();
+            temp_note.id = uuid::Uuid::nil();
 
-            let ai_client = match AiClient::new() {
+            let ai_client = match AiClient::for_prompt(&prompt) {
                 Ok(client) => client,
                 Err(e) => {
                     self.ai_state = AiState::Error(format!("Failed to create AI client: {}", e));
@@ -534,13 +1527,7 @@ impl App {
                 }
             };
 
-            tokio::spawn(async move {
-                let result = match ai_client.rewrite_note(&temp_note).await {
-                    Ok(content) => Ok(content),
-                    Err(e) => Err(e.to_string()),
-                };
-                let _ = tx.send(result);
-            });
+            self.spawn_rewrite(ai_client, temp_note, region, prompt);
         } else {
             self.status_message = Some("ai client not available. please check your configuration.".to_string());
         }
@@ -622,6 +1609,8 @@ impl App {
     fn run_app(&mut self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
         loop {
                     self.check_ai_result();
+            self.check_ask_result();
+            self.check_suggestion_result();
             terminal.draw(|f| self.ui(f))?;
 
             if crossterm::event::poll(std::time::Duration::from_millis(100))? {
@@ -644,4 +1633,67 @@ impl App {
     }
 
 
-}
\ No newline at end of file
+}
+
+fn subtree_mentions(node: &TagNode, needle: &str) -> bool {
+    node.path.to_lowercase().contains(needle) || node.children.iter().any(|child| subtree_mentions(child, needle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::MemoryRepository;
+    use crate::store::test_note;
+
+    // hub links out to spoke, and both fan and a trashed copy of it link back
+    fn linked_notes() -> (App, Note, Note, Note) {
+        let spoke = test_note("spoke", "");
+        let mut hub = test_note("hub", "see [[spoke]]");
+        hub.links_to = vec![spoke.id];
+        let mut fan = test_note("fan", "back to [[hub]]");
+        fan.links_to = vec![hub.id];
+        let mut trashed = fan.clone();
+        trashed.id = Uuid::new_v4();
+        trashed.deleted_at = Some(chrono::Utc::now());
+
+        let mut app = App::with_repository(Box::new(MemoryRepository::with_notes(vec![hub.clone(), spoke.clone(), fan.clone(), trashed])));
+        app.load_existing_notes();
+        (app, hub, spoke, fan)
+    }
+
+    #[test]
+    fn outgoing_links_come_before_backlinks_and_the_trash_is_left_out() {
+        let (mut app, hub, spoke, fan) = linked_notes();
+        app.refresh_note_links(hub.id);
+
+        let links: Vec<(LinkDirection, Uuid)> = app.note_links(hub.id).into_iter().map(|(direction, note)| (direction, note.id)).collect();
+        assert_eq!(links, vec![(LinkDirection::Outgoing, spoke.id), (LinkDirection::Incoming, fan.id)]);
+    }
+
+    #[test]
+    fn links_are_worked_out_once_until_the_notes_reload() {
+        let (mut app, hub, _, _) = linked_notes();
+        assert!(app.note_links(hub.id).is_empty());
+
+        app.refresh_note_links(hub.id);
+        assert_eq!(app.note_links(hub.id).len(), 2);
+        assert_eq!(app.view_links.as_ref().map(|(id, _)| *id), Some(hub.id));
+
+        app.load_existing_notes();
+        assert!(app.view_links.is_none());
+    }
+
+    #[test]
+    fn followed_links_can_be_walked_back() {
+        let (mut app, hub, spoke, _) = linked_notes();
+        app.view_note(hub.id);
+
+        app.follow_selected_link();
+        assert!(matches!(app.mode, AppMode::ViewNote(id) if id == spoke.id));
+
+        app.go_back_note();
+        assert!(matches!(app.mode, AppMode::ViewNote(id) if id == hub.id));
+        app.go_back_note();
+        assert_eq!(app.status_message.as_deref(), Some("no previous note"));
+    }
+}
@@ -2,13 +2,16 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
     Frame,
 };
 use uuid::Uuid;
 
 use super::app::App;
-use super::state::{AppMode, EditorMode, ActiveField, AiState};
+use super::state::{AppMode, EditorMode, ActiveField, AiState, LinkDirection, RetagPrompt};
+use crate::enrich::Suggestion;
+use crate::history::{self, DiffLine};
+use crate::hunks;
 
 pub trait Renderer {
     fn render(&mut self, f: &mut Frame);
@@ -23,11 +26,17 @@ pub trait Renderer {
     fn render_tag_filter(&mut self, f: &mut Frame, area: Rect);
     fn render_project_filter(&mut self, f: &mut Frame, area: Rect);
     fn render_delete_confirm(&mut self, f: &mut Frame, area: Rect, note_id: Uuid);
+    fn render_trash(&mut self, f: &mut Frame, area: Rect);
+    fn render_history(&mut self, f: &mut Frame, area: Rect, note_id: Uuid);
+    fn render_ask(&mut self, f: &mut Frame, area: Rect);
 }
 
 impl Renderer for App {
     fn render(&mut self, f: &mut Frame) {
         let area = f.area();
+        if let AppMode::ViewNote(note_id) = self.mode {
+            self.refresh_note_links(note_id);
+        }
 
         match self.mode.clone() {
             AppMode::Home => self.render_home(f, area),
@@ -43,6 +52,17 @@ impl Renderer for App {
             AppMode::TagFilter => self.render_tag_filter(f, area),
             AppMode::ProjectFilter => self.render_project_filter(f, area),
             AppMode::DeleteConfirm { note_id } => self.render_delete_confirm(f, area, note_id),
+            AppMode::Trash => self.render_trash(f, area),
+            AppMode::History { note_id } => self.render_history(f, area, note_id),
+            AppMode::Ask => self.render_ask(f, area),
+        }
+
+        if self.prompt_target.is_some() {
+            self.render_prompt_picker(f, area);
+        }
+
+        if self.payload_preview.is_some() {
+            self.render_payload_preview(f, area);
         }
 
         if let Some(ref message) = self.status_message {
@@ -128,6 +148,8 @@ impl Renderer for App {
                 Span::raw(" add  "),
                 Span::styled("/", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
                 Span::raw(" search  "),
+                Span::styled("?", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+                Span::raw(" ask  "),
                 Span::styled("t", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
                 Span::raw(" tags  "),
                 Span::styled("p", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
@@ -143,6 +165,8 @@ impl Renderer for App {
                 Span::raw(" delete  "),
                 Span::styled("c", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
                 Span::raw(" clear  "),
+                Span::styled("x", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
+                Span::raw(" trash  "),
                 Span::styled("r", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
                 Span::raw(" refresh  "),
                 Span::styled("↑↓/jk", Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
@@ -246,7 +270,7 @@ impl Renderer for App {
                         ]));
                     }
 
-                    let projects = crate::store::extract_projects(&note.content);
+                    let projects = &note.projects;
                     if !projects.is_empty() {
                         let projects_text = projects.iter()
                             .map(|proj| format!("+{}", proj))
@@ -278,7 +302,7 @@ impl Renderer for App {
             .constraints([
                 Constraint::Length(3),
                 Constraint::Min(0),
-                Constraint::Length(4),
+                Constraint::Length(if self.suggestions.is_empty() { 4 } else { 7 }),
                 Constraint::Length(2),
             ])
             .split(area);
@@ -335,7 +359,7 @@ impl Renderer for App {
             EditorMode::Command => Style::default().fg(Color::Yellow),
         };
 
-        let status_text = format!("{} • s:save • r:ai • q:quit • esc:command • i:insert", mode_indicator);
+        let status_text = format!("{} • s:save • r:ai • e:suggest • m:tags • q:quit • esc:command • i:insert", mode_indicator);
 
         let status_widget = Paragraph::new(status_text)
             .style(mode_style)
@@ -350,7 +374,7 @@ impl Renderer for App {
             .constraints([
                 Constraint::Length(3),
                 Constraint::Min(0),
-                Constraint::Length(4),
+                Constraint::Length(if self.suggestions.is_empty() { 4 } else { 7 }),
                 Constraint::Length(2),
             ])
             .split(area);
@@ -407,7 +431,7 @@ impl Renderer for App {
             EditorMode::Command => Style::default().fg(Color::Yellow),
         };
 
-        let status_text = format!("{} • s:save • r:ai • q:quit • esc:command • i:insert", mode_indicator);
+        let status_text = format!("{} • s:save • r:ai • e:suggest • m:tags • q:quit • esc:command • i:insert", mode_indicator);
 
         let status_widget = Paragraph::new(status_text)
             .style(mode_style)
@@ -417,7 +441,7 @@ impl Renderer for App {
     }
 
     fn render_view_note(&mut self, f: &mut Frame, area: Rect, note_id: Uuid) {
-        if let Some(note) = self.notes.iter().find(|n| n.id == note_id) {
+        if let Some(note) = self.all_notes.iter().find(|n| n.id == note_id) {
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints([
@@ -442,13 +466,33 @@ impl Renderer for App {
 
             f.render_widget(header_widget, chunks[0]);
 
+            let body_chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Percentage(70),
+                    Constraint::Percentage(30),
+                ])
+                .split(chunks[1]);
+
             let content_widget = Paragraph::new(note.content.as_str())
                 .block(Block::default().borders(Borders::ALL))
                 .wrap(Wrap { trim: true });
 
-            f.render_widget(content_widget, chunks[1]);
+            f.render_widget(content_widget, body_chunks[0]);
 
-            let help_text = "e:edit • r:ai • q:back";
+            if self.section_picker.is_some() {
+                self.render_section_picker(f, body_chunks[1]);
+            } else {
+                self.render_links_panel(f, body_chunks[1], note_id);
+            }
+
+            let help_text = if self.section_picker.is_some() {
+                "↑↓/jk:select section • enter:rewrite it with ai • esc:cancel"
+            } else if self.view_history.is_empty() {
+                "e:edit • r:ai • R:ai on a section • h:history • ↑↓/jk:select link • enter:open link • q:back"
+            } else {
+                "e:edit • r:ai • R:ai on a section • h:history • ↑↓/jk:select link • enter:open link • b:previous note • q:home"
+            };
             let help_widget = Paragraph::new(help_text)
                 .style(Style::default().fg(Color::DarkGray))
                 .alignment(Alignment::Center);
@@ -468,6 +512,7 @@ impl Renderer for App {
             Line::from("  • use #tagname for tags (e.g., #rust #webdev)"),
             Line::from("  • use +projectname for projects (e.g., +myapp +backend)"),
             Line::from("  • mix tags and projects in your content naturally"),
+            Line::from("  • or press m in the editor to attach tags and projects without writing them in"),
             Line::from(""),
             Line::from("search features:"),
             Line::from("  • stash search \"query\" - basic search"),
@@ -478,8 +523,16 @@ impl Renderer for App {
             Line::from(""),
             Line::from("tui controls:"),
             Line::from("  home: a=add, h=help, r=refresh, ↑↓/jk=navigate, q=quit"),
-            Line::from("  add note: t=edit title, c=edit content, s=save, q=quit"),
-            Line::from("  editor: i=insert mode, esc=command mode"),
+            Line::from("  add note: t=edit title, c=edit content, m=add or remove tags, s=save, q=quit"),
+            Line::from("  editor: i=insert mode, esc=command mode, shift+arrows=select, r=ai rewrite of the selection"),
+            Line::from("  suggestions: e=suggest a title, tags and projects, y=accept, n=dismiss, Y=accept all"),
+            Line::from("  r anywhere: pick a prompt from ~/.stash/prompts/ (↑↓, enter), or the built-in rewrite, p=preview what gets sent"),
+            Line::from("  ai rewrite: ↑↓/jk=select change, y=keep, n=drop, space=toggle, Y/N=all, enter=apply, esc=reject"),
+            Line::from("  view note: ↑↓/jk=select link, enter=open link, b=previous note, h=history, R=ai rewrite of a section"),
+            Line::from("  history: ↑↓/jk=select revision, r/enter=restore, q=back"),
+            Line::from("  trash: r/enter=restore, D=delete permanently (asks y/n first), q=back"),
+            Line::from("  ask (?): type a question, enter=ask, ↑↓/jk=select source, enter=open it, n=new question"),
+            Line::from("  tag/project filter: ↑↓=select, →←=expand/collapse, ctrl-r=rename or merge, ctrl-d=delete"),
             Line::from(""),
             Line::from("the right panel shows live tag/project preview"),
             Line::from(""),
@@ -539,6 +592,13 @@ impl Renderer for App {
             "no api key configured"
         };
 
+        // the key above is for openai, other providers are set up with `stash provider`
+        let provider_name = self.config.active_provider_name();
+        let provider_text = match self.config.provider(provider_name) {
+            Ok(provider) => format!("{} ({})", provider_name, provider.model),
+            Err(e) => e.to_string(),
+        };
+
         let api_lines = vec![
             Line::from(""),
             Line::from(vec![
@@ -554,6 +614,10 @@ impl Renderer for App {
                     Style::default().fg(Color::Red)
                 }),
             ]),
+            Line::from(vec![
+                Span::styled("provider: ", Style::default().fg(Color::White)),
+                Span::styled(provider_text, Style::default().fg(Color::Cyan)),
+            ]),
             Line::from(""),
             Line::from("get your api key from: https://platform.openai.com/account/api-keys"),
         ];
@@ -712,7 +776,7 @@ impl Renderer for App {
             let title = if self.title_input.is_empty() { "draft note" } else { &self.title_input };
             let content = self.content_editor.lines().join("\n");
             (title.to_string(), content)
-        } else if let Some(original_note) = self.notes.iter().find(|n| n.id == original_note_id) {
+        } else if let Some(original_note) = self.all_notes.iter().find(|n| n.id == original_note_id) {
             let title = original_note.title.as_deref().unwrap_or("untitled").to_string();
             (title, original_note.content.clone())
         } else {
@@ -728,7 +792,13 @@ impl Renderer for App {
             ])
             .split(area);
 
-        let header_text = format!("ai rewrite: {}", title);
+        // only the part being rewritten is shown while it's written
+        let (header_text, shown_content, original_title) = match self.rewrite_region.clone() {
+            Some(region) if original_content.get(region.clone()).is_some() => {
+                (format!("{}: {} (part)", self.rewrite_prompt_name, title), &original_content[region], "original part")
+            }
+            _ => (format!("{}: {}", self.rewrite_prompt_name, title), original_content.as_str(), "original"),
+        };
         let header_widget = Paragraph::new(header_text)
             .block(Block::default().borders(Borders::ALL))
             .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
@@ -736,6 +806,25 @@ impl Renderer for App {
 
         f.render_widget(header_widget, chunks[0]);
 
+        let progress = format!(
+            "{:.1}s • {} token{}",
+            self.ai_elapsed().as_secs_f32(),
+            self.ai_token_count,
+            if self.ai_token_count == 1 { "" } else { "s" }
+        );
+
+        if let (AiState::Success, Some(rewritten)) = (&self.ai_state, rewritten_content) {
+            self.render_rewrite_review(f, chunks[1], &original_content, rewritten, &progress);
+
+            let controls_widget = Paragraph::new("↑↓=select • y=keep • n=drop • space=toggle • Y/N=all • enter=apply • esc=reject")
+                .block(Block::default().borders(Borders::ALL))
+                .style(Style::default().fg(Color::DarkGray))
+                .alignment(Alignment::Center);
+
+            f.render_widget(controls_widget, chunks[2]);
+            return;
+        }
+
         let content_layout = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([
@@ -744,24 +833,40 @@ impl Renderer for App {
             ])
             .split(chunks[1]);
 
-        let original_widget = Paragraph::new(original_content.as_str())
-            .block(Block::default().borders(Borders::ALL).title("original"))
+        let original_widget = Paragraph::new(shown_content)
+            .block(Block::default().borders(Borders::ALL).title(original_title))
             .wrap(Wrap { trim: true });
 
         f.render_widget(original_widget, content_layout[0]);
 
         match (&self.ai_state, rewritten_content) {
-            (AiState::Processing, _) => {
+            (AiState::Processing, _) if self.ai_streamed.is_empty() => {
                 let processing_widget = Paragraph::new("processing with ai...\n\nplease wait while your note is being rewritten.")
-                    .block(Block::default().borders(Borders::ALL).title("ai rewrite"))
+                    .block(Block::default().borders(Borders::ALL).title(format!("ai rewrite • {}", progress)))
                     .style(Style::default().fg(Color::Yellow))
                     .alignment(Alignment::Center);
 
                 f.render_widget(processing_widget, content_layout[1]);
             }
+            (AiState::Processing, _) => {
+                // keep the newest text in view as it comes in
+                let width = content_layout[1].width.saturating_sub(2).max(1) as usize;
+                let height = content_layout[1].height.saturating_sub(2) as usize;
+                let lines: usize = self.ai_streamed.split('\n')
+                    .map(|line| line.chars().count().div_ceil(width).max(1))
+                    .sum();
+                let scroll = lines.saturating_sub(height) as u16;
+
+                let streaming_widget = Paragraph::new(self.ai_streamed.as_str())
+                    .block(Block::default().borders(Borders::ALL).title(format!("ai rewrite • writing {}", progress)))
+                    .wrap(Wrap { trim: false })
+                    .scroll((scroll, 0));
+
+                f.render_widget(streaming_widget, content_layout[1]);
+            }
             (AiState::Success, Some(content)) => {
                 let rewritten_widget = Paragraph::new(content.as_str())
-                    .block(Block::default().borders(Borders::ALL).title("ai rewrite"))
+                    .block(Block::default().borders(Borders::ALL).title(format!("ai rewrite • {}", progress)))
                     .wrap(Wrap { trim: true })
                     .style(Style::default().fg(Color::Green));
 
@@ -789,7 +894,7 @@ impl Renderer for App {
 
         let controls_text = match &self.ai_state {
             AiState::Success => "enter=accept rewrite • esc=reject and go back",
-            AiState::Processing => "please wait... • esc=cancel",
+            AiState::Processing => "writing... • esc=cancel",
             AiState::Error(_) => "esc=go back",
             _ => "processing... • esc=cancel",
         };
@@ -817,8 +922,20 @@ impl Renderer for App {
 
         f.render_widget(input_widget, chunks[0]);
 
-        let help_text = "type to search through note content and titles\npress enter to apply search, esc to cancel";
-        let help_widget = Paragraph::new(help_text)
+        let mut help_lines = vec![
+            Line::from("type to search through note content and titles"),
+            Line::from("combine words with OR, NOT and (parentheses), match \"exact phrases\""),
+            Line::from("or search one field with title: content: tag: project: (#tag and +project work too)"),
+            Line::from("filter by date with created: and updated: (2025-03, >2025-01-01, <7d, yesterday, this-week)"),
+            Line::from("press enter to apply search, esc to cancel"),
+        ];
+
+        if let Err(e) = crate::query::parse(&self.search_input) {
+            help_lines.push(Line::from(""));
+            help_lines.push(Line::styled(format!("invalid query: {}", e), Style::default().fg(Color::Red)));
+        }
+
+        let help_widget = Paragraph::new(help_lines)
             .block(Block::default().borders(Borders::ALL).title("help"))
             .style(Style::default().fg(Color::DarkGray))
             .alignment(Alignment::Center);
@@ -832,6 +949,7 @@ impl Renderer for App {
             .constraints([
                 Constraint::Length(3),
                 Constraint::Min(0),
+                Constraint::Length(3),
             ])
             .split(area);
 
@@ -841,13 +959,10 @@ impl Renderer for App {
 
         f.render_widget(input_widget, chunks[0]);
 
-        let help_text = "type tag name to filter notes (without #)\npress enter to apply filter, esc to cancel";
-        let help_widget = Paragraph::new(help_text)
-            .block(Block::default().borders(Borders::ALL).title("help"))
-            .style(Style::default().fg(Color::DarkGray))
-            .alignment(Alignment::Center);
+        self.render_filter_tree(f, chunks[1], '#', Color::Blue);
 
-        f.render_widget(help_widget, chunks[1]);
+        let help_text = "type to narrow (without #) • ↑↓ select • →/← expand/collapse • enter filter • ctrl-r rename • ctrl-d delete • esc cancel";
+        self.render_filter_help(f, chunks[2], '#', help_text);
     }
 
     fn render_project_filter(&mut self, f: &mut Frame, area: Rect) {
@@ -856,6 +971,7 @@ impl Renderer for App {
             .constraints([
                 Constraint::Length(3),
                 Constraint::Min(0),
+                Constraint::Length(3),
             ])
             .split(area);
 
@@ -865,13 +981,10 @@ impl Renderer for App {
 
         f.render_widget(input_widget, chunks[0]);
 
-        let help_text = "type project name to filter notes (without +)\npress enter to apply filter, esc to cancel";
-        let help_widget = Paragraph::new(help_text)
-            .block(Block::default().borders(Borders::ALL).title("help"))
-            .style(Style::default().fg(Color::DarkGray))
-            .alignment(Alignment::Center);
+        self.render_filter_tree(f, chunks[1], '+', Color::Green);
 
-        f.render_widget(help_widget, chunks[1]);
+        let help_text = "type to narrow (without +) • ↑↓ select • →/← expand/collapse • enter filter • ctrl-r rename • ctrl-d delete • esc cancel";
+        self.render_filter_help(f, chunks[2], '+', help_text);
     }
 
     fn render_delete_confirm(&mut self, f: &mut Frame, area: Rect, note_id: Uuid) {
@@ -912,8 +1025,8 @@ impl Renderer for App {
 
             f.render_widget(header_widget, chunks[0]);
 
-            let soft_selected = matches!(self.deletion_preference, crate::tui::app::DeletionType::Soft);
-            let hard_selected = matches!(self.deletion_preference, crate::tui::app::DeletionType::Hard);
+            let soft_selected = matches!(self.deletion_preference, crate::repository::DeletionType::Soft);
+            let hard_selected = matches!(self.deletion_preference, crate::repository::DeletionType::Hard);
 
             let options_lines = vec![
                 Line::from(""),
@@ -924,7 +1037,7 @@ impl Renderer for App {
                     } else {
                         Style::default().fg(Color::White)
                     }),
-                    Span::styled(" (adds deleted tag, recoverable)", Style::default().fg(Color::DarkGray)),
+                    Span::styled(" (moves to trash, recoverable)", Style::default().fg(Color::DarkGray)),
                 ]),
                 Line::from(vec![
                     Span::styled(if hard_selected { "► " } else { "  " }, Style::default().fg(Color::Cyan)),
@@ -950,7 +1063,7 @@ impl Renderer for App {
                 Line::from("  enter/y - confirm deletion"),
                 Line::from("  esc/n - cancel"),
                 Line::from(""),
-                Line::from("note: soft deleted notes can be recovered by removing the #deleted tag"),
+                Line::from("note: soft deleted notes can be restored from the trash (x on the home screen)"),
             ];
 
             let help_widget = Paragraph::new(help_lines)
@@ -961,14 +1074,361 @@ impl Renderer for App {
             f.render_widget(help_widget, chunks[2]);
         }
     }
+
+    fn render_trash(&mut self, f: &mut Frame, area: Rect) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(3),
+            ])
+            .split(area);
+
+        let trash_block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("trash ({} notes)", self.trashed_notes.len()));
+
+        if self.trashed_notes.is_empty() {
+            let empty_message = Paragraph::new("trash is empty")
+                .block(trash_block)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::DarkGray));
+            f.render_widget(empty_message, chunks[0]);
+        } else {
+            let items: Vec<ListItem> = self.trashed_notes
+                .iter()
+                .enumerate()
+                .map(|(i, note)| {
+                    let title = note.title.as_deref().unwrap_or("untitled");
+                    let trashed_at = note.trashed_at()
+                        .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                        .unwrap_or_default();
+
+                    let (marker, title_style) = if i == self.selected_trash {
+                        ("► ", Style::default().fg(Color::White).bg(Color::Blue).add_modifier(Modifier::BOLD))
+                    } else {
+                        ("  ", Style::default().add_modifier(Modifier::BOLD))
+                    };
+
+                    ListItem::new(Line::from(vec![
+                        Span::styled(marker, Style::default().fg(Color::Cyan)),
+                        Span::styled(title.to_string(), title_style),
+                        Span::styled(format!("  deleted {}", trashed_at), Style::default().fg(Color::DarkGray)),
+                    ]))
+                })
+                .collect();
+
+            f.render_widget(List::new(items).block(trash_block), chunks[0]);
+        }
+
+        let purging = self.purge_confirm.and_then(|id| self.trashed_notes.iter().find(|note| note.id == id));
+        let help_widget = match purging {
+            Some(note) => Paragraph::new(format!(
+                "permanently delete '{}' and its history? this can't be undone • y yes • n no",
+                note.title.as_deref().unwrap_or("untitled")
+            ))
+            .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+            None => Paragraph::new("r/enter restore • D delete permanently • j/k move • q back")
+                .style(Style::default().fg(Color::DarkGray)),
+        }
+        .block(Block::default().borders(Borders::ALL))
+        .alignment(Alignment::Center);
+
+        f.render_widget(help_widget, chunks[1]);
+    }
+
+    fn render_history(&mut self, f: &mut Frame, area: Rect, note_id: Uuid) {
+        let Some(note) = self.all_notes.iter().find(|n| n.id == note_id) else {
+            return;
+        };
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(area);
+
+        let body_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(30),
+                Constraint::Percentage(70),
+            ])
+            .split(chunks[0]);
+
+        let revisions_block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("history: {}", note.title.as_deref().unwrap_or("untitled")));
+
+        if self.revisions.is_empty() {
+            let empty_message = Paragraph::new("no earlier revisions")
+                .block(revisions_block)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::DarkGray));
+            f.render_widget(empty_message, body_chunks[0]);
+        } else {
+            let items: Vec<ListItem> = self.revisions
+                .iter()
+                .enumerate()
+                .map(|(i, revision)| {
+                    let (marker, number_style) = if i == self.selected_revision {
+                        ("► ", Style::default().fg(Color::White).bg(Color::Blue).add_modifier(Modifier::BOLD))
+                    } else {
+                        ("  ", Style::default().add_modifier(Modifier::BOLD))
+                    };
+
+                    ListItem::new(Line::from(vec![
+                        Span::styled(marker, Style::default().fg(Color::Cyan)),
+                        Span::styled(format!("rev {}", revision.number), number_style),
+                        Span::styled(format!("  {}", revision.saved_at().format("%Y-%m-%d %H:%M")), Style::default().fg(Color::DarkGray)),
+                    ]))
+                })
+                .collect();
+
+            f.render_widget(List::new(items).block(revisions_block), body_chunks[0]);
+        }
+
+        let diff_block = Block::default()
+            .borders(Borders::ALL)
+            .title("changes since this revision");
+
+        let diff_lines: Vec<Line> = match self.revisions.get(self.selected_revision) {
+            Some(revision) => {
+                let mut lines = Vec::new();
+                if revision.note.title != note.title {
+                    lines.push(Line::styled(format!("-title: {}", revision.note.title.as_deref().unwrap_or("")), Style::default().fg(Color::Red)));
+                    lines.push(Line::styled(format!("+title: {}", note.title.as_deref().unwrap_or("")), Style::default().fg(Color::Green)));
+                }
+
+                lines.extend(history::diff_lines(&revision.note.content, &note.content).into_iter().map(|line| match line {
+                    DiffLine::Hunk(header) => Line::styled(header, Style::default().fg(Color::Cyan)),
+                    DiffLine::Removed(text) => Line::styled(format!("-{}", text), Style::default().fg(Color::Red)),
+                    DiffLine::Added(text) => Line::styled(format!("+{}", text), Style::default().fg(Color::Green)),
+                    DiffLine::Context(text) => Line::styled(format!(" {}", text), Style::default().fg(Color::DarkGray)),
+                }));
+
+                if lines.is_empty() {
+                    lines.push(Line::styled("same as the current version", Style::default().fg(Color::DarkGray)));
+                }
+                lines
+            }
+            None => Vec::new(),
+        };
+
+        f.render_widget(Paragraph::new(diff_lines).block(diff_block), body_chunks[1]);
+
+        let help_widget = Paragraph::new("↑↓/jk:select revision • r/enter:restore • q:back")
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+
+        f.render_widget(help_widget, chunks[1]);
+    }
+
+    fn render_ask(&mut self, f: &mut Frame, area: Rect) {
+        let source_rows = (self.ask_sources.len() as u16).clamp(1, 8) + 2;
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(source_rows),
+                Constraint::Length(3),
+            ])
+            .split(area);
+
+        let input_text = if self.ask_answer.is_none() && !matches!(self.ai_state, AiState::Processing) {
+            format!("{}█", self.ask_input)
+        } else {
+            self.ask_input.clone()
+        };
+        let input_widget = Paragraph::new(input_text)
+            .block(Block::default().borders(Borders::ALL).title("ask your notes"))
+            .style(Style::default().fg(Color::Cyan));
+
+        f.render_widget(input_widget, chunks[0]);
+
+        let (answer_text, answer_style) = match (&self.ai_state, &self.ask_answer) {
+            (AiState::Processing, _) => (
+                format!("reading {} note{}...", self.ask_sources.len(), if self.ask_sources.len() == 1 { "" } else { "s" }),
+                Style::default().fg(Color::Yellow),
+            ),
+            (AiState::Error(error), _) => (format!("error: {}", error), Style::default().fg(Color::Red)),
+            (_, Some(answer)) => (answer.clone(), Style::default()),
+            _ => (
+                "ask anything your notes might know, e.g. \"when do we deploy?\" or \"what did i decide about postgres last month?\"".to_string(),
+                Style::default().fg(Color::DarkGray),
+            ),
+        };
+        let answer_widget = Paragraph::new(answer_text)
+            .block(Block::default().borders(Borders::ALL).title("answer"))
+            .style(answer_style)
+            .wrap(Wrap { trim: false });
+
+        f.render_widget(answer_widget, chunks[1]);
+
+        let items: Vec<ListItem> = self.ask_sources.iter().map(|source| {
+            let cited = self.ask_cited.contains(&source.number);
+            let mut spans = vec![
+                Span::styled(if cited { "✓ " } else { "  " }, Style::default().fg(Color::Green)),
+                Span::styled(format!("[{}] ", source.number), Style::default().fg(Color::DarkGray)),
+                Span::styled(
+                    source.note.title.clone().unwrap_or_else(|| "untitled".to_string()),
+                    if cited { Style::default().add_modifier(Modifier::BOLD) } else { Style::default() },
+                ),
+            ];
+            if source.truncated {
+                spans.push(Span::styled(" (shortened)", Style::default().fg(Color::DarkGray)));
+            }
+            ListItem::new(Line::from(spans))
+        }).collect();
+
+        let mut list_state = ListState::default();
+        if self.ask_answer.is_some() && !self.ask_sources.is_empty() {
+            list_state.select(Some(self.selected_source));
+        }
+
+        let sources_widget = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("sources (✓ cited)"))
+            .highlight_style(Style::default().bg(Color::DarkGray).add_modifier(Modifier::BOLD));
+
+        f.render_stateful_widget(sources_widget, chunks[2], &mut list_state);
+
+        let help_text = match (&self.ai_state, &self.ask_answer) {
+            (AiState::Processing, _) => "please wait... • esc back (the answer keeps coming)",
+            (_, Some(_)) => "↑↓/jk select source • enter open note • n new question • esc back",
+            _ => "type a question • enter ask • esc back",
+        };
+        let help_widget = Paragraph::new(help_text)
+            .block(Block::default().borders(Borders::ALL))
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+
+        f.render_widget(help_widget, chunks[3]);
+    }
 }
 
 impl App {
+    // the key help, or the rename/delete prompt while one is open
+    fn render_filter_help(&self, f: &mut Frame, area: Rect, sigil: char, help_text: &str) {
+        let (text, style) = match &self.retag_prompt {
+            Some(RetagPrompt::Rename { path, input }) => (
+                format!("rename {}{} to: {}█  (an existing name merges, enter apply • esc cancel)", sigil, path, input),
+                Style::default().fg(Color::Yellow),
+            ),
+            Some(RetagPrompt::Delete { path }) => (
+                format!("delete {}{} from every note? y/n", sigil, path),
+                Style::default().fg(Color::Red),
+            ),
+            None => (help_text.to_string(), Style::default().fg(Color::DarkGray)),
+        };
+
+        let help_widget = Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL))
+            .style(style)
+            .alignment(Alignment::Center);
+
+        f.render_widget(help_widget, area);
+    }
+
+    // the tag or project hierarchy with note counts, nested entries included
+    fn render_filter_tree(&self, f: &mut Frame, area: Rect, sigil: char, color: Color) {
+        let rows = self.filter_rows();
+        let block = Block::default().borders(Borders::ALL);
+
+        if rows.is_empty() {
+            let empty_message = Paragraph::new("nothing matches, enter filters by the text as typed")
+                .block(block)
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::DarkGray));
+            f.render_widget(empty_message, area);
+            return;
+        }
+
+        let items: Vec<ListItem> = rows.iter().enumerate().map(|(i, row)| {
+            let toggle = match (row.has_children, row.expanded) {
+                (false, _) => "  ",
+                (true, false) => "▸ ",
+                (true, true) => "▾ ",
+            };
+            let name = if row.depth == 0 { format!("{}{}", sigil, row.name) } else { row.name.clone() };
+            let name_style = if Some(i) == self.selected_filter_row {
+                Style::default().fg(Color::White).bg(color).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(color)
+            };
+
+            ListItem::new(Line::from(vec![
+                Span::raw("  ".repeat(row.depth)),
+                Span::styled(toggle, Style::default().fg(Color::DarkGray)),
+                Span::styled(name, name_style),
+                Span::styled(format!("  {}", row.count), Style::default().fg(Color::DarkGray)),
+            ]))
+        }).collect();
+
+        // keep the selection on screen in long lists
+        let mut state = ListState::default();
+        state.select(self.selected_filter_row);
+        f.render_stateful_widget(List::new(items).block(block), area, &mut state);
+    }
+
+    fn render_links_panel(&self, f: &mut Frame, area: Rect, note_id: Uuid) {
+        let entries = self.note_links(note_id);
+        let heading_style = Style::default().fg(Color::DarkGray).add_modifier(Modifier::BOLD);
+        let mut lines = Vec::new();
+
+        for (direction, heading) in [(LinkDirection::Outgoing, "links"), (LinkDirection::Incoming, "backlinks")] {
+            if !lines.is_empty() {
+                lines.push(Line::from(""));
+            }
+            lines.push(Line::from(Span::styled(heading, heading_style)));
+
+            let mut any = false;
+            for (i, (entry_direction, linked)) in entries.iter().enumerate() {
+                if *entry_direction != direction {
+                    continue;
+                }
+                any = true;
+
+                let title = linked.title.as_deref().unwrap_or("untitled");
+                let arrow = if direction == LinkDirection::Outgoing { "→" } else { "←" };
+                let line = if i == self.selected_link {
+                    Span::styled(format!("► {} {}", arrow, title), Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+                } else {
+                    Span::styled(format!("  {} {}", arrow, title), Style::default().fg(Color::Cyan))
+                };
+                lines.push(Line::from(line));
+            }
+
+            if !any {
+                lines.push(Line::from(Span::styled("  none", Style::default().fg(Color::DarkGray))));
+            }
+        }
+
+        let panel = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("linked notes"))
+            .wrap(Wrap { trim: false });
+
+        f.render_widget(panel, area);
+    }
+
     fn render_metadata_preview(&mut self, f: &mut Frame, area: Rect) {
         if area.height < 3 {
             return;
         }
 
+        let area = if self.suggestions.is_empty() || area.height < 6 {
+            area
+        } else {
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(3), Constraint::Min(0)])
+                .split(area);
+            self.render_suggestions(f, rows[0]);
+            rows[1]
+        };
+
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([
@@ -977,50 +1437,254 @@ impl App {
             ])
             .split(area);
 
-        let tags_display = if self.extracted_tags.is_empty() {
-            "none".to_string()
+        let metadata_active = matches!(self.active_field, ActiveField::Metadata);
+        let block_style = if metadata_active {
+            match self.editor_mode {
+                EditorMode::Insert => Style::default().fg(Color::Cyan),
+                EditorMode::Command => Style::default().fg(Color::Yellow),
+            }
         } else {
-            self.extracted_tags.iter()
-                .map(|tag| format!("#{}", tag))
-                .collect::<Vec<_>>()
-                .join(" ")
+            Style::default().fg(Color::DarkGray)
         };
 
-        let tags_content = vec![
-            Line::from(""),
+        // explicit entries in magenta, ones picked up from the content in the usual colour
+        let entry_spans = |explicit: &[String], inline: &[String], sigil: char, inline_color: Color| {
+            let mut spans = Vec::new();
+            for entry in explicit.iter().chain(inline.iter().filter(|entry| !explicit.contains(entry))) {
+                let color = if explicit.contains(entry) { Color::Magenta } else { inline_color };
+                if !spans.is_empty() {
+                    spans.push(Span::raw(" "));
+                }
+                spans.push(Span::styled(format!("{}{}", sigil, entry), Style::default().fg(color)));
+            }
+            if spans.is_empty() {
+                spans.push(Span::styled("none", Style::default().fg(inline_color)));
+            }
+            spans
+        };
+
+        let first_line = |hint: &str| if metadata_active {
+            Line::styled(hint.to_string(), Style::default().fg(Color::DarkGray))
+        } else {
+            Line::from("")
+        };
+
+        let mut tags_line = vec![Span::styled("tags: ", Style::default().fg(Color::DarkGray))];
+        tags_line.extend(entry_spans(&self.explicit_tags, &self.extracted_tags, '#', Color::Blue));
+
+        let input_line = if metadata_active {
             Line::from(vec![
-                Span::styled("tags: ", Style::default().fg(Color::DarkGray)),
-                Span::styled(tags_display, Style::default().fg(Color::Blue)),
-            ]),
-        ];
+                Span::styled("> ", Style::default().fg(Color::DarkGray)),
+                Span::raw(self.metadata_input.clone()),
+                Span::styled("_", Style::default().fg(Color::Cyan)),
+            ])
+        } else {
+            Line::from("")
+        };
 
-        let tags_widget = Paragraph::new(tags_content)
-            .block(Block::default().borders(Borders::ALL))
+        let tags_hint = if metadata_active { " (editing)" } else { " (m to add or remove)" };
+        let tags_widget = Paragraph::new(vec![input_line, Line::from(tags_line)])
+            .block(Block::default().borders(Borders::ALL).title(format!("tags{}", tags_hint)).style(block_style))
             .alignment(Alignment::Left);
 
         f.render_widget(tags_widget, chunks[0]);
 
-        let projects_display = if self.extracted_projects.is_empty() {
-            "none".to_string()
-        } else {
-            self.extracted_projects.iter()
-                .map(|project| format!("+{}", project))
-                .collect::<Vec<_>>()
-                .join(" ")
+        let mut projects_line = vec![Span::styled("projects: ", Style::default().fg(Color::DarkGray))];
+        projects_line.extend(entry_spans(&self.explicit_projects, &self.extracted_projects, '+', Color::Green));
+
+        let projects_widget = Paragraph::new(vec![first_line("#tag or +project adds, -#tag removes, enter applies"), Line::from(projects_line)])
+            .block(Block::default().borders(Borders::ALL).title("projects").style(block_style))
+            .alignment(Alignment::Left);
+
+        f.render_widget(projects_widget, chunks[1]);
+    }
+
+    // the rewrite as a diff against the original, one hunk at a time. hunks
+    // that are kept show the rewritten side, dropped ones the original
+    fn render_rewrite_review(&mut self, f: &mut Frame, area: Rect, original: &str, rewritten: &str, progress: &str) {
+        let (old_lines, new_lines) = (hunks::split_lines(original), hunks::split_lines(rewritten));
+        let text = |line: &str| line.trim_end_matches(['\n', '\r']).to_string();
+        let kept = self.accepted_hunks.iter().filter(|accepted| **accepted).count();
+
+        let mut lines: Vec<Line> = Vec::new();
+        let mut selected_line = 0;
+
+        for (index, hunk) in self.rewrite_hunks.iter().enumerate() {
+            let accepted = self.accepted_hunks.get(index).copied().unwrap_or(true);
+            let selected = index == self.selected_hunk;
+            if selected {
+                selected_line = lines.len();
+            }
+
+            if index > 0 {
+                lines.push(Line::from(""));
+            }
+            let header_style = if selected {
+                Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Cyan)
+            };
+            lines.push(Line::styled(
+                format!("{} {} change {} of {}, line {}", if selected { "►" } else { " " }, if accepted { "✓ keep" } else { "✗ drop" }, index + 1, self.rewrite_hunks.len(), hunk.old.start + 1),
+                header_style,
+            ));
+
+            let context = hunk.old.start.saturating_sub(2)..hunk.old.start;
+            for line in &old_lines[context] {
+                lines.push(Line::styled(format!(" {}", text(line)), Style::default().fg(Color::DarkGray)));
+            }
+
+            // the side that won't be used is dimmed and struck through
+            let (removed_style, added_style) = if accepted {
+                (Style::default().fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT), Style::default().fg(Color::Green))
+            } else {
+                (Style::default().fg(Color::Red), Style::default().fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT))
+            };
+
+            let removed = &old_lines[hunk.old.clone()];
+            let added = &new_lines[hunk.new.clone()];
+            let mut removed_lines = Vec::new();
+            let mut added_lines = Vec::new();
+
+            for pair in 0..removed.len().max(added.len()) {
+                match (removed.get(pair), added.get(pair)) {
+                    (Some(old_line), Some(new_line)) => {
+                        // lines changed in place get the changed words highlighted
+                        let (old_words, new_words) = hunks::word_changes(&text(old_line), &text(new_line));
+                        let spans = |sigil: &str, words: hunks::Words, style: Style| {
+                            let mut spans = vec![Span::styled(sigil.to_string(), style)];
+                            spans.extend(words.into_iter().map(|(word, changed)| {
+                                Span::styled(word, if changed { style.add_modifier(Modifier::REVERSED) } else { style })
+                            }));
+                            Line::from(spans)
+                        };
+                        removed_lines.push(spans("-", old_words, removed_style));
+                        added_lines.push(spans("+", new_words, added_style));
+                    }
+                    (Some(old_line), None) => removed_lines.push(Line::styled(format!("-{}", text(old_line)), removed_style)),
+                    (None, Some(new_line)) => added_lines.push(Line::styled(format!("+{}", text(new_line)), added_style)),
+                    (None, None) => {}
+                }
+            }
+            lines.extend(removed_lines);
+            lines.extend(added_lines);
+
+            let context = hunk.old.end..(hunk.old.end + 2).min(old_lines.len());
+            for line in &old_lines[context] {
+                lines.push(Line::styled(format!(" {}", text(line)), Style::default().fg(Color::DarkGray)));
+            }
+        }
+
+        if lines.is_empty() {
+            lines.push(Line::styled("the rewrite is the same as the original", Style::default().fg(Color::DarkGray)));
+        }
+
+        // keep the selected change in view
+        let height = area.height.saturating_sub(2) as usize;
+        let scroll = if lines.len() > height { selected_line.min(lines.len() - height) } else { 0 };
+
+        let title = format!("review changes • keeping {} of {} • {}", kept, self.rewrite_hunks.len(), progress);
+        let review_widget = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .scroll((scroll as u16, 0));
+
+        f.render_widget(review_widget, area);
+    }
+
+    // the prompt library over whatever screen it was opened from
+    fn render_prompt_picker(&mut self, f: &mut Frame, area: Rect) {
+        let width = area.width.saturating_sub(4).min(70);
+        let height = (self.prompts.len() as u16 + 2).min(area.height.saturating_sub(2));
+        let popup = Rect {
+            x: area.x + (area.width.saturating_sub(width)) / 2,
+            y: area.y + (area.height.saturating_sub(height)) / 2,
+            width,
+            height,
         };
 
-        let projects_content = vec![
-            Line::from(""),
-            Line::from(vec![
-                Span::styled("projects: ", Style::default().fg(Color::DarkGray)),
-                Span::styled(projects_display, Style::default().fg(Color::Green)),
-            ]),
-        ];
+        let items: Vec<ListItem> = self.prompts.iter()
+            .map(|prompt| ListItem::new(Line::from(vec![
+                Span::styled(prompt.name.clone(), Style::default().fg(Color::Cyan)),
+                Span::raw("  "),
+                Span::styled(prompt.summary(), Style::default().fg(Color::DarkGray)),
+            ])))
+            .collect();
 
-        let projects_widget = Paragraph::new(projects_content)
-            .block(Block::default().borders(Borders::ALL))
+        let mut state = ListState::default();
+        state.select(Some(self.selected_prompt));
+
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("run a prompt (↑↓ select • enter run • p preview • esc cancel)").style(Style::default().fg(Color::Yellow)))
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED))
+            .highlight_symbol("► ");
+
+        f.render_widget(Clear, popup);
+        f.render_stateful_widget(list, popup, &mut state);
+    }
+
+    // exactly what the selected prompt would send, over the picker
+    fn render_payload_preview(&mut self, f: &mut Frame, area: Rect) {
+        let Some(preview) = &self.payload_preview else {
+            return;
+        };
+        let popup = Rect {
+            x: area.x + 2,
+            y: area.y + 1,
+            width: area.width.saturating_sub(4),
+            height: area.height.saturating_sub(2),
+        };
+
+        let preview_widget = Paragraph::new(preview.as_str())
+            .block(Block::default().borders(Borders::ALL).title("payload preview (↑↓ scroll • enter send • esc back)").style(Style::default().fg(Color::Yellow)))
+            .style(Style::default().fg(Color::White))
+            .wrap(Wrap { trim: false })
+            .scroll((self.preview_scroll, 0));
+
+        f.render_widget(Clear, popup);
+        f.render_widget(preview_widget, popup);
+    }
+
+    // the heading sections of the viewed note, in place of its links
+    fn render_section_picker(&mut self, f: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self.note_sections.iter()
+            .map(|section| ListItem::new(format!("{}{}", "  ".repeat(section.level.saturating_sub(1)), section.title)))
+            .collect();
+
+        let mut state = ListState::default();
+        state.select(self.section_picker);
+
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("rewrite a section"))
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            .highlight_symbol("► ");
+
+        f.render_stateful_widget(list, area, &mut state);
+    }
+
+    // the pending ai suggestions, the selected one highlighted
+    fn render_suggestions(&mut self, f: &mut Frame, area: Rect) {
+        let mut spans = Vec::new();
+        for (index, suggestion) in self.suggestions.iter().enumerate() {
+            let color = match suggestion {
+                Suggestion::Title(_) => Color::White,
+                Suggestion::Tag(_) => Color::Blue,
+                Suggestion::Project(_) => Color::Green,
+            };
+            let style = if index == self.selected_suggestion {
+                Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(color)
+            };
+            if !spans.is_empty() {
+                spans.push(Span::raw("  "));
+            }
+            spans.push(Span::styled(suggestion.label(), style));
+        }
+
+        let suggestions_widget = Paragraph::new(Line::from(spans))
+            .block(Block::default().borders(Borders::ALL).title("suggestions (←→ select • y accept • n dismiss • Y accept all)").style(Style::default().fg(Color::Yellow)))
             .alignment(Alignment::Left);
 
-        f.render_widget(projects_widget, chunks[1]);
+        f.render_widget(suggestions_widget, area);
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,7 @@
 use crossterm::event::{KeyCode, KeyModifiers};
 
 use super::app::App;
-use super::state::{AppMode, EditorMode, ActiveField, AiState};
+use super::state::{AppMode, EditorMode, ActiveField, AiState, RetagPrompt, RewriteTarget};
 
 pub trait InputHandler {
     fn handle_input(&mut self, key: KeyCode, modifiers: KeyModifiers);
@@ -13,40 +13,49 @@ pub trait InputHandler {
     fn handle_settings_input(&mut self, key: KeyCode, modifiers: KeyModifiers);
     fn handle_ai_rewrite_input(&mut self, key: KeyCode);
     fn handle_search_input(&mut self, key: KeyCode);
-    fn handle_tag_filter_input(&mut self, key: KeyCode);
-    fn handle_project_filter_input(&mut self, key: KeyCode);
+    fn handle_tag_filter_input(&mut self, key: KeyCode, modifiers: KeyModifiers);
+    fn handle_project_filter_input(&mut self, key: KeyCode, modifiers: KeyModifiers);
+    fn handle_retag_prompt_input(&mut self, key: KeyCode);
     fn handle_delete_confirm_input(&mut self, key: KeyCode);
+    fn handle_trash_input(&mut self, key: KeyCode);
+    fn handle_purge_confirm_input(&mut self, key: KeyCode);
+    fn handle_history_input(&mut self, key: KeyCode);
+    fn handle_ask_input(&mut self, key: KeyCode);
+    fn handle_section_picker_input(&mut self, key: KeyCode);
+    fn handle_prompt_picker_input(&mut self, key: KeyCode);
+    fn handle_payload_preview_input(&mut self, key: KeyCode);
 }
 
 impl InputHandler for App {
     fn handle_input(&mut self, key: KeyCode, modifiers: KeyModifiers) {
         match self.mode.clone() {
+            _ if self.payload_preview.is_some() => self.handle_payload_preview_input(key),
+            _ if self.prompt_target.is_some() => self.handle_prompt_picker_input(key),
             AppMode::Home => self.handle_home_input(key),
             AppMode::AddNote => self.handle_add_note_input(key, modifiers),
             AppMode::EditNote(_) => self.handle_edit_note_input(key, modifiers),
+            AppMode::ViewNote(_) if self.section_picker.is_some() => self.handle_section_picker_input(key),
             AppMode::ViewNote(_) => self.handle_view_note_input(key),
             AppMode::Help => self.handle_help_input(key),
             AppMode::Settings => self.handle_settings_input(key, modifiers),
             AppMode::AiRewrite { .. } => self.handle_ai_rewrite_input(key),
             AppMode::Search => self.handle_search_input(key),
-            AppMode::TagFilter => self.handle_tag_filter_input(key),
-            AppMode::ProjectFilter => self.handle_project_filter_input(key),
+            AppMode::TagFilter if self.retag_prompt.is_some() => self.handle_retag_prompt_input(key),
+            AppMode::ProjectFilter if self.retag_prompt.is_some() => self.handle_retag_prompt_input(key),
+            AppMode::TagFilter => self.handle_tag_filter_input(key, modifiers),
+            AppMode::ProjectFilter => self.handle_project_filter_input(key, modifiers),
             AppMode::DeleteConfirm { .. } => self.handle_delete_confirm_input(key),
+            AppMode::Trash if self.purge_confirm.is_some() => self.handle_purge_confirm_input(key),
+            AppMode::Trash => self.handle_trash_input(key),
+            AppMode::History { .. } => self.handle_history_input(key),
+            AppMode::Ask => self.handle_ask_input(key),
         }
     }
 
     fn handle_home_input(&mut self, key: KeyCode) {
         match key {
             KeyCode::Char('q') => self.should_quit = true,
-            KeyCode::Char('a') => {
-                self.mode = AppMode::AddNote;
-                self.editor_mode = EditorMode::Insert;
-                self.active_field = ActiveField::Content;
-                self.content_editor = tui_textarea::TextArea::default();
-                self.title_input.clear();
-                self.extracted_tags.clear();
-                self.extracted_projects.clear();
-            }
+            KeyCode::Char('a') => self.start_new_note(),
             KeyCode::Char('h') => self.mode = AppMode::Help,
             KeyCode::Char('s') => {
                 self.mode = AppMode::Settings;
@@ -63,15 +72,18 @@ impl InputHandler for App {
                 self.active_field = ActiveField::Search;
                 self.search_input.clear();
             }
+            KeyCode::Char('?') => self.open_ask(),
             KeyCode::Char('t') => {
                 self.mode = AppMode::TagFilter;
                 self.active_field = ActiveField::TagFilter;
                 self.tag_filter_input.clear();
+                self.selected_filter_row = None;
             }
             KeyCode::Char('p') => {
                 self.mode = AppMode::ProjectFilter;
                 self.active_field = ActiveField::ProjectFilter;
                 self.project_filter_input.clear();
+                self.selected_filter_row = None;
             }
             KeyCode::Char('d') => {
                 self.confirm_delete_current_note();
@@ -79,6 +91,10 @@ impl InputHandler for App {
             KeyCode::Char('c') => {
                 self.clear_filters();
             }
+            KeyCode::Char('x') => {
+                self.selected_trash = 0;
+                self.open_trash();
+            }
             KeyCode::Char('r') => {
                 self.load_existing_notes();
                 self.status_message = Some("notes refreshed".to_string());
@@ -89,11 +105,9 @@ impl InputHandler for App {
             KeyCode::Down | KeyCode::Char('j') => {
                 self.next_note();
             }
-            KeyCode::Enter => {
-                if !self.notes.is_empty() && self.selected_note < self.notes.len() {
-                    let note_id = self.notes[self.selected_note].id;
-                    self.mode = AppMode::ViewNote(note_id);
-                }
+            KeyCode::Enter if !self.notes.is_empty() && self.selected_note < self.notes.len() => {
+                let note_id = self.notes[self.selected_note].id;
+                self.view_note(note_id);
             }
             _ => {}
         }
@@ -123,6 +137,20 @@ impl InputHandler for App {
                                     _ => {}
                                 }
                             }
+                            ActiveField::Metadata => {
+                                match key {
+                                    KeyCode::Char(c) => {
+                                        self.metadata_input.push(c);
+                                    }
+                                    KeyCode::Backspace => {
+                                        self.metadata_input.pop();
+                                    }
+                                    KeyCode::Enter => {
+                                        self.apply_metadata_input();
+                                    }
+                                    _ => {}
+                                }
+                            }
                             ActiveField::ApiKey => {
                                 // apikey field should not be active in addnote mode
                             }
@@ -146,14 +174,14 @@ impl InputHandler for App {
                         self.editor_mode = EditorMode::Command;
                         self.content_editor = tui_textarea::TextArea::default();
                         self.title_input.clear();
-                        self.extracted_tags.clear();
-                        self.extracted_projects.clear();
+                        self.reset_metadata_fields();
+                        self.clear_suggestions();
                     }
                     KeyCode::Char('s') => {
                         self.save_note();
                     }
                     KeyCode::Char('r') => {
-                        self.start_ai_rewrite_draft();
+                        self.open_prompt_picker(RewriteTarget::Editor);
                     }
                     KeyCode::Char('i') => {
                         self.editor_mode = EditorMode::Insert;
@@ -166,6 +194,18 @@ impl InputHandler for App {
                         self.active_field = ActiveField::Content;
                         self.editor_mode = EditorMode::Insert;
                     }
+                    KeyCode::Char('m') => {
+                        self.active_field = ActiveField::Metadata;
+                        self.editor_mode = EditorMode::Insert;
+                    }
+                    KeyCode::Char('e') => {
+                        self.start_enrich();
+                    }
+                    KeyCode::Right if !self.suggestions.is_empty() => self.next_suggestion(),
+                    KeyCode::Left if !self.suggestions.is_empty() => self.previous_suggestion(),
+                    KeyCode::Char('y') if !self.suggestions.is_empty() => self.accept_suggestion(),
+                    KeyCode::Char('n') if !self.suggestions.is_empty() => self.dismiss_suggestion(),
+                    KeyCode::Char('Y') if !self.suggestions.is_empty() => self.accept_all_suggestions(),
                     _ => {}
                 }
             }
@@ -175,7 +215,7 @@ impl InputHandler for App {
     fn handle_view_note_input(&mut self, key: KeyCode) {
         match key {
             KeyCode::Esc | KeyCode::Char('q') => {
-                self.mode = AppMode::Home;
+                self.mode = if std::mem::take(&mut self.return_to_ask) { AppMode::Ask } else { AppMode::Home };
             }
             KeyCode::Char('e') => {
                 if let AppMode::ViewNote(note_id) = self.mode {
@@ -184,9 +224,59 @@ impl InputHandler for App {
             }
             KeyCode::Char('r') => {
                 if let AppMode::ViewNote(note_id) = self.mode {
-                    self.start_ai_rewrite(note_id);
+                    self.open_prompt_picker(RewriteTarget::Note { note_id, region: None });
+                }
+            }
+            KeyCode::Char('R') => {
+                if let AppMode::ViewNote(note_id) = self.mode {
+                    self.open_section_picker(note_id);
+                }
+            }
+            KeyCode::Char('h') => {
+                if let AppMode::ViewNote(note_id) = self.mode {
+                    self.open_history(note_id);
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.next_link();
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.previous_link();
+            }
+            KeyCode::Enter => {
+                self.follow_selected_link();
+            }
+            KeyCode::Backspace | KeyCode::Char('b') => {
+                self.go_back_note();
+            }
+            _ => {}
+        }
+    }
+
+    // typing a question until it's sent, then picking a source to open
+    fn handle_ask_input(&mut self, key: KeyCode) {
+        let answered = self.ask_answer.is_some();
+        let waiting = matches!(self.ai_state, AiState::Processing);
+
+        match key {
+            KeyCode::Esc => self.mode = AppMode::Home,
+            _ if waiting => {}
+            KeyCode::Char('q') if answered => self.mode = AppMode::Home,
+            KeyCode::Char('n') | KeyCode::Char('/') if answered => self.new_question(),
+            KeyCode::Down | KeyCode::Char('j') if answered => self.next_source(),
+            KeyCode::Up | KeyCode::Char('k') if answered => self.previous_source(),
+            KeyCode::Enter if answered => self.open_selected_source(),
+            _ if answered => {}
+            KeyCode::Enter => self.start_ask(),
+            KeyCode::Char(c) => {
+                self.ask_input.push(c);
+                if matches!(self.ai_state, AiState::Error(_)) {
+                    self.ai_state = AiState::Idle;
                 }
             }
+            KeyCode::Backspace => {
+                self.ask_input.pop();
+            }
             _ => {}
         }
     }
@@ -209,19 +299,15 @@ impl InputHandler for App {
             }
             KeyCode::Enter => {
                 match self.active_field {
-                    ActiveField::ApiKey => {
-                        if !self.api_key_input.trim().is_empty() {
-                            if let Err(_) = self.set_api_key(self.api_key_input.clone()) {
-                                self.status_message = Some("failed to save api key".to_string());
-                            } else {
-                                if let Err(e) = self.save_prompt_settings() {
-                                    self.status_message = Some(e);
-                                } else {
-                                    self.mode = AppMode::Home;
-                                    self.api_key_input.clear();
-                                    self.custom_prompt_input.clear();
-                                }
-                            }
+                    ActiveField::ApiKey if !self.api_key_input.trim().is_empty() => {
+                        if self.set_api_key(self.api_key_input.clone()).is_err() {
+                            self.status_message = Some("failed to save api key".to_string());
+                        } else if let Err(e) = self.save_prompt_settings() {
+                            self.status_message = Some(e);
+                        } else {
+                            self.mode = AppMode::Home;
+                            self.api_key_input.clear();
+                            self.custom_prompt_input.clear();
                         }
                     }
                     ActiveField::PromptStyle | ActiveField::CustomPrompt => {
@@ -291,6 +377,14 @@ impl InputHandler for App {
                     self.accept_ai_rewrite();
                 }
             }
+            _ if !matches!(self.ai_state, AiState::Success) => {}
+            KeyCode::Down | KeyCode::Char('j') => self.next_hunk(),
+            KeyCode::Up | KeyCode::Char('k') => self.previous_hunk(),
+            KeyCode::Char(' ') => self.toggle_hunk(),
+            KeyCode::Char('y') => self.set_hunk(true),
+            KeyCode::Char('n') => self.set_hunk(false),
+            KeyCode::Char('Y') => self.set_all_hunks(true),
+            KeyCode::Char('N') => self.set_all_hunks(false),
             _ => {}
         }
     }
@@ -304,6 +398,9 @@ impl InputHandler for App {
             KeyCode::Enter => {
                 if self.search_input.trim().is_empty() {
                     self.current_search = None;
+                } else if let Err(e) = crate::query::parse(&self.search_input) {
+                    self.status_message = Some(format!("invalid query: {}", e));
+                    return;
                 } else {
                     self.current_search = Some(self.search_input.clone());
                 }
@@ -321,15 +418,22 @@ impl InputHandler for App {
         }
     }
 
-    fn handle_tag_filter_input(&mut self, key: KeyCode) {
+    fn handle_tag_filter_input(&mut self, key: KeyCode, modifiers: KeyModifiers) {
+        if modifiers.contains(KeyModifiers::CONTROL) {
+            self.start_retag_prompt(key);
+            return;
+        }
+
         match key {
             KeyCode::Esc => {
                 self.mode = AppMode::Home;
                 self.tag_filter_input.clear();
             }
             KeyCode::Enter => {
-                if self.tag_filter_input.trim().is_empty() {
+                if self.tag_filter_input.trim().is_empty() && self.selected_filter_row.is_none() {
                     self.current_tag_filter = None;
+                } else if let Some(path) = self.selected_filter_path() {
+                    self.current_tag_filter = Some(path);
                 } else {
                     self.current_tag_filter = Some(self.tag_filter_input.clone());
                 }
@@ -337,25 +441,38 @@ impl InputHandler for App {
                 self.mode = AppMode::Home;
                 self.tag_filter_input.clear();
             }
+            KeyCode::Down => self.next_filter_row(),
+            KeyCode::Up => self.previous_filter_row(),
+            KeyCode::Right => self.expand_filter_row(),
+            KeyCode::Left => self.collapse_filter_row(),
             KeyCode::Char(c) => {
                 self.tag_filter_input.push(c);
+                self.select_first_filter_match();
             }
             KeyCode::Backspace => {
                 self.tag_filter_input.pop();
+                self.select_first_filter_match();
             }
             _ => {}
         }
     }
 
-    fn handle_project_filter_input(&mut self, key: KeyCode) {
+    fn handle_project_filter_input(&mut self, key: KeyCode, modifiers: KeyModifiers) {
+        if modifiers.contains(KeyModifiers::CONTROL) {
+            self.start_retag_prompt(key);
+            return;
+        }
+
         match key {
             KeyCode::Esc => {
                 self.mode = AppMode::Home;
                 self.project_filter_input.clear();
             }
             KeyCode::Enter => {
-                if self.project_filter_input.trim().is_empty() {
+                if self.project_filter_input.trim().is_empty() && self.selected_filter_row.is_none() {
                     self.current_project_filter = None;
+                } else if let Some(path) = self.selected_filter_path() {
+                    self.current_project_filter = Some(path);
                 } else {
                     self.current_project_filter = Some(self.project_filter_input.clone());
                 }
@@ -363,11 +480,35 @@ impl InputHandler for App {
                 self.mode = AppMode::Home;
                 self.project_filter_input.clear();
             }
+            KeyCode::Down => self.next_filter_row(),
+            KeyCode::Up => self.previous_filter_row(),
+            KeyCode::Right => self.expand_filter_row(),
+            KeyCode::Left => self.collapse_filter_row(),
             KeyCode::Char(c) => {
                 self.project_filter_input.push(c);
+                self.select_first_filter_match();
             }
             KeyCode::Backspace => {
                 self.project_filter_input.pop();
+                self.select_first_filter_match();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_retag_prompt_input(&mut self, key: KeyCode) {
+        match (self.retag_prompt.as_mut(), key) {
+            (_, KeyCode::Esc) | (Some(RetagPrompt::Delete { .. }), KeyCode::Char('n')) => {
+                self.retag_prompt = None;
+            }
+            (_, KeyCode::Enter) | (Some(RetagPrompt::Delete { .. }), KeyCode::Char('y')) => {
+                self.run_retag_prompt();
+            }
+            (Some(RetagPrompt::Rename { input, .. }), KeyCode::Char(c)) => {
+                input.push(c);
+            }
+            (Some(RetagPrompt::Rename { input, .. }), KeyCode::Backspace) => {
+                input.pop();
             }
             _ => {}
         }
@@ -384,10 +525,10 @@ impl InputHandler for App {
                 }
                 KeyCode::Enter | KeyCode::Char('y') => {
                     match self.deletion_preference {
-                        crate::tui::app::DeletionType::Soft => {
+                        crate::repository::DeletionType::Soft => {
                             self.soft_delete_note(note_id);
                         }
-                        crate::tui::app::DeletionType::Hard => {
+                        crate::repository::DeletionType::Hard => {
                             self.hard_delete_note(note_id);
                         }
                     }
@@ -397,6 +538,55 @@ impl InputHandler for App {
         }
     }
 
+    fn handle_trash_input(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.mode = AppMode::Home;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.previous_trashed_note();
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.next_trashed_note();
+            }
+            KeyCode::Char('r') | KeyCode::Enter => {
+                self.restore_selected_trash();
+            }
+            KeyCode::Char('D') => {
+                self.confirm_purge_selected_trash();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_purge_confirm_input(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Char('y') => self.purge_confirmed_trash(),
+            KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('q') => self.purge_confirm = None,
+            _ => {}
+        }
+    }
+
+    fn handle_history_input(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                if let AppMode::History { note_id } = self.mode {
+                    self.mode = AppMode::ViewNote(note_id);
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.previous_revision();
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.next_revision();
+            }
+            KeyCode::Char('r') | KeyCode::Enter => {
+                self.restore_selected_revision();
+            }
+            _ => {}
+        }
+    }
+
     fn handle_edit_note_input(&mut self, key: KeyCode, modifiers: KeyModifiers) {
         match self.editor_mode {
             EditorMode::Insert => {
@@ -421,6 +611,20 @@ impl InputHandler for App {
                                     _ => {}
                                 }
                             }
+                            ActiveField::Metadata => {
+                                match key {
+                                    KeyCode::Char(c) => {
+                                        self.metadata_input.push(c);
+                                    }
+                                    KeyCode::Backspace => {
+                                        self.metadata_input.pop();
+                                    }
+                                    KeyCode::Enter => {
+                                        self.apply_metadata_input();
+                                    }
+                                    _ => {}
+                                }
+                            }
                             _ => {}
                         }
                     }
@@ -433,15 +637,17 @@ impl InputHandler for App {
                         self.editor_mode = EditorMode::Command;
                         self.content_editor = tui_textarea::TextArea::default();
                         self.title_input.clear();
-                        self.extracted_tags.clear();
-                        self.extracted_projects.clear();
+                        self.reset_metadata_fields();
+                        self.clear_suggestions();
                     }
                     KeyCode::Char('s') => {
                         self.save_edited_note();
                     }
                     KeyCode::Char('r') => {
-                        if let AppMode::EditNote(note_id) = self.mode {
-                            self.start_ai_rewrite(note_id);
+                        if self.editor_selection().is_some() {
+                            self.open_prompt_picker(RewriteTarget::Editor);
+                        } else if let AppMode::EditNote(note_id) = self.mode {
+                            self.open_prompt_picker(RewriteTarget::Note { note_id, region: None });
                         }
                     }
                     KeyCode::Char('i') => {
@@ -455,9 +661,58 @@ impl InputHandler for App {
                         self.active_field = ActiveField::Content;
                         self.editor_mode = EditorMode::Insert;
                     }
+                    KeyCode::Char('m') => {
+                        self.active_field = ActiveField::Metadata;
+                        self.editor_mode = EditorMode::Insert;
+                    }
+                    KeyCode::Char('e') => {
+                        self.start_enrich();
+                    }
+                    KeyCode::Right if !self.suggestions.is_empty() => self.next_suggestion(),
+                    KeyCode::Left if !self.suggestions.is_empty() => self.previous_suggestion(),
+                    KeyCode::Char('y') if !self.suggestions.is_empty() => self.accept_suggestion(),
+                    KeyCode::Char('n') if !self.suggestions.is_empty() => self.dismiss_suggestion(),
+                    KeyCode::Char('Y') if !self.suggestions.is_empty() => self.accept_all_suggestions(),
                     _ => {}
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    fn handle_section_picker_input(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Esc | KeyCode::Char('q') => self.section_picker = None,
+            KeyCode::Down | KeyCode::Char('j') => self.next_section(),
+            KeyCode::Up | KeyCode::Char('k') => self.previous_section(),
+            KeyCode::Enter => {
+                if let AppMode::ViewNote(note_id) = self.mode {
+                    self.rewrite_selected_section(note_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_prompt_picker_input(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Esc | KeyCode::Char('q') => self.prompt_target = None,
+            KeyCode::Down | KeyCode::Char('j') => self.next_prompt(),
+            KeyCode::Up | KeyCode::Char('k') => self.previous_prompt(),
+            KeyCode::Enter => self.confirm_selected_prompt(),
+            KeyCode::Char('p') => self.preview_selected_prompt(),
+            _ => {}
+        }
+    }
+
+    fn handle_payload_preview_input(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') => self.close_payload_preview(),
+            KeyCode::Enter | KeyCode::Char('y') => self.send_previewed_prompt(),
+            KeyCode::Down | KeyCode::Char('j') => self.preview_scroll = self.preview_scroll.saturating_add(1),
+            KeyCode::Up | KeyCode::Char('k') => self.preview_scroll = self.preview_scroll.saturating_sub(1),
+            KeyCode::PageDown => self.preview_scroll = self.preview_scroll.saturating_add(10),
+            KeyCode::PageUp => self.preview_scroll = self.preview_scroll.saturating_sub(10),
+            _ => {}
+        }
+    }
+}
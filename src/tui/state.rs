@@ -1,3 +1,4 @@
+use std::ops::Range;
 use uuid::Uuid;
 
 #[derive(Debug, Clone)]
@@ -13,6 +14,9 @@ pub enum AppMode {
     TagFilter,
     ProjectFilter,
     DeleteConfirm { note_id: Uuid },
+    Trash,
+    History { note_id: Uuid },
+    Ask,
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +29,7 @@ pub enum EditorMode {
 pub enum ActiveField {
     Title,
     Content,
+    Metadata,
     ApiKey,
     PromptStyle,
     CustomPrompt,
@@ -40,4 +45,25 @@ pub enum AiState {
     Processing,
     Success,
     Error(String),
-}
\ No newline at end of file
+}
+
+// a bulk change to the selected tag or project, waiting for input or confirmation
+#[derive(Debug, Clone)]
+pub enum RetagPrompt {
+    Rename { path: String, input: String },
+    Delete { path: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkDirection {
+    Outgoing,
+    Incoming,
+}
+
+// what the prompt picked from the prompt picker will be run on
+#[derive(Debug, Clone)]
+pub enum RewriteTarget {
+    // the text in the editor, or the part of it that is selected
+    Editor,
+    Note { note_id: Uuid, region: Option<Range<usize>> },
+}
@@ -0,0 +1,99 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use console::Style;
+
+use crate::config::{Config, ConfigError};
+
+pub const DEFAULT_VAULT: &str = "default";
+pub const STASH_DIR_ENV: &str = "STASH_DIR";
+// where each part of a stash lives, under its root
+pub const NOTES_DIR: &str = "notes";
+pub const INDEX_DIR: &str = "index";
+pub const HISTORY_DIR: &str = "history";
+
+static STASH_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+// resolves the stash root once per process. precedence is the --vault flag,
+// then $STASH_DIR, then the active vault from the config, then ~/.stash
+pub fn init(vault: Option<&str>) -> Result<PathBuf, ConfigError> {
+    if let Some(dir) = STASH_DIR.get() {
+        return Ok(dir.clone());
+    }
+
+    let config = Config::load()?;
+    let env_dir = env::var_os(STASH_DIR_ENV).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let dir = config.resolve_stash_dir(vault, env_dir)?;
+
+    Ok(STASH_DIR.get_or_init(|| dir).clone())
+}
+
+pub fn stash_dir() -> Result<PathBuf, ConfigError> {
+    init(None)
+}
+
+pub fn notes_dir() -> Result<PathBuf, ConfigError> {
+    Ok(stash_dir()?.join(NOTES_DIR))
+}
+
+pub fn index_dir() -> Result<PathBuf, ConfigError> {
+    Ok(stash_dir()?.join(INDEX_DIR))
+}
+
+pub fn history_dir() -> Result<PathBuf, ConfigError> {
+    Ok(stash_dir()?.join(HISTORY_DIR))
+}
+
+pub fn list_vaults() -> Result<(), ConfigError> {
+    let config = Config::load()?;
+    let current = stash_dir()?;
+    let name_style = Style::new().bold().cyan();
+    let path_style = Style::new().dim();
+    let active_style = Style::new().bold().green();
+
+    let mut vaults = vec![(DEFAULT_VAULT.to_string(), Config::default_stash_dir()?)];
+    vaults.extend(config.vaults.iter().map(|(name, path)| (name.clone(), path.clone())));
+
+    println!("\n{} vaults:", name_style.apply_to("🗄️"));
+    println!("{}", "─".repeat(50));
+
+    for (name, path) in vaults {
+        let marker = if path == current { active_style.apply_to("*").to_string() } else { " ".to_string() };
+        println!("{} {} {}", marker, name_style.apply_to(&name), path_style.apply_to(path.display()));
+    }
+
+    if env::var_os(STASH_DIR_ENV).is_some_and(|v| !v.is_empty()) {
+        println!("\n${} is set and overrides the active vault", STASH_DIR_ENV);
+    }
+
+    Ok(())
+}
+
+pub fn add_vault(name: &str, path: &Path) -> Result<(), ConfigError> {
+    let mut config = Config::load()?;
+    let path = absolute_path(path)?;
+    config.add_vault(name.to_string(), path.clone())?;
+    std::fs::create_dir_all(path.join(NOTES_DIR))?;
+    println!("vault '{}' added at {}", name, path.display());
+    Ok(())
+}
+
+pub fn use_vault(name: &str) -> Result<(), ConfigError> {
+    let mut config = Config::load()?;
+    config.use_vault(name)?;
+    println!("now using vault '{}'", name);
+    Ok(())
+}
+
+fn absolute_path(path: &Path) -> Result<PathBuf, ConfigError> {
+    if let Ok(rest) = path.strip_prefix("~") {
+        let home = dirs::home_dir().ok_or(ConfigError::HomeNotFound)?;
+        return Ok(home.join(rest));
+    }
+
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(env::current_dir()?.join(path))
+    }
+}